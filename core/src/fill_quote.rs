@@ -1,7 +1,10 @@
-use crate::OrderSide;
+use crate::{OrderMarker, OrderSide, OrderTombstone};
 use borsh::{BorshDeserialize, BorshSerialize};
 use solana_program::pubkey::Pubkey;
 
+#[cfg(feature = "permit-signing")]
+use ed25519_dalek::{Keypair as Ed25519Keypair, Signer as Ed25519Signer};
+
 /// Data structure that a trusted keeper signs to authorize a fill
 /// This allows anyone to submit the fill transaction while maintaining security
 #[repr(C)]
@@ -100,6 +103,57 @@ impl SignedFillQuote {
     pub fn message_hash(&self) -> Result<[u8; 32], FillQuoteError> {
         self.quote.message_hash()
     }
+
+    /// Check the signature over the serialized quote against `keeper_pubkey`
+    #[cfg(feature = "permit-signing")]
+    pub fn verify(&self) -> Result<(), FillQuoteError> {
+        use ed25519_dalek::{PublicKey, Signature, Verifier};
+
+        let public_key = PublicKey::from_bytes(self.keeper_pubkey.as_ref())
+            .map_err(|_| FillQuoteError::SignatureVerificationFailed)?;
+        let signature = Signature::from_bytes(&self.signature)
+            .map_err(|_| FillQuoteError::SignatureVerificationFailed)?;
+        public_key
+            .verify(&self.quote_bytes()?, &signature)
+            .map_err(|_| FillQuoteError::SignatureVerificationFailed)
+    }
+}
+
+/// Serialize and sign a fill quote with the keeper's Ed25519 keypair.
+/// The serialized quote bytes are signed, matching the ed25519 verify instruction
+/// that accompanies `FillOrderQuote`.
+#[cfg(feature = "permit-signing")]
+pub fn sign_fill_quote_ed25519(
+    quote: &OffchainFillQuote,
+    keeper: &Ed25519Keypair,
+) -> Result<SignedFillQuote, FillQuoteError> {
+    let bytes = quote.to_bytes()?;
+    let signature = keeper.sign(&bytes).to_bytes();
+    Ok(SignedFillQuote::new(
+        quote.clone(),
+        signature,
+        Pubkey::new_from_array(keeper.public.to_bytes()),
+    ))
+}
+
+/// Whether `available_liquidity` on the opposite side of the book can absorb all of `qty`.
+/// Used to pre-check fill-or-kill orders before submitting them.
+pub fn can_fully_fill(qty: u64, available_liquidity: u64) -> bool {
+    available_liquidity >= qty
+}
+
+/// Whether a taker would be matching against their own resting order
+pub fn is_self_trade(taker_user: &Pubkey, maker_user: &Pubkey) -> bool {
+    taker_user == maker_user
+}
+
+/// Self-trade prevention for the matching engine: `Err(SelfTrade)` is the tombstone to apply
+/// when the taker and maker orders belong to the same user.
+pub fn check_self_trade(taker: &OrderMarker, maker: &OrderMarker) -> Result<(), OrderTombstone> {
+    if is_self_trade(&taker.user, &maker.user) {
+        return Err(OrderTombstone::SelfTrade());
+    }
+    Ok(())
 }
 
 /// Errors that can occur when working with fill quotes
@@ -153,6 +207,36 @@ mod tests {
     use super::*;
     use solana_program::pubkey::Pubkey;
 
+    #[test]
+    fn test_can_fully_fill_boundary() {
+        assert!(can_fully_fill(1_000_000, 1_000_000));
+        assert!(can_fully_fill(1_000_000, 1_000_001));
+        assert!(!can_fully_fill(1_000_000, 999_999));
+        assert!(can_fully_fill(0, 0));
+    }
+
+    #[test]
+    fn test_check_self_trade_same_user() {
+        let user = Pubkey::new_unique();
+        assert!(is_self_trade(&user, &user));
+        assert_eq!(
+            check_self_trade(&OrderMarker::new(user, 1), &OrderMarker::new(user, 2)),
+            Err(OrderTombstone::SelfTrade())
+        );
+    }
+
+    #[test]
+    fn test_check_self_trade_different_users() {
+        let taker = Pubkey::new_unique();
+        let maker = Pubkey::new_unique();
+        assert!(!is_self_trade(&taker, &maker));
+        // Same order id across users is not a self-trade
+        assert_eq!(
+            check_self_trade(&OrderMarker::new(taker, 1), &OrderMarker::new(maker, 1)),
+            Ok(())
+        );
+    }
+
     #[test]
     fn test_fill_quote_creation() {
         let user = Pubkey::new_unique();
@@ -230,4 +314,40 @@ mod tests {
         assert_eq!(signed_quote.signature, signature);
         assert_eq!(signed_quote.keeper_pubkey, keeper);
     }
+
+    #[cfg(feature = "permit-signing")]
+    #[test]
+    fn test_sign_fill_quote_ed25519_roundtrip() {
+        use ed25519_dalek::{PublicKey, SecretKey};
+
+        let secret = SecretKey::from_bytes(&[11u8; 32]).unwrap();
+        let public = PublicKey::from(&secret);
+        let keeper = Ed25519Keypair { secret, public };
+
+        let user = Pubkey::new_unique();
+        let quote =
+            OffchainFillQuote::new(123, OrderSide::Ask, 1000000, 50000, 1000, 42, user, 7).unwrap();
+
+        let signed = sign_fill_quote_ed25519(&quote, &keeper).unwrap();
+        assert_eq!(signed.quote, quote);
+        assert_eq!(signed.keeper_pubkey.to_bytes(), public.to_bytes());
+        assert_eq!(signed.quote_bytes().unwrap(), quote.to_bytes().unwrap());
+        signed.verify().unwrap();
+
+        // Tampering with the quote invalidates the signature
+        let mut tampered = signed.clone();
+        tampered.quote.fill_qty += 1;
+        assert_eq!(
+            tampered.verify().unwrap_err(),
+            FillQuoteError::SignatureVerificationFailed
+        );
+
+        // As does claiming a different keeper
+        let mut wrong_keeper = signed;
+        wrong_keeper.keeper_pubkey = Pubkey::new_unique();
+        assert_eq!(
+            wrong_keeper.verify().unwrap_err(),
+            FillQuoteError::SignatureVerificationFailed
+        );
+    }
 }
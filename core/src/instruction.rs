@@ -12,6 +12,7 @@ pub enum TestnetIx {
         min_deposit_size: u64,
     },
     /// Initialize a new market with parameters.
+    /// The resulting market state must pass `MarketStateV1::validate_parameters`.
     InitializeMarket {
         market_id: u64,
         oracle: Pubkey,
@@ -92,7 +93,9 @@ pub enum TestnetIx {
         /// Time in force
         tif: TimeInForce,
     },
-    /// Update market data with latest price values (keeper only)
+    /// Update market data with latest price values (keeper only).
+    /// Handlers apply it with `MarketStateV1::update_market_data`, which also stamps the
+    /// `last_price_update_unix` timestamp that `is_tradable` checks for staleness.
     /// Accounts: Keeper (signer), Target (dummy - same as keeper), Global, Market
     UpdateMarketData {
         /// The market ID to update
@@ -190,7 +193,8 @@ pub enum TestnetIx {
         keeper_pubkey: Pubkey,
     },
     /// Reset clearing house stats for a market (admin only)
-    /// Resets open interest, clearing position, entry price, and realized PnL to zero
+    /// Resets open interest, clearing position, entry price, and realized PnL to zero.
+    /// Only allowed once clearing is flat and open interest is zero (see `reset_clearing`)
     /// Accounts: Admin (signer), Target (dummy - same as admin), Global, Market
     ResetClearingHouse {
         /// The market ID to reset
@@ -266,7 +270,8 @@ pub enum TestnetIx {
     /// Consume a signed permit envelope to execute an action
     /// Accounts: Submitter (signer), InstructionsSysvar, Global, (action-specific accounts)
     ConsumePermit {
-        /// Borsh-encoded PermitEnvelopeV1
+        /// Borsh-encoded versioned `PermitEnvelope` (version byte, then the envelope body),
+        /// decoded with `PermitEnvelope::decode`; the signature covers exactly these bytes
         permit_bytes: Vec<u8>,
         /// Index of the ed25519/secp256k1 verify instruction in this transaction
         verify_ix_index: u8,
@@ -343,6 +348,97 @@ pub enum TestnetIx {
     },
 }
 
+impl TestnetIx {
+    /// Number of variants; discriminants run from 0 to `VARIANT_COUNT - 1`
+    pub const VARIANT_COUNT: u8 = 38;
+
+    /// Decode instruction data, rejecting unknown discriminants, truncated payloads and
+    /// trailing bytes (usually a client/program version mismatch)
+    pub fn try_decode(data: &[u8]) -> Result<TestnetIx, IxDecodeError> {
+        let discriminant = *data.first().ok_or(IxDecodeError::Truncated)?;
+        if discriminant >= Self::VARIANT_COUNT {
+            return Err(IxDecodeError::UnknownDiscriminant(discriminant));
+        }
+
+        let mut rest = data;
+        let ix = TestnetIx::deserialize(&mut rest).map_err(|e| {
+            if crate::is_borsh_truncation::<TestnetIx>(data, rest.len(), &e) {
+                IxDecodeError::Truncated
+            } else {
+                IxDecodeError::InvalidData(e.to_string())
+            }
+        })?;
+        if !rest.is_empty() {
+            return Err(IxDecodeError::TrailingBytes(rest.len()));
+        }
+        Ok(ix)
+    }
+
+    /// Build an `OrderEntryV2` with the trigger, peg and builder fields at their required defaults
+    #[allow(clippy::too_many_arguments)]
+    pub fn order_entry_v2(
+        market_id: u64,
+        order_id: u64,
+        side: u8,
+        qty: u64,
+        price: Option<u64>,
+        tif: TimeInForce,
+        origin: OrderOriginator,
+        reduce_only: bool,
+    ) -> Self {
+        TestnetIx::OrderEntryV2 {
+            market_id,
+            order_id,
+            side,
+            qty,
+            price,
+            tif,
+            origin,
+            reduce_only,
+            trigger_price: None,
+            trigger_type: 0,
+            price_peg_type: 0,
+            builder_code: None,
+        }
+    }
+
+    /// Borsh-encode the instruction into instruction data
+    pub fn encode(&self) -> Vec<u8> {
+        self.try_to_vec()
+            .expect("serializing into a Vec cannot fail")
+    }
+}
+
+/// Errors from decoding raw instruction data into `TestnetIx`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IxDecodeError {
+    /// First byte does not name a known instruction
+    UnknownDiscriminant(u8),
+    /// Data ended before the variant's fields were complete
+    Truncated,
+    /// A valid variant was decoded but this many bytes were left over
+    TrailingBytes(usize),
+    /// A field failed to decode (e.g. a bad nested enum or bool tag)
+    InvalidData(String),
+}
+
+impl std::fmt::Display for IxDecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IxDecodeError::UnknownDiscriminant(d) => {
+                write!(f, "unknown instruction discriminant {}", d)
+            }
+            IxDecodeError::Truncated => write!(f, "instruction data truncated"),
+            IxDecodeError::TrailingBytes(n) => {
+                write!(f, "{} trailing bytes after instruction data", n)
+            }
+            IxDecodeError::InvalidData(e) => write!(f, "invalid instruction data: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for IxDecodeError {}
+
 #[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq, Eq)]
 pub struct OrderBookLevelInput {
     /// Price expressed in 1e-6 precision like other on-chain prices
@@ -520,4 +616,127 @@ mod tests {
         let data = ix.try_to_vec().unwrap();
         assert_eq!(data[0], 37);
     }
+
+    #[test]
+    fn test_order_entry_v2_encode_roundtrip() {
+        let ix = TestnetIx::order_entry_v2(
+            3,
+            42,
+            1,
+            500,
+            Some(101_000_000),
+            TimeInForce::IOC,
+            OrderOriginator::User(),
+            true,
+        );
+        let data = ix.encode();
+        assert_eq!(data, ix.try_to_vec().unwrap());
+
+        match TestnetIx::try_from_slice(&data).unwrap() {
+            TestnetIx::OrderEntryV2 {
+                market_id,
+                order_id,
+                side,
+                qty,
+                price,
+                tif,
+                origin,
+                reduce_only,
+                trigger_price,
+                trigger_type,
+                price_peg_type,
+                builder_code,
+            } => {
+                assert_eq!(market_id, 3);
+                assert_eq!(order_id, 42);
+                assert_eq!(side, 1);
+                assert_eq!(qty, 500);
+                assert_eq!(price, Some(101_000_000));
+                assert_eq!(tif, TimeInForce::IOC);
+                assert_eq!(origin, OrderOriginator::User());
+                assert!(reduce_only);
+                assert_eq!(trigger_price, None);
+                assert_eq!(trigger_type, 0);
+                assert_eq!(price_peg_type, 0);
+                assert_eq!(builder_code, None);
+            }
+            other => panic!("unexpected variant {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_variant_count_matches_last_discriminant() {
+        let ix = TestnetIx::UpdateOrderBookSnapshot {
+            market_id: 1,
+            n_sig_figs: 4,
+            bids: vec![],
+            asks: vec![],
+        };
+        assert_eq!(ix.encode()[0], TestnetIx::VARIANT_COUNT - 1);
+    }
+
+    #[test]
+    fn test_try_decode_valid() {
+        let data = TestnetIx::Deposit { amount: 123 }.encode();
+        match TestnetIx::try_decode(&data).unwrap() {
+            TestnetIx::Deposit { amount } => assert_eq!(amount, 123),
+            other => panic!("unexpected variant {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_try_decode_unknown_discriminant() {
+        assert_eq!(
+            TestnetIx::try_decode(&[TestnetIx::VARIANT_COUNT, 0, 0]).unwrap_err(),
+            IxDecodeError::UnknownDiscriminant(TestnetIx::VARIANT_COUNT)
+        );
+        assert_eq!(
+            TestnetIx::try_decode(&[255]).unwrap_err(),
+            IxDecodeError::UnknownDiscriminant(255)
+        );
+    }
+
+    #[test]
+    fn test_try_decode_truncated() {
+        assert_eq!(
+            TestnetIx::try_decode(&[]).unwrap_err(),
+            IxDecodeError::Truncated
+        );
+        let data = TestnetIx::Withdraw { amount: 456 }.encode();
+        assert_eq!(
+            TestnetIx::try_decode(&data[..data.len() - 1]).unwrap_err(),
+            IxDecodeError::Truncated
+        );
+    }
+
+    #[test]
+    fn test_try_decode_trailing_bytes() {
+        let mut data = TestnetIx::Withdraw { amount: 456 }.encode();
+        data.extend_from_slice(&[0, 0]);
+        assert_eq!(
+            TestnetIx::try_decode(&data).unwrap_err(),
+            IxDecodeError::TrailingBytes(2)
+        );
+    }
+
+    #[test]
+    fn test_try_decode_invalid_field() {
+        let mut data = TestnetIx::order_entry_v2(
+            0,
+            1,
+            0,
+            100,
+            None,
+            TimeInForce::GTC,
+            OrderOriginator::User(),
+            false,
+        )
+        .encode();
+        // market_id (8) + order_id (8) + side (1) + qty (8) + price None (1) puts tif at 27
+        data[27] = 200;
+        assert!(matches!(
+            TestnetIx::try_decode(&data).unwrap_err(),
+            IxDecodeError::InvalidData(_)
+        ));
+    }
 }
@@ -1,3 +1,17 @@
+//! Shared data models for the Ambient Ember program, keepers, indexers and SDKs.
+//!
+//! Everything compiled without features is on-chain safe: `fill_quote`, `instruction`,
+//! `permit`, `state` and `storage` only use integer math and Borsh layouts.
+//!
+//! Off-chain only helpers sit behind cargo features:
+//! - `std`: f64 conversions (`lots_to_f64`, `price_to_f64`), `Display` for `OrderDetails`,
+//!   `OrderDetailStorage::builder_volume_rollup` and `CrossMarginAccountV1::to_snapshot`
+//! - `permit-signing`: Ed25519/secp256k1 signing for permits and fill quotes
+//!
+//! CI should build both profiles so a helper can't leak into the on-chain build:
+//! `cargo build -p ember-core --no-default-features` and
+//! `cargo test -p ember-core --all-features`.
+
 pub mod fill_quote;
 pub mod instruction;
 pub mod permit;
@@ -9,3 +23,75 @@ pub use instruction::*;
 pub use permit::*;
 pub use state::*;
 pub use storage::*;
+
+/// Zero bytes appended when probing for truncation: the largest payload a transaction carries
+const TRUNCATION_PROBE_PAD: usize = 1232;
+
+/// Whether decoding `data` as `T` failed with `err` only because the input ran out, given
+/// that `rest_len` bytes were left unconsumed at the failure.
+///
+/// Borsh 0.9 reports short input as `InvalidInput`, the same kind as a bad tag byte. A bad tag
+/// fails at the same offset whatever follows it, while a short read fails without consuming,
+/// so the input is truncated exactly when zero padding lets the decoder get further.
+pub(crate) fn is_borsh_truncation<T: borsh::BorshDeserialize>(
+    data: &[u8],
+    rest_len: usize,
+    err: &std::io::Error,
+) -> bool {
+    match err.kind() {
+        std::io::ErrorKind::UnexpectedEof => true,
+        std::io::ErrorKind::InvalidInput => {
+            let failed_at = data.len() - rest_len;
+            let mut padded = data.to_vec();
+            padded.resize(data.len() + TRUNCATION_PROBE_PAD, 0);
+            let mut rest = padded.as_slice();
+            let _ = T::deserialize(&mut rest);
+            padded.len() - rest.len() > failed_at
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use borsh::{BorshDeserialize, BorshSerialize};
+
+    #[derive(BorshSerialize, BorshDeserialize, Debug, PartialEq)]
+    enum Probe {
+        Flag(bool),
+        Blob(u64, Vec<u8>),
+    }
+
+    fn decode_err(data: &[u8]) -> (usize, std::io::Error) {
+        let mut rest = data;
+        let err = Probe::deserialize(&mut rest).unwrap_err();
+        (rest.len(), err)
+    }
+
+    #[test]
+    fn test_is_borsh_truncation_matches_borsh_errors() {
+        let blob = Probe::Blob(7, vec![1, 2, 3]).try_to_vec().unwrap();
+
+        // Every strict prefix is truncated, and borsh reports it as InvalidInput
+        for len in 0..blob.len() {
+            let (rest_len, err) = decode_err(&blob[..len]);
+            assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+            assert!(is_borsh_truncation::<Probe>(&blob[..len], rest_len, &err));
+        }
+
+        // Bad tags are the same error kind but not truncation
+        for bad in [vec![2u8], vec![0u8, 2]] {
+            let (rest_len, err) = decode_err(&bad);
+            assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+            assert!(!is_borsh_truncation::<Probe>(&bad, rest_len, &err));
+        }
+
+        // A length prefix no transaction could satisfy is malformed rather than cut short
+        let mut huge = vec![1u8];
+        huge.extend_from_slice(&7u64.to_le_bytes());
+        huge.extend_from_slice(&u32::MAX.to_le_bytes());
+        let (rest_len, err) = decode_err(&huge);
+        assert!(!is_borsh_truncation::<Probe>(&huge, rest_len, &err));
+    }
+}
@@ -1,10 +1,13 @@
 use borsh::{BorshDeserialize, BorshSerialize};
-use solana_program::pubkey::Pubkey;
+use solana_program::{msg, program_error::ProgramError, pubkey::Pubkey};
 
 #[cfg(feature = "permit-signing")]
 use ed25519_dalek::{Keypair as Ed25519Keypair, Signer as Ed25519Signer};
 
-use crate::TimeInForce;
+use crate::{
+    OrderDetails, OrderOriginator, OrderPrice, OrderSide, OrderTombstone, PriceReference,
+    TimeInForce, TriggerCondition, TriggerEntrySize,
+};
 
 /// Domain separator for permit verification
 #[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
@@ -102,7 +105,7 @@ pub enum PermitAction {
     },
     SetLeverage {
         market_id: u64,
-        target_leverage_bps: u16,
+        target_leverage_bps: u32,
         health_floor: Option<HealthFloor>,
     },
     Noop,
@@ -113,6 +116,91 @@ pub enum PermitAction {
     },
 }
 
+/// Permit `trigger_type` codes carried by `Place`/`Modify` actions
+pub const PERMIT_TRIGGER_NONE: u8 = 0;
+pub const PERMIT_TRIGGER_PRICE_BELOW: u8 = 1;
+pub const PERMIT_TRIGGER_PRICE_ABOVE: u8 = 2;
+
+impl PermitAction {
+    /// Translate a `Place` action into the `OrderDetails` stored for the order.
+    ///
+    /// Triggered places start in `PreTrigger` with a mark-price entry condition, and
+    /// `reduce_only` is carried as a `ReduceOnlyFail` cancel condition. The health floor
+    /// is checked by the permit handler when the action is consumed and is not stored.
+    pub fn to_order_details(&self, order_id: u64) -> Result<OrderDetails, PermitError> {
+        let PermitAction::Place {
+            client_id,
+            side,
+            qty,
+            price,
+            tif,
+            reduce_only,
+            trigger_price,
+            trigger_type,
+            ..
+        } = self
+        else {
+            return Err(PermitError::NotPlaceAction);
+        };
+
+        let side = OrderSide::try_from(*side).map_err(|_| PermitError::InvalidSide(*side))?;
+        let price = match price {
+            Some(px) => OrderPrice::Limit(*px),
+            None => OrderPrice::Market(),
+        };
+
+        let mut order = OrderDetails::new(order_id, side, *qty, price, tif.clone());
+        order.origin = OrderOriginator::Permit();
+        order.set_client_id(*client_id);
+
+        match (*trigger_type, trigger_price) {
+            (PERMIT_TRIGGER_NONE, None) => {}
+            (PERMIT_TRIGGER_PRICE_BELOW, Some(px)) => {
+                order.entry_cond = TriggerCondition::PriceBelow(*px, PriceReference::MarkPrice());
+            }
+            (PERMIT_TRIGGER_PRICE_ABOVE, Some(px)) => {
+                order.entry_cond = TriggerCondition::PriceAbove(*px, PriceReference::MarkPrice());
+            }
+            (trigger_type, _) => return Err(PermitError::InvalidTrigger(trigger_type)),
+        }
+        if order.entry_cond != TriggerCondition::Off() {
+            order.entry_cond_size = TriggerEntrySize::OrderSizePercent(10000);
+            order.tombstone = OrderTombstone::PreTrigger();
+        }
+
+        if *reduce_only {
+            order.cancel_cond_2 = TriggerCondition::ReduceOnlyFail();
+        }
+
+        Ok(order)
+    }
+}
+
+/// Errors that can occur when interpreting a permit action
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PermitError {
+    /// The action is not a `Place`
+    NotPlaceAction,
+    /// Side byte is neither 0 (bid) nor 1 (ask)
+    InvalidSide(u8),
+    /// Unknown trigger type, or trigger type and trigger price disagree
+    InvalidTrigger(u8),
+}
+
+impl std::fmt::Display for PermitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PermitError::NotPlaceAction => write!(f, "Permit action is not a Place"),
+            PermitError::InvalidSide(side) => write!(f, "Invalid order side: {}", side),
+            PermitError::InvalidTrigger(trigger_type) => {
+                write!(f, "Invalid trigger type: {}", trigger_type)
+            }
+        }
+    }
+}
+
+impl std::error::Error for PermitError {}
+
 /// Main permit envelope structure (V1)
 #[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
 pub struct PermitEnvelopeV1 {
@@ -127,7 +215,122 @@ pub struct PermitEnvelopeV1 {
     pub nonce: u64,
 }
 
-#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+impl PermitEnvelopeV1 {
+    /// Check the fee a relayer is charging against the permit's cap, and that the
+    /// relayer is the one the permit pins (if any).
+    pub fn validate_fee(&self, charged_fee: u64, relayer: &Pubkey) -> Result<(), ProgramError> {
+        if charged_fee > self.max_fee_quote {
+            msg!(
+                "Error: Relayer fee {} exceeds permit max fee {}",
+                charged_fee,
+                self.max_fee_quote
+            );
+            return Err(ProgramError::InvalidArgument);
+        }
+        if let Some(pinned) = self.relayer {
+            if pinned != *relayer {
+                msg!(
+                    "Error: Relayer {} does not match permit relayer {}",
+                    relayer,
+                    pinned
+                );
+                return Err(ProgramError::InvalidArgument);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Versioned permit envelope as sent on the wire: a leading version byte followed by the
+/// Borsh body for that version, so future envelope layouts can be told apart.
+#[derive(Debug, Clone)]
+pub enum PermitEnvelope {
+    V1(PermitEnvelopeV1),
+}
+
+impl PermitEnvelope {
+    pub const VERSION_V1: u8 = 1;
+
+    pub fn version(&self) -> u8 {
+        match self {
+            PermitEnvelope::V1(_) => Self::VERSION_V1,
+        }
+    }
+
+    /// Decode a versioned envelope, rejecting unknown versions and leftover bytes
+    pub fn decode(bytes: &[u8]) -> Result<PermitEnvelope, PermitDecodeError> {
+        let version = *bytes.first().ok_or(PermitDecodeError::Truncated)?;
+        if version != Self::VERSION_V1 {
+            return Err(PermitDecodeError::UnknownVersion(version));
+        }
+
+        let mut rest = bytes;
+        let envelope = PermitEnvelope::deserialize(&mut rest).map_err(|e| {
+            if crate::is_borsh_truncation::<PermitEnvelope>(bytes, rest.len(), &e) {
+                PermitDecodeError::Truncated
+            } else {
+                PermitDecodeError::InvalidData(e.to_string())
+            }
+        })?;
+        if !rest.is_empty() {
+            return Err(PermitDecodeError::TrailingBytes(rest.len()));
+        }
+        Ok(envelope)
+    }
+}
+
+impl BorshSerialize for PermitEnvelope {
+    fn serialize<W: std::io::Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        self.version().serialize(writer)?;
+        match self {
+            PermitEnvelope::V1(envelope) => envelope.serialize(writer),
+        }
+    }
+}
+
+impl BorshDeserialize for PermitEnvelope {
+    fn deserialize(buf: &mut &[u8]) -> std::io::Result<Self> {
+        match u8::deserialize(buf)? {
+            Self::VERSION_V1 => Ok(PermitEnvelope::V1(PermitEnvelopeV1::deserialize(buf)?)),
+            version => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("Unknown permit envelope version {}", version),
+            )),
+        }
+    }
+}
+
+/// Errors from decoding a versioned `PermitEnvelope`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PermitDecodeError {
+    /// Version byte does not name a known envelope layout
+    UnknownVersion(u8),
+    /// Data ended before the envelope was complete
+    Truncated,
+    /// A valid envelope was decoded but this many bytes were left over
+    TrailingBytes(usize),
+    /// A field failed to decode
+    InvalidData(String),
+}
+
+impl std::fmt::Display for PermitDecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PermitDecodeError::UnknownVersion(v) => {
+                write!(f, "unknown permit envelope version {}", v)
+            }
+            PermitDecodeError::Truncated => write!(f, "permit envelope truncated"),
+            PermitDecodeError::TrailingBytes(n) => {
+                write!(f, "{} trailing bytes after permit envelope", n)
+            }
+            PermitDecodeError::InvalidData(e) => write!(f, "invalid permit envelope: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for PermitDecodeError {}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq, Eq)]
 pub enum KeyType {
     Ed25519,
     Secp256k1,
@@ -148,6 +351,10 @@ pub struct SessionState {
 impl SessionState {
     pub const LEN: usize = 32 + 32 + 8 + 4 + 8 + 8 + 1;
 
+    pub fn scopes(&self) -> SessionScopes {
+        SessionScopes::from_bits(self.scopes_bits)
+    }
+
     pub fn has_scope(&self, action: &PermitAction) -> bool {
         match action {
             PermitAction::Place { .. } => (self.scopes_bits & SCOPE_PLACE) != 0,
@@ -171,6 +378,161 @@ pub const SCOPE_WITHDRAW: u32 = 1 << 2;
 pub const SCOPE_SET_LEVERAGE: u32 = 1 << 3;
 pub const SCOPE_FAUCET: u32 = 1 << 4;
 
+/// A single session permission, backed by one of the `SCOPE_*` bits
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionScope {
+    Place,
+    Cancel,
+    Withdraw,
+    SetLeverage,
+    Faucet,
+}
+
+impl SessionScope {
+    pub const ALL: [SessionScope; 5] = [
+        SessionScope::Place,
+        SessionScope::Cancel,
+        SessionScope::Withdraw,
+        SessionScope::SetLeverage,
+        SessionScope::Faucet,
+    ];
+
+    pub fn bit(self) -> u32 {
+        match self {
+            SessionScope::Place => SCOPE_PLACE,
+            SessionScope::Cancel => SCOPE_CANCEL,
+            SessionScope::Withdraw => SCOPE_WITHDRAW,
+            SessionScope::SetLeverage => SCOPE_SET_LEVERAGE,
+            SessionScope::Faucet => SCOPE_FAUCET,
+        }
+    }
+}
+
+/// Typed view over the `scopes_bits` carried by `DelegateSession` and `SessionState`.
+/// Unknown bits are preserved so the value round-trips unchanged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SessionScopes(u32);
+
+impl SessionScopes {
+    pub fn empty() -> Self {
+        SessionScopes(0)
+    }
+
+    pub fn from_bits(bits: u32) -> Self {
+        SessionScopes(bits)
+    }
+
+    pub fn to_bits(self) -> u32 {
+        self.0
+    }
+
+    pub fn insert(&mut self, scope: SessionScope) {
+        self.0 |= scope.bit();
+    }
+
+    pub fn contains(self, scope: SessionScope) -> bool {
+        self.0 & scope.bit() != 0
+    }
+}
+
+impl FromIterator<SessionScope> for SessionScopes {
+    fn from_iter<I: IntoIterator<Item = SessionScope>>(iter: I) -> Self {
+        let mut scopes = SessionScopes::empty();
+        for scope in iter {
+            scopes.insert(scope);
+        }
+        scopes
+    }
+}
+
+/// Check that a session holding `scopes` may perform `action`.
+///
+/// `Modify` needs both place and cancel scopes. For `Place`/`Modify`, `qty` must not exceed
+/// `per_market_size_limit_lots`; a non-positive limit disables the size check.
+pub fn authorize_action(
+    action: &PermitAction,
+    scopes: SessionScopes,
+    per_market_size_limit_lots: i64,
+) -> Result<(), ProgramError> {
+    let (required, qty): (&[SessionScope], Option<u64>) = match action {
+        PermitAction::Place { qty, .. } => (&[SessionScope::Place], Some(*qty)),
+        PermitAction::Modify { qty, .. } => {
+            (&[SessionScope::Place, SessionScope::Cancel], Some(*qty))
+        }
+        PermitAction::CancelById { .. }
+        | PermitAction::CancelByClientId { .. }
+        | PermitAction::CancelAll { .. } => (&[SessionScope::Cancel], None),
+        PermitAction::Withdraw { .. } => (&[SessionScope::Withdraw], None),
+        PermitAction::SetLeverage { .. } => (&[SessionScope::SetLeverage], None),
+        PermitAction::Faucet { .. } => (&[SessionScope::Faucet], None),
+        PermitAction::Noop => (&[], None),
+    };
+
+    if let Some(missing) = required.iter().find(|scope| !scopes.contains(**scope)) {
+        msg!("Error: Session lacks {:?} scope", missing);
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    if let Some(qty) = qty {
+        if per_market_size_limit_lots > 0 && qty > per_market_size_limit_lots as u64 {
+            msg!(
+                "Error: Order qty {} exceeds session size limit {}",
+                qty,
+                per_market_size_limit_lots
+            );
+            return Err(ProgramError::InvalidArgument);
+        }
+    }
+    Ok(())
+}
+
+/// Rolling 24h withdrawal usage for a session's `withdraw_limit_24h`
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct WithdrawWindow {
+    pub window_start_unix: i64,
+    pub used: u64,
+}
+
+impl WithdrawWindow {
+    pub const LEN: usize = 8 + 8;
+    pub const WINDOW_SECS: i64 = 24 * 60 * 60;
+
+    /// Record a withdrawal of `amount`, starting a fresh window once 24h have elapsed
+    /// since the current one began. Rejects (leaving usage unchanged) when the window's
+    /// total would exceed `limit`.
+    pub fn try_withdraw(
+        &mut self,
+        amount: u64,
+        limit: u64,
+        now_unix: i64,
+    ) -> Result<(), ProgramError> {
+        let (window_start_unix, used) =
+            if now_unix.saturating_sub(self.window_start_unix) >= Self::WINDOW_SECS {
+                (now_unix, 0)
+            } else {
+                (self.window_start_unix, self.used)
+            };
+
+        let new_used = used.checked_add(amount).ok_or_else(|| {
+            msg!("Error: Overflow tracking session withdrawals");
+            ProgramError::ArithmeticOverflow
+        })?;
+        if new_used > limit {
+            msg!(
+                "Error: Withdrawal of {} exceeds 24h limit {} (used {})",
+                amount,
+                limit,
+                used
+            );
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        self.window_start_unix = window_start_unix;
+        self.used = new_used;
+        Ok(())
+    }
+}
+
 /// Allowance PDA state
 #[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
 pub struct AllowanceState {
@@ -255,12 +617,15 @@ pub struct PermitConsumedEvent {
     pub timestamp: i64,
 }
 
-/// Result of signing a permit envelope with an Ed25519 keypair.
+/// Result of signing a permit envelope with an Ed25519 or secp256k1 key.
 #[cfg(feature = "permit-signing")]
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct SignedPermit {
     pub bytes: Vec<u8>,
     pub signature: [u8; 64],
+    pub key_type: KeyType,
+    /// secp256k1 recovery id (always 0 for Ed25519)
+    pub recovery_id: u8,
 }
 
 #[cfg(feature = "permit-signing")]
@@ -268,17 +633,134 @@ impl SignedPermit {
     pub fn into_parts(self) -> (Vec<u8>, [u8; 64]) {
         (self.bytes, self.signature)
     }
+
+    /// Recover the Ethereum-style address that signed a secp256k1 permit, so relayers
+    /// can cross-check it against the envelope's `authorizer`.
+    pub fn recover_secp256k1_address(&self) -> Result<[u8; 20], SignerError> {
+        use solana_program::keccak;
+        use solana_program::secp256k1_recover::secp256k1_recover;
+
+        if self.key_type != KeyType::Secp256k1 {
+            return Err(SignerError::UnsupportedKeyType);
+        }
+
+        let message_hash = keccak::hash(&self.bytes);
+        let pubkey = secp256k1_recover(&message_hash.0, self.recovery_id, &self.signature)
+            .map_err(|_| SignerError::RecoveryFailed)?;
+
+        let pubkey_hash = keccak::hash(&pubkey.to_bytes());
+        let mut address = [0u8; 20];
+        address.copy_from_slice(&pubkey_hash.0[12..]);
+        Ok(address)
+    }
+}
+
+/// Errors that can occur when signing or inspecting permits
+#[cfg(feature = "permit-signing")]
+#[derive(Debug)]
+pub enum SignerError {
+    /// The envelope could not be Borsh-serialized
+    Serialization(std::io::Error),
+    /// The envelope's `key_type` does not match the key used to sign it
+    KeyTypeMismatch { expected: KeyType, found: KeyType },
+    /// The operation is not available for this key type
+    UnsupportedKeyType,
+    /// The signature did not recover to a valid public key
+    RecoveryFailed,
+}
+
+#[cfg(feature = "permit-signing")]
+impl std::fmt::Display for SignerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SignerError::Serialization(e) => write!(f, "Failed to serialize permit: {}", e),
+            SignerError::KeyTypeMismatch { expected, found } => write!(
+                f,
+                "Envelope key type {:?} does not match signing key {:?}",
+                found, expected
+            ),
+            SignerError::UnsupportedKeyType => write!(f, "Unsupported key type for operation"),
+            SignerError::RecoveryFailed => write!(f, "Failed to recover signer from signature"),
+        }
+    }
+}
+
+#[cfg(feature = "permit-signing")]
+impl std::error::Error for SignerError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SignerError::Serialization(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "permit-signing")]
+impl From<std::io::Error> for SignerError {
+    fn from(e: std::io::Error) -> Self {
+        SignerError::Serialization(e)
+    }
+}
+
+/// Reject envelopes whose declared key type differs from the signing key
+#[cfg(feature = "permit-signing")]
+fn check_key_type(envelope: &PermitEnvelopeV1, expected: KeyType) -> Result<(), SignerError> {
+    if envelope.key_type != expected {
+        return Err(SignerError::KeyTypeMismatch {
+            expected,
+            found: envelope.key_type.clone(),
+        });
+    }
+    Ok(())
 }
 
-/// Serialize and sign a permit envelope with the provided Ed25519 keypair.
+/// Serialize and sign a V1 permit envelope with the provided Ed25519 keypair. The signed
+/// bytes are the versioned `PermitEnvelope::V1` encoding, same as `sign_permit`.
 #[cfg(feature = "permit-signing")]
 pub fn sign_permit_ed25519(
     envelope: &PermitEnvelopeV1,
     keypair: &Ed25519Keypair,
-) -> Result<SignedPermit, std::io::Error> {
+) -> Result<SignedPermit, SignerError> {
+    sign_permit(&PermitEnvelope::V1(envelope.clone()), keypair)
+}
+
+/// Sign the versioned encoding of a permit envelope (version byte included) with the
+/// provided Ed25519 keypair.
+#[cfg(feature = "permit-signing")]
+pub fn sign_permit(
+    envelope: &PermitEnvelope,
+    keypair: &Ed25519Keypair,
+) -> Result<SignedPermit, SignerError> {
+    let PermitEnvelope::V1(inner) = envelope;
+    check_key_type(inner, KeyType::Ed25519)?;
     let bytes = envelope.try_to_vec()?;
     let signature = keypair.sign(&bytes).to_bytes();
-    Ok(SignedPermit { bytes, signature })
+    Ok(SignedPermit {
+        bytes,
+        signature,
+        key_type: KeyType::Ed25519,
+        recovery_id: 0,
+    })
+}
+
+/// Serialize and sign a V1 permit envelope with the provided secp256k1 secret key.
+/// The keccak-256 hash of the versioned `PermitEnvelope::V1` encoding is signed.
+#[cfg(feature = "permit-signing")]
+pub fn sign_permit_secp256k1(
+    envelope: &PermitEnvelopeV1,
+    secret_key: &libsecp256k1::SecretKey,
+) -> Result<SignedPermit, SignerError> {
+    check_key_type(envelope, KeyType::Secp256k1)?;
+    let bytes = PermitEnvelope::V1(envelope.clone()).try_to_vec()?;
+    let message_hash = solana_program::keccak::hash(&bytes);
+    let message = libsecp256k1::Message::parse(&message_hash.0);
+    let (signature, recovery_id) = libsecp256k1::sign(&message, secret_key);
+    Ok(SignedPermit {
+        bytes,
+        signature: signature.serialize(),
+        key_type: KeyType::Secp256k1,
+        recovery_id: recovery_id.serialize(),
+    })
 }
 
 #[cfg(test)]
@@ -396,6 +878,216 @@ mod tests {
         }));
     }
 
+    #[test]
+    fn test_session_scopes_bits() {
+        assert_eq!(SessionScope::Place.bit(), 1);
+        assert_eq!(SessionScope::Cancel.bit(), 2);
+        assert_eq!(SessionScope::Withdraw.bit(), 4);
+        assert_eq!(SessionScope::SetLeverage.bit(), 8);
+        assert_eq!(SessionScope::Faucet.bit(), 16);
+
+        for scope in SessionScope::ALL {
+            let mut scopes = SessionScopes::empty();
+            scopes.insert(scope);
+            assert_eq!(scopes.to_bits(), scope.bit());
+            assert!(SessionScope::ALL
+                .iter()
+                .all(|other| scopes.contains(*other) == (*other == scope)));
+        }
+
+        let scopes: SessionScopes = [SessionScope::Place, SessionScope::Withdraw]
+            .into_iter()
+            .collect();
+        assert_eq!(scopes.to_bits(), SCOPE_PLACE | SCOPE_WITHDRAW);
+        assert_eq!(SessionScopes::from_bits(scopes.to_bits()), scopes);
+        assert!(scopes.contains(SessionScope::Withdraw));
+        assert!(!scopes.contains(SessionScope::Cancel));
+
+        // Unknown bits survive the round trip
+        let raw = SCOPE_CANCEL | (1 << 31);
+        assert_eq!(SessionScopes::from_bits(raw).to_bits(), raw);
+    }
+
+    #[test]
+    fn test_authorize_action() {
+        let scopes: SessionScopes = [SessionScope::Place, SessionScope::Cancel]
+            .into_iter()
+            .collect();
+
+        // Place within the size limit is allowed, above it is not
+        let place = place_action(Some(1_000), TimeInForce::GTC);
+        assert_eq!(authorize_action(&place, scopes, 250), Ok(()));
+        assert_eq!(
+            authorize_action(&place, scopes, 249),
+            Err(ProgramError::InvalidArgument)
+        );
+        assert_eq!(authorize_action(&place, scopes, 0), Ok(()));
+
+        // Withdraw requires the withdraw scope
+        let withdraw = PermitAction::Withdraw {
+            amount: 1_000,
+            to_owner: Pubkey::new_unique(),
+            health_floor: None,
+        };
+        assert_eq!(
+            authorize_action(&withdraw, scopes, 0),
+            Err(ProgramError::MissingRequiredSignature)
+        );
+        let mut with_withdraw = scopes;
+        with_withdraw.insert(SessionScope::Withdraw);
+        assert_eq!(authorize_action(&withdraw, with_withdraw, 0), Ok(()));
+
+        // Modify needs both place and cancel
+        let modify = PermitAction::Modify {
+            market_id: 1,
+            cancel_order_id: 5,
+            new_client_id: 6,
+            side: 0,
+            qty: 10,
+            price: Some(1_000),
+            tif: TimeInForce::GTC,
+            reduce_only: false,
+            trigger_price: None,
+            trigger_type: PERMIT_TRIGGER_NONE,
+            health_floor: None,
+        };
+        assert_eq!(authorize_action(&modify, scopes, 100), Ok(()));
+        let place_only: SessionScopes = [SessionScope::Place].into_iter().collect();
+        assert_eq!(
+            authorize_action(&modify, place_only, 100),
+            Err(ProgramError::MissingRequiredSignature)
+        );
+
+        assert_eq!(
+            authorize_action(&PermitAction::Noop, SessionScopes::empty(), 0),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_withdraw_window_limit_and_rollover() {
+        let start = 1_700_000_000;
+        let mut window = WithdrawWindow::default();
+
+        window.try_withdraw(600, 1_000, start).unwrap();
+        assert_eq!(window.window_start_unix, start);
+        assert_eq!(window.used, 600);
+
+        // Mid-window: the running total may reach but not pass the limit
+        window.try_withdraw(400, 1_000, start + 3_600).unwrap();
+        assert_eq!(
+            window.try_withdraw(1, 1_000, start + 7_200),
+            Err(ProgramError::InvalidArgument)
+        );
+        assert_eq!(window.used, 1_000);
+        assert_eq!(window.window_start_unix, start);
+
+        // Just before rollover still counts against the old window
+        assert!(window
+            .try_withdraw(1, 1_000, start + WithdrawWindow::WINDOW_SECS - 1)
+            .is_err());
+
+        // After 24h the window resets
+        let later = start + WithdrawWindow::WINDOW_SECS;
+        window.try_withdraw(900, 1_000, later).unwrap();
+        assert_eq!(window.window_start_unix, later);
+        assert_eq!(window.used, 900);
+
+        // A single withdrawal above the limit is rejected even in a fresh window
+        let mut fresh = WithdrawWindow::default();
+        assert!(fresh.try_withdraw(1_001, 1_000, start).is_err());
+        assert_eq!(fresh, WithdrawWindow::default());
+    }
+
+    fn place_action(price: Option<u64>, tif: TimeInForce) -> PermitAction {
+        PermitAction::Place {
+            market_id: 1,
+            client_id: 777,
+            side: 1,
+            qty: 250,
+            price,
+            tif,
+            reduce_only: false,
+            trigger_price: None,
+            trigger_type: PERMIT_TRIGGER_NONE,
+            health_floor: None,
+        }
+    }
+
+    #[test]
+    fn test_place_limit_gtc_to_order_details() {
+        let order = place_action(Some(50_000), TimeInForce::GTC)
+            .to_order_details(42)
+            .unwrap();
+
+        assert_eq!(order.order_id, 42);
+        assert_eq!(order.client_id(), 777);
+        assert_eq!(order.side, OrderSide::Ask);
+        assert_eq!(order.qty, 250);
+        assert_eq!(order.price, OrderPrice::Limit(50_000));
+        assert_eq!(order.origin, OrderOriginator::Permit());
+        assert_eq!(order.tombstone, OrderTombstone::Open());
+        assert_eq!(order.entry_cond, TriggerCondition::Off());
+        assert_eq!(order.cancel_cond, TriggerCondition::Off());
+    }
+
+    #[test]
+    fn test_place_market_ioc_to_order_details() {
+        let mut action = place_action(None, TimeInForce::IOC);
+        if let PermitAction::Place {
+            side, reduce_only, ..
+        } = &mut action
+        {
+            *side = 0;
+            *reduce_only = true;
+        }
+
+        let order = action.to_order_details(7).unwrap();
+        assert_eq!(order.side, OrderSide::Bid);
+        assert_eq!(order.price, OrderPrice::Market());
+        assert_eq!(order.cancel_cond, TriggerCondition::ImmediateOrCancelFail());
+        assert_eq!(order.cancel_cond_2, TriggerCondition::ReduceOnlyFail());
+    }
+
+    #[test]
+    fn test_place_trigger_and_invalid_actions() {
+        let mut action = place_action(Some(50_000), TimeInForce::GTC);
+        if let PermitAction::Place {
+            trigger_price,
+            trigger_type,
+            ..
+        } = &mut action
+        {
+            *trigger_price = Some(48_000);
+            *trigger_type = PERMIT_TRIGGER_PRICE_BELOW;
+        }
+        let order = action.to_order_details(1).unwrap();
+        assert_eq!(
+            order.entry_cond,
+            TriggerCondition::PriceBelow(48_000, PriceReference::MarkPrice())
+        );
+        assert_eq!(order.tombstone, OrderTombstone::PreTrigger());
+
+        // Trigger type without a trigger price is rejected
+        if let PermitAction::Place { trigger_price, .. } = &mut action {
+            *trigger_price = None;
+        }
+        assert_eq!(
+            action.to_order_details(1),
+            Err(PermitError::InvalidTrigger(PERMIT_TRIGGER_PRICE_BELOW))
+        );
+
+        if let PermitAction::Place { side, .. } = &mut action {
+            *side = 2;
+        }
+        assert_eq!(action.to_order_details(1), Err(PermitError::InvalidSide(2)));
+
+        assert_eq!(
+            PermitAction::Noop.to_order_details(1),
+            Err(PermitError::NotPlaceAction)
+        );
+    }
+
     #[test]
     fn test_permit_envelope_serialization() {
         let envelope = PermitEnvelopeV1 {
@@ -483,9 +1175,221 @@ mod tests {
         };
 
         let signed = sign_permit_ed25519(&envelope, &keypair).expect("signing should succeed");
-        assert_eq!(signed.bytes, envelope.try_to_vec().unwrap());
+        assert_eq!(
+            signed.bytes,
+            PermitEnvelope::V1(envelope.clone()).try_to_vec().unwrap()
+        );
 
         let expected = keypair.sign(&signed.bytes).to_bytes();
         assert_eq!(signed.signature, expected);
+
+        assert!(matches!(
+            signed.recover_secp256k1_address(),
+            Err(SignerError::UnsupportedKeyType)
+        ));
+    }
+
+    #[test]
+    fn test_validate_fee() {
+        let relayer = Pubkey::new_unique();
+        let mut envelope = noop_envelope(KeyType::Ed25519);
+        envelope.max_fee_quote = 500;
+
+        // Unpinned permit accepts any relayer up to the cap
+        assert!(envelope.validate_fee(500, &relayer).is_ok());
+        assert!(envelope.validate_fee(0, &Pubkey::new_unique()).is_ok());
+        assert_eq!(
+            envelope.validate_fee(501, &relayer),
+            Err(ProgramError::InvalidArgument)
+        );
+
+        envelope.relayer = Some(relayer);
+        assert!(envelope.validate_fee(100, &relayer).is_ok());
+        assert_eq!(
+            envelope.validate_fee(100, &Pubkey::new_unique()),
+            Err(ProgramError::InvalidArgument)
+        );
+        assert_eq!(
+            envelope.validate_fee(501, &relayer),
+            Err(ProgramError::InvalidArgument)
+        );
+    }
+
+    #[test]
+    fn test_permit_envelope_v1_round_trip() {
+        let inner = noop_envelope(KeyType::Ed25519);
+        let bytes = PermitEnvelope::V1(inner.clone()).try_to_vec().unwrap();
+
+        // Version byte, then the bare V1 layout
+        assert_eq!(bytes[0], PermitEnvelope::VERSION_V1);
+        assert_eq!(&bytes[1..], inner.try_to_vec().unwrap().as_slice());
+
+        let PermitEnvelope::V1(decoded) = PermitEnvelope::decode(&bytes).unwrap();
+        assert_eq!(decoded.try_to_vec().unwrap(), inner.try_to_vec().unwrap());
+        assert_eq!(decoded.nonce, inner.nonce);
+    }
+
+    #[test]
+    fn test_permit_envelope_decode_errors() {
+        let bytes = PermitEnvelope::V1(noop_envelope(KeyType::Ed25519))
+            .try_to_vec()
+            .unwrap();
+
+        let mut unknown = bytes.clone();
+        unknown[0] = 2;
+        assert_eq!(
+            PermitEnvelope::decode(&unknown).unwrap_err(),
+            PermitDecodeError::UnknownVersion(2)
+        );
+        // A bare, unversioned V1 envelope starts with the program id, not a version
+        unknown[0] = 0;
+        assert_eq!(
+            PermitEnvelope::decode(&unknown).unwrap_err(),
+            PermitDecodeError::UnknownVersion(0)
+        );
+
+        assert_eq!(
+            PermitEnvelope::decode(&[]).unwrap_err(),
+            PermitDecodeError::Truncated
+        );
+        assert_eq!(
+            PermitEnvelope::decode(&bytes[..bytes.len() - 1]).unwrap_err(),
+            PermitDecodeError::Truncated
+        );
+
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert_eq!(
+            PermitEnvelope::decode(&trailing).unwrap_err(),
+            PermitDecodeError::TrailingBytes(1)
+        );
+    }
+
+    #[cfg(feature = "permit-signing")]
+    #[test]
+    fn test_sign_permit_versioned() {
+        let secret_key = SecretKey::from_bytes(&[7u8; 32]).unwrap();
+        let public_key = PublicKey::from(&secret_key);
+        let keypair = Ed25519Keypair {
+            secret: secret_key,
+            public: public_key,
+        };
+
+        let envelope = PermitEnvelope::V1(noop_envelope(KeyType::Ed25519));
+        let signed = sign_permit(&envelope, &keypair).unwrap();
+        assert_eq!(signed.bytes, envelope.try_to_vec().unwrap());
+        assert_eq!(signed.signature, keypair.sign(&signed.bytes).to_bytes());
+        assert!(PermitEnvelope::decode(&signed.bytes).is_ok());
+
+        let secp = PermitEnvelope::V1(noop_envelope(KeyType::Secp256k1));
+        assert!(matches!(
+            sign_permit(&secp, &keypair),
+            Err(SignerError::KeyTypeMismatch { .. })
+        ));
+    }
+
+    #[cfg(feature = "permit-signing")]
+    #[test]
+    fn test_recover_secp256k1_address() {
+        let secret_key = libsecp256k1::SecretKey::parse(&[9u8; 32]).unwrap();
+        let public_key = libsecp256k1::PublicKey::from_secret_key(&secret_key);
+        let public_hash = solana_program::keccak::hash(&public_key.serialize()[1..]);
+        let mut expected_address = [0u8; 20];
+        expected_address.copy_from_slice(&public_hash.0[12..]);
+
+        let envelope = PermitEnvelopeV1 {
+            domain: PermitDomain {
+                program_id: Pubkey::new_unique(),
+                cluster: ClusterType::Testnet,
+                version: 1,
+            },
+            authorizer: Pubkey::new_unique(),
+            key_type: KeyType::Secp256k1,
+            action: PermitAction::Noop,
+            mode: ReplayMode::HlWindow { k: 16 },
+            expires_unix: 1_700_000_000,
+            max_fee_quote: 0,
+            relayer: None,
+            nonce: 42,
+        };
+
+        let signed = sign_permit_secp256k1(&envelope, &secret_key).expect("signing should succeed");
+        assert_eq!(signed.key_type, KeyType::Secp256k1);
+        assert!(PermitEnvelope::decode(&signed.bytes).is_ok());
+        assert_eq!(
+            signed.recover_secp256k1_address().unwrap(),
+            expected_address
+        );
+
+        // A tampered payload recovers a different address
+        let mut tampered = signed.clone();
+        tampered.bytes[0] ^= 1;
+        assert_ne!(
+            tampered.recover_secp256k1_address().ok(),
+            Some(expected_address)
+        );
+    }
+
+    fn noop_envelope(key_type: KeyType) -> PermitEnvelopeV1 {
+        PermitEnvelopeV1 {
+            domain: PermitDomain {
+                program_id: Pubkey::new_unique(),
+                cluster: ClusterType::Testnet,
+                version: 1,
+            },
+            authorizer: Pubkey::new_unique(),
+            key_type,
+            action: PermitAction::Noop,
+            mode: ReplayMode::HlWindow { k: 16 },
+            expires_unix: 1_700_000_000,
+            max_fee_quote: 0,
+            relayer: None,
+            nonce: 42,
+        }
+    }
+
+    #[cfg(feature = "permit-signing")]
+    #[test]
+    fn test_signer_error_variants() {
+        use std::error::Error;
+
+        // Signing with the wrong key type is a validation failure
+        let secret_key = SecretKey::from_bytes(&[7u8; 32]).unwrap();
+        let keypair = Ed25519Keypair {
+            public: PublicKey::from(&secret_key),
+            secret: secret_key,
+        };
+        assert!(matches!(
+            sign_permit_ed25519(&noop_envelope(KeyType::Secp256k1), &keypair),
+            Err(SignerError::KeyTypeMismatch {
+                expected: KeyType::Ed25519,
+                found: KeyType::Secp256k1,
+            })
+        ));
+
+        let secp_key = libsecp256k1::SecretKey::parse(&[9u8; 32]).unwrap();
+        assert!(matches!(
+            sign_permit_secp256k1(&noop_envelope(KeyType::Ed25519), &secp_key),
+            Err(SignerError::KeyTypeMismatch {
+                expected: KeyType::Secp256k1,
+                found: KeyType::Ed25519,
+            })
+        ));
+
+        // A signature that does not recover to a public key
+        let mut signed =
+            sign_permit_secp256k1(&noop_envelope(KeyType::Secp256k1), &secp_key).unwrap();
+        signed.signature = [0u8; 64];
+        assert!(matches!(
+            signed.recover_secp256k1_address(),
+            Err(SignerError::RecoveryFailed)
+        ));
+
+        // Serialization failures convert from io errors and keep their source
+        let err: SignerError =
+            std::io::Error::new(std::io::ErrorKind::InvalidData, "bad envelope").into();
+        assert!(matches!(err, SignerError::Serialization(_)));
+        assert!(err.source().is_some());
+        assert!(err.to_string().contains("bad envelope"));
     }
 }
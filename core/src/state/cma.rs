@@ -1,5 +1,6 @@
-use crate::state::math::mul_qty_px_signed;
-use crate::state::math::mul_qty_px_to_notional;
+use crate::state::math::apply_bps;
+use crate::state::math::{mul_qty_px_to_notional, mul_qty_px_to_notional_round_up};
+use crate::state::position::PositionDirection;
 use borsh::{BorshDeserialize, BorshSerialize};
 use solana_program::pubkey::Pubkey;
 use solana_program::{msg, program_error::ProgramError};
@@ -12,8 +13,9 @@ mod cma_test;
 #[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq, Eq)]
 pub struct TokenBalance {
     pub mint: Pubkey,
-    pub amount: u64, // free collateral for this mint
-    pub _pad: [u8; 16],
+    pub amount: u64,             // free collateral for this mint
+    pub lifetime_deposited: u64, // cumulative deposits, never decremented
+    pub _pad: [u8; 8],
 }
 
 /// Indicates where a margin/position bucket applies.
@@ -21,7 +23,18 @@ pub struct TokenBalance {
 pub enum MarginScope {
     /// Collateral committed to a single isolated market (identified by its numeric id).
     MarketIsolated(u64),
-    // Future scopes (FullCross, CrossGroup, etc.) can be added here while preserving Borsh order.
+    /// Position in a market (identified by its numeric id) margined from the account-wide cross pool.
+    FullCross(u64),
+    // Future scopes (CrossGroup, etc.) can be added here while preserving Borsh order.
+}
+
+impl MarginScope {
+    /// Market the scope's position belongs to
+    pub fn market_id(&self) -> u64 {
+        match self {
+            MarginScope::MarketIsolated(id) | MarginScope::FullCross(id) => *id,
+        }
+    }
 }
 
 /// Holds committed collateral, reserved margin, and position size for a given scope & token.
@@ -69,9 +82,106 @@ pub struct CmaFillResult {
     pub realized_pnl_banked: i64,
 }
 
+/// Aggregate outcome of closing every open position at the current mark
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct CloseAllEstimate {
+    /// Sum of realized PnL across all closed positions (collateral units)
+    pub realized_pnl: i64,
+    /// Sum of the closing notional, the base any taker fee would be charged on
+    pub close_notional: u64,
+    /// Taker fees for closing every position, charged per position on its notional
+    pub estimated_fees: u64,
+    /// Number of positions that would be closed
+    pub positions_closed: u32,
+}
+
+/// Risk state of a single margin bucket as exposed to indexers
+#[cfg(feature = "std")]
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq, Eq)]
+pub struct BucketSnapshot {
+    pub market_id: u64,
+    pub net_position: i64,
+    pub avg_entry_price: u64,
+    pub committed: u64,
+    /// Committed collateral plus unrealized PnL; `None` when no mark was supplied for the market
+    pub equity: Option<i64>,
+    pub open_bid_qty: u64,
+    pub open_ask_qty: u64,
+}
+
+/// Compact, version-tagged view of an account's risk state for indexers.
+/// Independent of the on-chain `CrossMarginAccountV1` layout.
+#[cfg(feature = "std")]
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq, Eq)]
+pub struct AccountSnapshot {
+    pub version: u8,
+    pub user: Pubkey,
+    pub buckets: Vec<BucketSnapshot>,
+    /// Free collateral summed over all mints
+    pub total_free: u64,
+    /// Committed collateral summed over all buckets
+    pub total_committed: u64,
+    /// Equity summed over the buckets with a known mark
+    pub total_equity: i64,
+}
+
+#[cfg(feature = "std")]
+impl AccountSnapshot {
+    pub const CURRENT_VERSION: u8 = 1;
+}
+
 impl CrossMarginAccountV1 {
     pub const CURRENT_VERSION: u8 = 2;
 
+    /// Build an indexer snapshot, valuing each bucket at the mark in `marks`
+    /// (`(market_id, mark_price)` pairs).
+    #[cfg(feature = "std")]
+    pub fn to_snapshot(&self, marks: &[(u64, u64)]) -> AccountSnapshot {
+        use crate::state::position::calculate_equity;
+
+        let buckets: Vec<BucketSnapshot> = self
+            .buckets
+            .iter()
+            .map(|b| {
+                let market_id = b.scope.market_id();
+                let equity =
+                    marks
+                        .iter()
+                        .find(|(id, _)| *id == market_id)
+                        .and_then(|&(_, mark)| {
+                            calculate_equity(b.committed, b.net_position, b.avg_entry_price, mark)
+                                .ok()
+                        });
+                BucketSnapshot {
+                    market_id,
+                    net_position: b.net_position,
+                    avg_entry_price: b.avg_entry_price,
+                    committed: b.committed,
+                    equity,
+                    open_bid_qty: b.open_bid_qty,
+                    open_ask_qty: b.open_ask_qty,
+                }
+            })
+            .collect();
+
+        AccountSnapshot {
+            version: AccountSnapshot::CURRENT_VERSION,
+            user: self.user,
+            total_free: self
+                .balances
+                .iter()
+                .fold(0u64, |acc, tb| acc.saturating_add(tb.amount)),
+            total_committed: buckets
+                .iter()
+                .fold(0u64, |acc, b| acc.saturating_add(b.committed)),
+            total_equity: buckets
+                .iter()
+                .filter_map(|b| b.equity)
+                .fold(0i64, |acc, e| acc.saturating_add(e)),
+            buckets,
+        }
+    }
+
     /// Convenience – locate (or lazily create) a free-collateral bucket for given mint.
     pub fn balance_for_mut(&mut self, mint: &Pubkey) -> &mut TokenBalance {
         if let Some(pos) = self.balances.iter().position(|tb| &tb.mint == mint) {
@@ -80,12 +190,143 @@ impl CrossMarginAccountV1 {
             self.balances.push(TokenBalance {
                 mint: *mint,
                 amount: 0,
-                _pad: [0; 16],
+                lifetime_deposited: 0,
+                _pad: [0; 8],
             });
             self.balances.last_mut().unwrap()
         }
     }
 
+    /// Credit free collateral for `mint`, creating the balance entry if needed.
+    pub fn deposit(&mut self, mint: &Pubkey, amount: u64) -> Result<(), ProgramError> {
+        let balance = self.balance_for_mut(mint);
+        let new_amount = balance.amount.checked_add(amount).ok_or_else(|| {
+            msg!("Error: Overflow crediting deposit");
+            ProgramError::ArithmeticOverflow
+        })?;
+        let new_lifetime = balance
+            .lifetime_deposited
+            .checked_add(amount)
+            .ok_or_else(|| {
+                msg!("Error: Overflow tracking lifetime deposits");
+                ProgramError::ArithmeticOverflow
+            })?;
+
+        balance.amount = new_amount;
+        balance.lifetime_deposited = new_lifetime;
+        msg!(
+            "Deposit: mint={} amount={} free={}",
+            mint,
+            amount,
+            new_amount
+        );
+        Ok(())
+    }
+
+    /// Debit free (uncommitted) collateral for `mint`.
+    pub fn withdraw(&mut self, mint: &Pubkey, amount: u64) -> Result<(), ProgramError> {
+        let balance = self.balances.iter_mut().find(|tb| &tb.mint == mint);
+        let free = balance.as_ref().map_or(0, |tb| tb.amount);
+        let balance = match balance {
+            Some(balance) if free >= amount => balance,
+            _ => {
+                msg!(
+                    "Error: Insufficient free collateral to withdraw: free={} requested={}",
+                    free,
+                    amount
+                );
+                return Err(ProgramError::InsufficientFunds);
+            }
+        };
+
+        balance.amount -= amount;
+        msg!(
+            "Withdraw: mint={} amount={} free={}",
+            mint,
+            amount,
+            balance.amount
+        );
+        Ok(())
+    }
+
+    /// Move `amount` of free `mint` collateral into the market's isolated bucket.
+    pub fn commit(
+        &mut self,
+        market_id: u64,
+        mint: &Pubkey,
+        amount: u64,
+    ) -> Result<(), ProgramError> {
+        let free = self.uncommitted_collateral(mint);
+        if free < amount {
+            msg!(
+                "Error: Insufficient free collateral to commit: free={} requested={}",
+                free,
+                amount
+            );
+            return Err(ProgramError::InsufficientFunds);
+        }
+
+        let bucket = self.bucket_for_mut(&MarginScope::MarketIsolated(market_id), mint);
+        bucket.committed = bucket.committed.checked_add(amount).ok_or_else(|| {
+            msg!("Error: Overflow committing collateral");
+            ProgramError::ArithmeticOverflow
+        })?;
+        self.balance_for_mut(mint).amount -= amount;
+
+        msg!(
+            "Commit: market={} mint={} amount={}",
+            market_id,
+            mint,
+            amount
+        );
+        Ok(())
+    }
+
+    /// Move `amount` of committed collateral from the market's isolated bucket back to the
+    /// free balance. The amount may not exceed what `calculate_uncommittable_amount` allows
+    /// at `mark_price`/`im_bps`, nor the bucket's committed collateral.
+    pub fn uncommit(
+        &mut self,
+        market_id: u64,
+        mint: &Pubkey,
+        amount: u64,
+        mark_price: u64,
+        im_bps: u16,
+    ) -> Result<(), ProgramError> {
+        let scope = MarginScope::MarketIsolated(market_id);
+        let bucket = self.bucket_for_view(&scope, mint).ok_or_else(|| {
+            msg!("Error: No margin bucket to uncommit from");
+            ProgramError::InvalidAccountData
+        })?;
+
+        let allowed = bucket
+            .calculate_uncommittable_amount(mark_price, im_bps)?
+            .min(bucket.committed);
+        if amount > allowed {
+            msg!(
+                "Error: Uncommit of {} exceeds uncommittable amount {}",
+                amount,
+                allowed
+            );
+            return Err(ProgramError::InsufficientFunds);
+        }
+
+        let balance = self.balance_for_mut(mint);
+        balance.amount = balance.amount.checked_add(amount).ok_or_else(|| {
+            msg!("Error: Overflow crediting uncommitted collateral");
+            ProgramError::ArithmeticOverflow
+        })?;
+        self.bucket_for_mut(&scope, mint).committed -= amount;
+
+        msg!(
+            "Uncommit: market={} mint={} amount={}",
+            market_id,
+            mint,
+            amount
+        );
+        Ok(())
+    }
+
     /// Update open order quantities when cancelling an order
     pub fn update_collateral_on_cancel(
         &mut self,
@@ -156,6 +397,14 @@ impl CrossMarginAccountV1 {
         }
     }
 
+    /// Drop every bucket that holds no collateral, position, or open orders.
+    /// Returns the number of buckets removed; surviving buckets keep their order.
+    pub fn prune_empty_buckets(&mut self) -> usize {
+        let before = self.buckets.len();
+        self.buckets.retain(|b| !b.is_empty());
+        before - self.buckets.len()
+    }
+
     /// Aggregate committed + free across all mints.
     pub fn total_collateral(&self, mint: &Pubkey) -> u64 {
         let free_sum: u64 = self
@@ -173,6 +422,75 @@ impl CrossMarginAccountV1 {
         free_sum.saturating_add(committed_sum)
     }
 
+    /// Verify account-wide solvency invariants, failing on the first violation:
+    /// - free plus committed collateral per mint must fit in a u64. This is only an overflow
+    ///   check: the account keeps no withdrawal total and committed collateral moves with
+    ///   realized PnL, so committed can't be bounded by net deposits. It catches corruption
+    ///   that `total_collateral` would otherwise hide by saturating.
+    /// - no bucket may have negative equity at its mark in `marks` (`(market_id, mark_price)`)
+    ///
+    /// Buckets without an open position need no mark.
+    pub fn check_invariants(&self, marks: &[(u64, u64)]) -> Result<(), ProgramError> {
+        use crate::state::position::calculate_equity;
+
+        for (i, bucket) in self.buckets.iter().enumerate() {
+            // Sum each mint once, at its first bucket
+            if !self.buckets[..i].iter().any(|b| b.mint == bucket.mint) {
+                let committed_sum = self.buckets[i..]
+                    .iter()
+                    .filter(|b| b.mint == bucket.mint)
+                    .try_fold(0u64, |acc, b| acc.checked_add(b.committed));
+                let free_sum = self
+                    .balances
+                    .iter()
+                    .filter(|tb| tb.mint == bucket.mint)
+                    .try_fold(0u64, |acc, tb| acc.checked_add(tb.amount));
+                if committed_sum
+                    .zip(free_sum)
+                    .and_then(|(c, f)| c.checked_add(f))
+                    .is_none()
+                {
+                    msg!(
+                        "Error: Invariant violated: total collateral for mint {} overflows u64",
+                        bucket.mint
+                    );
+                    return Err(ProgramError::InvalidAccountData);
+                }
+            }
+
+            if bucket.net_position == 0 {
+                continue;
+            }
+            let market_id = bucket.scope.market_id();
+            let (_, mark) = marks
+                .iter()
+                .find(|(id, _)| *id == market_id)
+                .ok_or_else(|| {
+                    msg!(
+                        "Error: Invariant check missing mark for market {}",
+                        market_id
+                    );
+                    ProgramError::InvalidArgument
+                })?;
+            let equity = calculate_equity(
+                bucket.committed,
+                bucket.net_position,
+                bucket.avg_entry_price,
+                *mark,
+            )?;
+            if equity < 0 {
+                msg!(
+                    "Error: Invariant violated: market {} bucket equity {} is negative",
+                    market_id,
+                    equity
+                );
+                return Err(ProgramError::InsufficientFunds);
+            }
+        }
+
+        Ok(())
+    }
+
     /// Available for new commitments: sum of free balances minus account-level reserved.
     pub fn uncommitted_collateral(&self, mint: &Pubkey) -> u64 {
         self.balances
@@ -196,6 +514,22 @@ impl CrossMarginAccountV1 {
     ) -> Result<(), solana_program::program_error::ProgramError> {
         // Find or create margin bucket for this market
         let market_scope = MarginScope::MarketIsolated(market_id);
+
+        // Margin for this market must be drawn from its base token; a bucket committed
+        // in any other mint is stale and must not back new orders.
+        if let Some(stale) = self
+            .buckets
+            .iter()
+            .find(|b| b.scope == market_scope && b.mint != market_state.base_token)
+        {
+            msg!(
+                "Error: Margin bucket mint {} does not match market base token {}",
+                stale.mint,
+                market_state.base_token
+            );
+            return Err(ProgramError::InvalidAccountData);
+        }
+
         let bucket = self.bucket_for_mut(&market_scope, &market_state.base_token);
 
         if is_liquidation {
@@ -235,7 +569,6 @@ impl CrossMarginAccountV1 {
         price: u64,
         mint: &Pubkey,
     ) -> Result<CmaFillResult, solana_program::program_error::ProgramError> {
-        use crate::state::position::{process_fill, Fill};
         use solana_program::{msg, program_error::ProgramError};
 
         // Find the market-specific bucket
@@ -251,70 +584,94 @@ impl CrossMarginAccountV1 {
                 ProgramError::InvalidAccountData
             })?;
 
-        // Create fill struct
-        let fill = Fill { side, qty, price };
+        bucket.apply_fill(side, qty, price)
+    }
 
-        // Process the fill
-        let fill_result = process_fill(bucket.net_position, bucket.avg_entry_price, &fill)?;
+    /// Preview `process_fill` without mutating the account. Only the affected bucket is cloned.
+    pub fn simulate_fill(
+        &self,
+        market_id: u64,
+        side: crate::state::order::OrderSide,
+        qty: u64,
+        price: u64,
+        mint: &Pubkey,
+    ) -> Result<CmaFillResult, solana_program::program_error::ProgramError> {
+        let mut bucket = self
+            .bucket_for_view(&MarginScope::MarketIsolated(market_id), mint)
+            .ok_or_else(|| {
+                msg!("Error: Market bucket not found for simulated fill");
+                ProgramError::InvalidAccountData
+            })?
+            .clone();
+
+        bucket.apply_fill(side, qty, price)
+    }
 
-        let old_net_position = bucket.net_position;
+    /// Estimate the result of closing every open position for `mint` at each market's last
+    /// mark price, without mutating the account. Each close is charged `taker_fee_bps` on
+    /// its notional.
+    pub fn simulate_close_all(
+        &self,
+        markets: &[(u64, crate::MarketStateV1)],
+        mint: &Pubkey,
+        taker_fee_bps: u16,
+    ) -> Result<CloseAllEstimate, ProgramError> {
+        use crate::state::order::OrderSide;
 
-        // Update bucket with results
-        bucket.net_position = fill_result.new_net_position;
-        bucket.avg_entry_price = fill_result.new_avg_entry_price;
+        let mut estimate = CloseAllEstimate::default();
+        for bucket in self
+            .buckets
+            .iter()
+            .filter(|b| &b.mint == mint && b.net_position != 0)
+        {
+            let market_id = bucket.scope.market_id();
+            let (_, market) = markets
+                .iter()
+                .find(|(id, _)| *id == market_id)
+                .ok_or_else(|| {
+                    msg!(
+                        "Error: Market {} not provided for close-all estimate",
+                        market_id
+                    );
+                    ProgramError::InvalidArgument
+                })?;
 
-        // Update open order quantities
-        match side {
-            crate::state::order::OrderSide::Bid => {
-                bucket.open_bid_qty = bucket.open_bid_qty.saturating_sub(qty);
-            }
-            crate::state::order::OrderSide::Ask => {
-                bucket.open_ask_qty = bucket.open_ask_qty.saturating_sub(qty);
-            }
-        }
+            let side = if bucket.net_position > 0 {
+                OrderSide::Ask
+            } else {
+                OrderSide::Bid
+            };
+            let qty = bucket.net_position.unsigned_abs();
+            let mark_price = market.last_mark_price;
 
-        // Handle realized PnL if any
-        if fill_result.realized_pnl != 0 {
-            // Apply realized PnL to committed capital
-            if fill_result.realized_pnl > 0 {
-                // Profit increases committed capital
-                bucket.committed = bucket
-                    .committed
-                    .checked_add(fill_result.realized_pnl as u64)
+            let fill_result = bucket.clone().apply_fill(side, qty, mark_price)?;
+            let notional = mul_qty_px_to_notional(qty, mark_price)?;
+            let fee = apply_bps(notional, taker_fee_bps)?;
+
+            estimate.realized_pnl = estimate
+                .realized_pnl
+                .checked_add(fill_result.realized_pnl_banked)
+                .ok_or_else(|| {
+                    msg!("Error: Overflow summing close-all realized PnL");
+                    ProgramError::ArithmeticOverflow
+                })?;
+            estimate.close_notional =
+                estimate
+                    .close_notional
+                    .checked_add(notional)
                     .ok_or_else(|| {
-                        msg!("Error: Overflow adding realized profit");
+                        msg!("Error: Overflow summing close-all notional");
                         ProgramError::ArithmeticOverflow
                     })?;
-            } else {
-                // Loss reduces committed capital
-                let loss = (-fill_result.realized_pnl) as u64;
-                if bucket.committed < loss {
-                    msg!(
-                        "Error: Warning: Loss {} exceeds committed capital. Current: {}",
-                        loss,
-                        bucket.committed
-                    );
-                    bucket.committed = 0;
-                } else {
-                    bucket.committed = bucket.committed.saturating_sub(loss);
-                }
-            }
-
-            msg!(
-                "Fill processed: realized PnL = {}, new committed = {}",
-                fill_result.realized_pnl,
-                bucket.committed
-            );
+            estimate.estimated_fees =
+                estimate.estimated_fees.checked_add(fee).ok_or_else(|| {
+                    msg!("Error: Overflow summing close-all fees");
+                    ProgramError::ArithmeticOverflow
+                })?;
+            estimate.positions_closed += 1;
         }
 
-        msg!("Fill processed: new net_position={}, avg_entry_price={}, open_bid_qty={}, open_ask_qty={}", 
-             bucket.net_position, bucket.avg_entry_price, bucket.open_bid_qty, bucket.open_ask_qty);
-
-        Ok(CmaFillResult {
-            new_net_position: bucket.net_position,
-            old_net_position,
-            realized_pnl_banked: fill_result.realized_pnl,
-        })
+        Ok(estimate)
     }
 
     /// Calculate equity for a margin bucket given a mark price
@@ -355,6 +712,22 @@ impl CrossMarginAccountV1 {
             None => Ok(0),
         }
     }
+
+    /// Combined signed position for a market across every margin scope (isolated and cross)
+    pub fn net_position_all_scopes(
+        &self,
+        market_id: u64,
+        mint: &Pubkey,
+    ) -> Result<i64, solana_program::program_error::ProgramError> {
+        self.buckets
+            .iter()
+            .filter(|b| b.scope.market_id() == market_id && &b.mint == mint)
+            .try_fold(0i64, |total, b| total.checked_add(b.net_position))
+            .ok_or_else(|| {
+                msg!("Error: Overflow summing net position across scopes");
+                ProgramError::ArithmeticOverflow
+            })
+    }
 }
 
 impl MarginBucket {
@@ -369,6 +742,111 @@ impl MarginBucket {
         self.net_position != 0 || self.open_bid_qty != 0 || self.open_ask_qty != 0
     }
 
+    /// Check that an order of `qty` on `side` only reduces the net position: bids must
+    /// close part or all of a short, asks part or all of a long, never past flat.
+    pub fn validate_reduce_only(
+        &self,
+        side: crate::state::order::OrderSide,
+        qty: u64,
+    ) -> Result<(), ProgramError> {
+        let reducible = match side {
+            crate::state::order::OrderSide::Bid if self.net_position < 0 => {
+                self.net_position.unsigned_abs()
+            }
+            crate::state::order::OrderSide::Ask if self.net_position > 0 => {
+                self.net_position.unsigned_abs()
+            }
+            _ => 0,
+        };
+
+        if qty > reducible {
+            msg!(
+                "Error: Reduce-only {:?} of {} exceeds reducible position {} (net {})",
+                side,
+                qty,
+                reducible,
+                self.net_position
+            );
+            return Err(ProgramError::InvalidArgument);
+        }
+        Ok(())
+    }
+
+    /// Apply a fill to this bucket's position, open order quantities, and committed capital
+    pub fn apply_fill(
+        &mut self,
+        side: crate::state::order::OrderSide,
+        qty: u64,
+        price: u64,
+    ) -> Result<CmaFillResult, solana_program::program_error::ProgramError> {
+        use crate::state::position::{process_fill, Fill};
+
+        // Create fill struct
+        let fill = Fill { side, qty, price };
+
+        // Process the fill
+        let fill_result = process_fill(self.net_position, self.avg_entry_price, &fill)?;
+
+        let old_net_position = self.net_position;
+
+        // Update bucket with results
+        self.net_position = fill_result.new_net_position;
+        self.avg_entry_price = fill_result.new_avg_entry_price;
+
+        // Update open order quantities
+        match side {
+            crate::state::order::OrderSide::Bid => {
+                self.open_bid_qty = self.open_bid_qty.saturating_sub(qty);
+            }
+            crate::state::order::OrderSide::Ask => {
+                self.open_ask_qty = self.open_ask_qty.saturating_sub(qty);
+            }
+        }
+
+        // Handle realized PnL if any
+        if fill_result.realized_pnl != 0 {
+            // Apply realized PnL to committed capital
+            if fill_result.realized_pnl > 0 {
+                // Profit increases committed capital
+                self.committed = self
+                    .committed
+                    .checked_add(fill_result.realized_pnl as u64)
+                    .ok_or_else(|| {
+                        msg!("Error: Overflow adding realized profit");
+                        ProgramError::ArithmeticOverflow
+                    })?;
+            } else {
+                // Loss reduces committed capital
+                let loss = (-fill_result.realized_pnl) as u64;
+                if self.committed < loss {
+                    msg!(
+                        "Error: Warning: Loss {} exceeds committed capital. Current: {}",
+                        loss,
+                        self.committed
+                    );
+                    self.committed = 0;
+                } else {
+                    self.committed = self.committed.saturating_sub(loss);
+                }
+            }
+
+            msg!(
+                "Fill processed: realized PnL = {}, new committed = {}",
+                fill_result.realized_pnl,
+                self.committed
+            );
+        }
+
+        msg!("Fill processed: new net_position={}, avg_entry_price={}, open_bid_qty={}, open_ask_qty={}", 
+             self.net_position, self.avg_entry_price, self.open_bid_qty, self.open_ask_qty);
+
+        Ok(CmaFillResult {
+            new_net_position: self.net_position,
+            old_net_position,
+            realized_pnl_banked: fill_result.realized_pnl,
+        })
+    }
+
     pub fn validate_and_update_open_order_qty(
         &mut self,
         market_state: &crate::MarketStateV1,
@@ -460,15 +938,22 @@ impl MarginBucket {
         usage: u64,
     ) -> Result<u64, solana_program::program_error::ProgramError> {
         let effective_im_bps = mkt_im_bps.max(self.user_set_im_bps);
-        let notional_value = mul_qty_px_to_notional(usage, last_mark_price)?;
+        let notional_value = mul_qty_px_to_notional_round_up(usage, last_mark_price)?;
 
-        notional_value
-            .checked_mul(effective_im_bps as u64)
-            .and_then(|x| x.checked_div(10000))
-            .ok_or_else(|| {
-                msg!("Error: Overflow calculating required margin");
-                ProgramError::ArithmeticOverflow
-            })
+        apply_bps(notional_value, effective_im_bps)
+            .inspect_err(|_| msg!("Error: Overflow calculating required margin"))
+    }
+
+    /// Required margin for this bucket if an order of `add_qty` on `side` were added
+    /// on top of the current position and resting orders.
+    pub fn required_margin_after(
+        &self,
+        market_state: &crate::MarketStateV1,
+        side: crate::state::order::OrderSide,
+        add_qty: u64,
+    ) -> Result<u64, solana_program::program_error::ProgramError> {
+        let usage = self.worst_case_direction_add(side, add_qty)?;
+        self.calc_required_margin_mkt(market_state, usage)
     }
 
     pub fn worst_case_direction_add(
@@ -496,17 +981,26 @@ impl MarginBucket {
         })
     }
 
+    /// Whether the bucket's net position is long, short or flat
+    pub fn direction(&self) -> PositionDirection {
+        PositionDirection::from_net(self.net_position)
+    }
+
     /// Calculate the worst case position magnitude considering open orders
     /// Returns max(net_position + open_bid_qty, -net_position + open_ask_qty)
     pub fn worst_case_position(&self) -> u64 {
-        // Worst case long: all bids fill
-        let worst_long = self.net_position.saturating_add(self.open_bid_qty as i64);
-
-        // Worst case short: all asks fill (position becomes more negative)
-        let worst_short = (-self.net_position).saturating_add(self.open_ask_qty as i64);
-
-        // Return the larger absolute value
-        worst_long.abs().max(worst_short.abs()) as u64
+        let size = self.net_position.unsigned_abs();
+        match self.direction() {
+            // Bids grow the long; asks can at most flip it into a short of asks - size
+            PositionDirection::Long => size
+                .saturating_add(self.open_bid_qty)
+                .max(self.open_ask_qty.saturating_sub(size)),
+            // Asks grow the short; bids can at most flip it into a long of bids - size
+            PositionDirection::Short => size
+                .saturating_add(self.open_ask_qty)
+                .max(self.open_bid_qty.saturating_sub(size)),
+            PositionDirection::Flat => self.open_bid_qty.max(self.open_ask_qty),
+        }
     }
 
     /// Calculate the maximum amount that can be uncommitted from this bucket
@@ -516,20 +1010,15 @@ impl MarginBucket {
         last_mark_price: u64,
         im_bps: u16,
     ) -> Result<u64, solana_program::program_error::ProgramError> {
-        use solana_program::{msg, program_error::ProgramError};
+        use solana_program::msg;
 
         let worst_case_pos = self.worst_case_position();
 
         // Calculate required collateral: worst_case_position * last_mark_price * im_bps / 10000
-        let notional = mul_qty_px_to_notional(worst_case_pos, last_mark_price)?;
+        let notional = mul_qty_px_to_notional_round_up(worst_case_pos, last_mark_price)?;
 
-        let required_collateral = notional
-            .checked_mul(im_bps as u64)
-            .and_then(|x| x.checked_div(10000))
-            .ok_or_else(|| {
-                msg!("Error: Overflow calculating required collateral");
-                ProgramError::ArithmeticOverflow
-            })?;
+        let required_collateral = apply_bps(notional, im_bps)
+            .inspect_err(|_| msg!("Error: Overflow calculating required collateral"))?;
 
         let equity = self.calc_equity(last_mark_price)?;
 
@@ -549,18 +1038,17 @@ impl MarginBucket {
         &self,
         last_mark_price: u64,
     ) -> Result<u64, solana_program::program_error::ProgramError> {
+        // A flat bucket has no PnL, its equity is just the committed collateral
+        if self.direction() == PositionDirection::Flat {
+            return Ok(self.committed);
+        }
+
         // Calculate unrealized PnL (scaled to collateral decimals)
-        let unrealized_pnl = if self.net_position != 0 {
-            let price_diff = if self.net_position > 0 {
-                last_mark_price as i64 - self.avg_entry_price as i64
-            } else {
-                self.avg_entry_price as i64 - last_mark_price as i64
-            };
-            // Use helper to scale qty*price_diff / 1e8
-            mul_qty_px_signed(self.net_position.abs(), price_diff)?
-        } else {
-            0
-        };
+        let unrealized_pnl = crate::state::position::unrealized_pnl(
+            self.net_position,
+            self.avg_entry_price,
+            last_mark_price,
+        )?;
 
         // Calculate equity = committed + unrealized_pnl
         let equity = if unrealized_pnl >= 0 {
@@ -577,6 +1065,136 @@ impl MarginBucket {
         Ok(equity)
     }
 
+    /// True when equity at the market's last mark is below maintenance margin on the
+    /// worst-case position. Empty buckets are never liquidatable.
+    pub fn is_liquidatable(&self, market: &crate::MarketStateV1) -> Result<bool, ProgramError> {
+        if self.is_empty() {
+            return Ok(false);
+        }
+        let notional =
+            mul_qty_px_to_notional_round_up(self.worst_case_position(), market.last_mark_price)?;
+        let maintenance_margin = apply_bps(notional, market.mm_bps)
+            .inspect_err(|_| msg!("Error: Overflow calculating maintenance margin"))?;
+        Ok(self.calc_equity(market.last_mark_price)? < maintenance_margin)
+    }
+
+    /// Smallest quantity to close at the market's last mark so that equity covers
+    /// `target_im_bps` margin (via `calc_required_margin`) on the remaining position.
+    /// Closing at mark leaves equity unchanged, and resting orders are assumed to be
+    /// cancelled first. Returns 0 if the bucket is already healthy.
+    pub fn liquidation_qty_to_health(
+        &self,
+        market: &crate::MarketStateV1,
+        target_im_bps: u16,
+    ) -> Result<u64, ProgramError> {
+        let mark = market.last_mark_price;
+        let position = self.net_position.unsigned_abs();
+        let equity = self.calc_equity(mark)?;
+
+        if self.calc_required_margin(mark, target_im_bps, position)? <= equity {
+            return Ok(0);
+        }
+
+        // Largest remaining size whose requirement fits within equity; requirement is
+        // monotone in size, so binary search over [0, position)
+        let (mut lo, mut hi) = (0u64, position - 1);
+        while lo < hi {
+            let mid = lo + (hi - lo).div_ceil(2);
+            if self.calc_required_margin(mark, target_im_bps, mid)? <= equity {
+                lo = mid;
+            } else {
+                hi = mid - 1;
+            }
+        }
+        Ok(position - lo)
+    }
+
+    /// Unrealized PnL at `mark_price` as signed bps of committed collateral (0 if nothing committed)
+    pub fn return_bps(&self, mark_price: u64) -> Result<i64, ProgramError> {
+        if self.committed == 0 {
+            return Ok(0);
+        }
+        let pnl = crate::state::position::unrealized_pnl(
+            self.net_position,
+            self.avg_entry_price,
+            mark_price,
+        )?;
+        let bps = pnl as i128 * crate::state::math::BPS_DENOM as i128 / self.committed as i128;
+        i64::try_from(bps).map_err(|_| {
+            msg!("Error: Overflow calculating return bps");
+            ProgramError::ArithmeticOverflow
+        })
+    }
+
+    /// Auto-deleverage ranking: `return_bps × leverage`, where leverage is position notional
+    /// over equity. Higher scores are deleveraged first. Flat buckets score 0 and buckets
+    /// with no equity left rank last.
+    pub fn adl_score(&self, mark_price: u64) -> Result<i64, ProgramError> {
+        if self.net_position == 0 {
+            return Ok(0);
+        }
+        let equity = self.calc_equity(mark_price)?;
+        if equity == 0 {
+            return Ok(i64::MIN);
+        }
+
+        let leverage_bps = self.effective_leverage_bps(mark_price)?;
+        let score = self.return_bps(mark_price)? as i128 * leverage_bps as i128
+            / crate::state::math::BPS_DENOM as i128;
+        Ok(score.clamp(i64::MIN as i128, i64::MAX as i128) as i64)
+    }
+
+    /// Position notional at `mark_price` over equity, in bps (10000 = 1x). Returns 0 with
+    /// no position and saturates at `u64::MAX` when equity is zero or the ratio overflows.
+    pub fn effective_leverage_bps(&self, mark_price: u64) -> Result<u64, ProgramError> {
+        if self.net_position == 0 {
+            return Ok(0);
+        }
+        let notional = mul_qty_px_to_notional(self.net_position.unsigned_abs(), mark_price)?;
+        let equity = self.calc_equity(mark_price)?;
+        if equity == 0 {
+            return Ok(u64::MAX);
+        }
+        let leverage = notional as u128 * crate::state::math::BPS_DENOM as u128 / equity as u128;
+        Ok(u64::try_from(leverage).unwrap_or(u64::MAX))
+    }
+
+    /// Apply a `SetLeverage` target by storing the equivalent initial margin in
+    /// `user_set_im_bps`. `target_leverage_bps` is leverage in basis points (10_000 = 1x),
+    /// so the margin is `1e8 / target`, rounded up so the resulting leverage never exceeds
+    /// the target. Targets below 1x, or that imply margin below the market's maintenance,
+    /// are rejected.
+    pub fn set_target_leverage(
+        &mut self,
+        target_leverage_bps: u32,
+        market_mm_bps: u16,
+    ) -> Result<(), ProgramError> {
+        use crate::state::math::BPS_DENOM;
+
+        if (target_leverage_bps as u64) < BPS_DENOM {
+            msg!(
+                "Error: Target leverage {} bps is below 1x",
+                target_leverage_bps
+            );
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        // target >= 1x keeps the margin at or below 10_000 bps
+        let im_bps = (BPS_DENOM * BPS_DENOM).div_ceil(target_leverage_bps as u64) as u16;
+        if im_bps < market_mm_bps {
+            msg!(
+                "Error: Target leverage {} implies IM {} bps below maintenance {} bps",
+                target_leverage_bps,
+                im_bps,
+                market_mm_bps
+            );
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        self.user_set_im_bps = im_bps;
+        Ok(())
+    }
+
     /// Default constructor for MarginBucket
     pub fn new(scope: MarginScope, mint: Pubkey) -> Self {
         Self {
@@ -6,6 +6,7 @@ mod tests {
     use crate::MarginBucket;
     use crate::MarginScope;
     use crate::MarketStateV1;
+    use crate::PositionDirection;
     use solana_program::{program_error::ProgramError, pubkey::Pubkey};
 
     fn create_test_market_state() -> MarketStateV1 {
@@ -191,6 +192,29 @@ mod tests {
         assert_eq!(result.unwrap_err(), ProgramError::InsufficientFunds);
     }
 
+    #[test]
+    fn test_validate_and_update_collateral_rejects_bucket_mint_mismatch() {
+        let mut cma = create_test_cma();
+        let market_state = create_test_market_state();
+
+        // Stale bucket committed in a token other than the market's base token
+        let mut bucket = MarginBucket::new(MarginScope::MarketIsolated(1), Pubkey::new_unique());
+        bucket.committed = 100_000_000_000;
+        cma.buckets.push(bucket);
+
+        let result = cma.validate_and_update_collateral(
+            &market_state,
+            1, // market_id
+            OrderSide::Bid,
+            5_000_000, // 5.0 tokens
+            false,     // not a liquidation
+        );
+
+        assert_eq!(result.unwrap_err(), ProgramError::InvalidAccountData);
+        assert_eq!(cma.buckets.len(), 1);
+        assert_eq!(cma.buckets[0].open_bid_qty, 0);
+    }
+
     #[test]
     fn test_validate_and_update_collateral_multiple_orders_same_side() {
         let mut cma = create_test_cma();
@@ -332,6 +356,310 @@ mod tests {
         assert!(open_orders_bucket.is_open());
     }
 
+    #[test]
+    fn test_prune_empty_buckets() {
+        let mut cma = create_test_cma();
+        let mint = Pubkey::new_unique();
+
+        let mut committed = MarginBucket::new(MarginScope::MarketIsolated(1), mint);
+        committed.committed = 1_000;
+        let mut positioned = MarginBucket::new(MarginScope::MarketIsolated(3), mint);
+        positioned.net_position = -500;
+        positioned.avg_entry_price = 50_000;
+        let mut resting = MarginBucket::new(MarginScope::MarketIsolated(5), mint);
+        resting.open_ask_qty = 200;
+
+        cma.buckets = vec![
+            MarginBucket::new(MarginScope::MarketIsolated(0), mint),
+            committed.clone(),
+            MarginBucket::new(MarginScope::MarketIsolated(2), mint),
+            positioned.clone(),
+            MarginBucket::new(MarginScope::MarketIsolated(4), mint),
+            resting.clone(),
+        ];
+
+        assert_eq!(cma.prune_empty_buckets(), 3);
+        assert_eq!(cma.buckets, vec![committed, positioned, resting]);
+
+        // Nothing left to prune
+        assert_eq!(cma.prune_empty_buckets(), 0);
+        assert_eq!(cma.buckets.len(), 3);
+    }
+
+    #[test]
+    fn test_deposit_credits_free_balance() {
+        let mut cma = create_test_cma();
+        let mint = Pubkey::new_unique();
+
+        cma.deposit(&mint, 1_000_000).unwrap();
+        cma.deposit(&mint, 500_000).unwrap();
+
+        assert_eq!(cma.balances.len(), 1);
+        assert_eq!(cma.balances[0].amount, 1_500_000);
+        assert_eq!(cma.balances[0].lifetime_deposited, 1_500_000);
+        assert_eq!(cma.uncommitted_collateral(&mint), 1_500_000);
+    }
+
+    #[test]
+    fn test_deposit_overflow_leaves_balance_unchanged() {
+        let mut cma = create_test_cma();
+        let mint = Pubkey::new_unique();
+        cma.deposit(&mint, u64::MAX - 10).unwrap();
+
+        assert_eq!(
+            cma.deposit(&mint, 11).unwrap_err(),
+            ProgramError::ArithmeticOverflow
+        );
+        assert_eq!(cma.balances[0].amount, u64::MAX - 10);
+        assert_eq!(cma.balances[0].lifetime_deposited, u64::MAX - 10);
+
+        // Lifetime total overflows even when the free balance would not
+        cma.withdraw(&mint, 100).unwrap();
+        assert_eq!(
+            cma.deposit(&mint, 50).unwrap_err(),
+            ProgramError::ArithmeticOverflow
+        );
+        assert_eq!(cma.balances[0].amount, u64::MAX - 110);
+    }
+
+    #[test]
+    fn test_commit_moves_free_to_bucket() {
+        let mut cma = create_test_cma();
+        let mint = Pubkey::new_unique();
+        cma.deposit(&mint, 1_000_000).unwrap();
+
+        cma.commit(1, &mint, 600_000).unwrap();
+        assert_eq!(cma.uncommitted_collateral(&mint), 400_000);
+        let bucket = cma
+            .bucket_for_view(&MarginScope::MarketIsolated(1), &mint)
+            .unwrap();
+        assert_eq!(bucket.committed, 600_000);
+        assert_eq!(cma.total_collateral(&mint), 1_000_000);
+
+        assert_eq!(
+            cma.commit(1, &mint, 400_001).unwrap_err(),
+            ProgramError::InsufficientFunds
+        );
+        assert_eq!(cma.uncommitted_collateral(&mint), 400_000);
+    }
+
+    #[test]
+    fn test_uncommit_respects_uncommittable_boundary() {
+        let mut cma = create_test_cma();
+        let mint = Pubkey::new_unique();
+        let mark = 100_000_000; // $100
+        let im_bps = 1_000; // 10%
+
+        cma.deposit(&mint, 300_000_000).unwrap();
+        cma.commit(1, &mint, 300_000_000).unwrap();
+        // Long 10 tokens at mark: $1000 notional needs $100 of margin, so $200 is free
+        let bucket = cma.bucket_for_mut(&MarginScope::MarketIsolated(1), &mint);
+        bucket.net_position = 1_000_000_000;
+        bucket.avg_entry_price = mark;
+
+        assert_eq!(
+            cma.uncommit(1, &mint, 200_000_001, mark, im_bps)
+                .unwrap_err(),
+            ProgramError::InsufficientFunds
+        );
+        cma.uncommit(1, &mint, 200_000_000, mark, im_bps).unwrap();
+        assert_eq!(cma.uncommitted_collateral(&mint), 200_000_000);
+        assert_eq!(
+            cma.bucket_for_view(&MarginScope::MarketIsolated(1), &mint)
+                .unwrap()
+                .committed,
+            100_000_000
+        );
+        assert_eq!(
+            cma.uncommit(1, &mint, 1, mark, im_bps).unwrap_err(),
+            ProgramError::InsufficientFunds
+        );
+
+        // Unrealized profit raises equity but only committed funds can be returned
+        cma.bucket_for_mut(&MarginScope::MarketIsolated(1), &mint)
+            .net_position = 0;
+        cma.uncommit(1, &mint, 100_000_000, mark, im_bps).unwrap();
+        assert_eq!(cma.uncommitted_collateral(&mint), 300_000_000);
+
+        assert_eq!(
+            cma.uncommit(2, &mint, 1, mark, im_bps).unwrap_err(),
+            ProgramError::InvalidAccountData
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_snapshot_equity_matches_bucket_equity() {
+        let mut cma = create_test_cma();
+        let mint = Pubkey::new_unique();
+        cma.deposit(&mint, 5_000_000).unwrap();
+
+        let long = cma.bucket_for_mut(&MarginScope::MarketIsolated(1), &mint);
+        long.committed = 100_000_000;
+        long.net_position = 200_000_000; // long 2
+        long.avg_entry_price = 50_000_000;
+        long.open_bid_qty = 10_000_000;
+        let short = cma.bucket_for_mut(&MarginScope::MarketIsolated(2), &mint);
+        short.committed = 40_000_000;
+        short.net_position = -100_000_000; // short 1
+        short.avg_entry_price = 20_000_000;
+        short.open_ask_qty = 30_000_000;
+        cma.bucket_for_mut(&MarginScope::MarketIsolated(3), &mint)
+            .committed = 7_000_000;
+
+        let marks = [(1, 55_000_000), (2, 26_000_000)];
+        let snap = cma.to_snapshot(&marks);
+
+        assert_eq!(snap.version, crate::AccountSnapshot::CURRENT_VERSION);
+        assert_eq!(snap.user, cma.user);
+        assert_eq!(snap.buckets.len(), 3);
+        for (market_id, mark) in marks {
+            let b = snap
+                .buckets
+                .iter()
+                .find(|b| b.market_id == market_id)
+                .unwrap();
+            assert_eq!(
+                b.equity,
+                Some(cma.calculate_bucket_equity(market_id, &mint, mark).unwrap())
+            );
+        }
+        assert_eq!(snap.buckets[0].equity, Some(110_000_000));
+        assert_eq!(snap.buckets[1].equity, Some(34_000_000));
+        assert_eq!(snap.buckets[0].open_bid_qty, 10_000_000);
+        assert_eq!(snap.buckets[1].open_ask_qty, 30_000_000);
+        // No mark supplied for market 3
+        assert_eq!(snap.buckets[2].equity, None);
+
+        assert_eq!(snap.total_free, 5_000_000);
+        assert_eq!(snap.total_committed, 147_000_000);
+        assert_eq!(snap.total_equity, 144_000_000);
+    }
+
+    #[test]
+    fn test_check_invariants_healthy_account() {
+        let mut cma = create_test_cma();
+        let mint = Pubkey::new_unique();
+        cma.deposit(&mint, 5_000_000).unwrap();
+        let bucket = cma.bucket_for_mut(&MarginScope::MarketIsolated(1), &mint);
+        bucket.committed = 10_000_000;
+        bucket.net_position = 100_000_000; // long 1 @ $50
+        bucket.avg_entry_price = 50_000_000;
+        // Flat bucket needs no mark
+        cma.bucket_for_mut(&MarginScope::MarketIsolated(2), &mint)
+            .committed = 1_000_000;
+
+        assert!(cma.check_invariants(&[(1, 45_000_000)]).is_ok());
+        // Exactly wiped out is still solvent
+        assert!(cma.check_invariants(&[(1, 40_000_000)]).is_ok());
+        assert_eq!(
+            cma.check_invariants(&[]).unwrap_err(),
+            ProgramError::InvalidArgument
+        );
+    }
+
+    #[test]
+    fn test_check_invariants_rejects_negative_equity_bucket() {
+        let mut cma = create_test_cma();
+        let mint = Pubkey::new_unique();
+        let bucket = cma.bucket_for_mut(&MarginScope::MarketIsolated(1), &mint);
+        bucket.committed = 10_000_000;
+        bucket.net_position = -100_000_000; // short 1 @ $50
+        bucket.avg_entry_price = 50_000_000;
+
+        assert_eq!(
+            cma.check_invariants(&[(1, 60_000_001)]).unwrap_err(),
+            ProgramError::InsufficientFunds
+        );
+    }
+
+    #[test]
+    fn test_check_invariants_rejects_overflowing_total_collateral() {
+        let mut cma = create_test_cma();
+        let mint = Pubkey::new_unique();
+        cma.deposit(&mint, 1).unwrap();
+        cma.bucket_for_mut(&MarginScope::MarketIsolated(1), &mint)
+            .committed = u64::MAX;
+
+        // total_collateral saturates and hides the corruption
+        assert_eq!(cma.total_collateral(&mint), u64::MAX);
+        assert_eq!(
+            cma.check_invariants(&[]).unwrap_err(),
+            ProgramError::InvalidAccountData
+        );
+
+        // Overflow split across two buckets of the same mint is caught too
+        let mut cma = create_test_cma();
+        cma.bucket_for_mut(&MarginScope::MarketIsolated(1), &mint)
+            .committed = u64::MAX / 2 + 1;
+        cma.bucket_for_mut(&MarginScope::MarketIsolated(2), &mint)
+            .committed = u64::MAX / 2 + 1;
+        assert_eq!(
+            cma.check_invariants(&[]).unwrap_err(),
+            ProgramError::InvalidAccountData
+        );
+    }
+
+    #[test]
+    fn test_withdraw_within_balance() {
+        let mut cma = create_test_cma();
+        let mint = Pubkey::new_unique();
+        cma.deposit(&mint, 1_000_000).unwrap();
+
+        cma.withdraw(&mint, 400_000).unwrap();
+        assert_eq!(cma.uncommitted_collateral(&mint), 600_000);
+        // Lifetime deposits are unaffected by withdrawals
+        assert_eq!(cma.balances[0].lifetime_deposited, 1_000_000);
+
+        cma.withdraw(&mint, 600_000).unwrap();
+        assert_eq!(cma.uncommitted_collateral(&mint), 0);
+    }
+
+    #[test]
+    fn test_withdraw_exceeding_balance() {
+        let mut cma = create_test_cma();
+        let mint = Pubkey::new_unique();
+        cma.deposit(&mint, 1_000_000).unwrap();
+        // Committed collateral is not withdrawable
+        cma.bucket_for_mut(&MarginScope::MarketIsolated(1), &mint)
+            .committed = 5_000_000;
+
+        let result = cma.withdraw(&mint, 1_000_001);
+        assert_eq!(result.unwrap_err(), ProgramError::InsufficientFunds);
+        assert_eq!(cma.uncommitted_collateral(&mint), 1_000_000);
+
+        // Unknown mint has no free balance and is not created
+        let other = Pubkey::new_unique();
+        assert_eq!(
+            cma.withdraw(&other, 1).unwrap_err(),
+            ProgramError::InsufficientFunds
+        );
+        assert_eq!(cma.balances.len(), 1);
+    }
+
+    #[test]
+    fn test_net_position_all_scopes() {
+        let mut cma = create_test_cma();
+        let mint = Pubkey::new_unique();
+
+        cma.bucket_for_mut(&MarginScope::MarketIsolated(1), &mint)
+            .net_position = 300;
+        cma.bucket_for_mut(&MarginScope::FullCross(1), &mint)
+            .net_position = -100;
+        // Other markets and mints are excluded
+        cma.bucket_for_mut(&MarginScope::FullCross(2), &mint)
+            .net_position = 50;
+        cma.bucket_for_mut(&MarginScope::MarketIsolated(1), &Pubkey::new_unique())
+            .net_position = 7;
+
+        assert_eq!(cma.net_position_all_scopes(1, &mint).unwrap(), 200);
+        assert_eq!(cma.net_position_all_scopes(2, &mint).unwrap(), 50);
+        assert_eq!(cma.net_position_all_scopes(3, &mint).unwrap(), 0);
+
+        // Isolated-only query is unchanged
+        assert_eq!(cma.net_position(1, &mint).unwrap(), 300);
+    }
+
     #[test]
     fn test_update_collateral_on_cancel_bid_order() {
         let mint = Pubkey::new_unique();
@@ -600,6 +928,91 @@ mod tests {
         assert_eq!(cma.buckets[0].committed, 1_000_000);
     }
 
+    #[test]
+    fn test_simulate_fill_matches_process_fill() {
+        use borsh::BorshSerialize;
+
+        let mut cma = create_test_cma();
+        let mint = Pubkey::new_unique();
+        cma.buckets.push(MarginBucket {
+            scope: MarginScope::MarketIsolated(1),
+            mint,
+            committed: 1_000_000,
+            net_position: 100_000_000,
+            open_bid_qty: 0,
+            open_ask_qty: 150_000_000,
+            avg_entry_price: 50_000,
+            user_set_im_bps: 0,
+            _pad: [0; 32],
+        });
+        let before = cma.try_to_vec().unwrap();
+
+        // Flip from 1 long to 0.5 short at a profit
+        let simulated = cma
+            .simulate_fill(1, OrderSide::Ask, 150_000_000, 52_000, &mint)
+            .unwrap();
+        assert_eq!(cma.try_to_vec().unwrap(), before);
+
+        let processed = cma
+            .process_fill(1, OrderSide::Ask, 150_000_000, 52_000, &mint)
+            .unwrap();
+        assert_eq!(simulated, processed);
+        assert_eq!(processed.old_net_position, 100_000_000);
+        assert_eq!(processed.new_net_position, -50_000_000);
+        assert_eq!(processed.realized_pnl_banked, 2_000);
+
+        // Missing bucket errors the same way as process_fill
+        assert_eq!(
+            cma.simulate_fill(2, OrderSide::Bid, 1, 1, &mint)
+                .unwrap_err(),
+            ProgramError::InvalidAccountData
+        );
+    }
+
+    #[test]
+    fn test_simulate_close_all_sums_winning_and_losing_positions() {
+        let mut cma = create_test_cma();
+        let mint = Pubkey::new_unique();
+
+        // Winning long in market 1: entry 90_000, mark 100_000
+        let long_bucket = cma.bucket_for_mut(&MarginScope::MarketIsolated(1), &mint);
+        long_bucket.committed = 50_000;
+        long_bucket.net_position = 100_000_000;
+        long_bucket.avg_entry_price = 90_000;
+
+        // Losing short in market 2: entry 96_000, mark 100_000
+        let short_bucket = cma.bucket_for_mut(&MarginScope::MarketIsolated(2), &mint);
+        short_bucket.committed = 50_000;
+        short_bucket.net_position = -100_000_000;
+        short_bucket.avg_entry_price = 96_000;
+
+        // Flat bucket is ignored
+        cma.bucket_for_mut(&MarginScope::MarketIsolated(3), &mint)
+            .committed = 10_000;
+
+        let markets = vec![
+            (1, create_test_market_state()),
+            (2, create_test_market_state()),
+        ];
+        let before = cma.buckets.clone();
+
+        // 5 bps taker fee on each 100_000 close
+        let estimate = cma.simulate_close_all(&markets, &mint, 5).unwrap();
+        assert_eq!(estimate.positions_closed, 2);
+        assert_eq!(estimate.realized_pnl, 10_000 - 4_000);
+        assert_eq!(estimate.close_notional, 200_000);
+        assert_eq!(estimate.estimated_fees, 50 + 50);
+        assert_eq!(cma.buckets, before);
+
+        // No fee rate, no fees
+        let estimate = cma.simulate_close_all(&markets, &mint, 0).unwrap();
+        assert_eq!(estimate.estimated_fees, 0);
+
+        // A missing market is an error rather than a silent skip
+        let result = cma.simulate_close_all(&markets[..1], &mint, 5);
+        assert_eq!(result, Err(ProgramError::InvalidArgument));
+    }
+
     #[test]
     fn test_process_fill_full_close_with_loss() {
         let mut cma = create_test_cma();
@@ -811,6 +1224,297 @@ mod tests {
         assert_eq!(bucket.avg_entry_price, 0);
     }
 
+    #[test]
+    fn test_validate_reduce_only() {
+        let mut bucket = MarginBucket::new(MarginScope::MarketIsolated(1), Pubkey::new_unique());
+
+        // Flat: nothing can reduce
+        assert_eq!(
+            bucket.validate_reduce_only(OrderSide::Bid, 1),
+            Err(ProgramError::InvalidArgument)
+        );
+        assert_eq!(
+            bucket.validate_reduce_only(OrderSide::Ask, 1),
+            Err(ProgramError::InvalidArgument)
+        );
+
+        // Short 100: bids reduce up to an exact close
+        bucket.net_position = -100;
+        assert!(bucket.validate_reduce_only(OrderSide::Bid, 40).is_ok());
+        assert!(bucket.validate_reduce_only(OrderSide::Bid, 100).is_ok());
+        assert_eq!(
+            bucket.validate_reduce_only(OrderSide::Bid, 101),
+            Err(ProgramError::InvalidArgument)
+        );
+        assert_eq!(
+            bucket.validate_reduce_only(OrderSide::Ask, 10),
+            Err(ProgramError::InvalidArgument)
+        );
+
+        // Long 100: asks reduce, bids increase
+        bucket.net_position = 100;
+        assert!(bucket.validate_reduce_only(OrderSide::Ask, 60).is_ok());
+        assert!(bucket.validate_reduce_only(OrderSide::Ask, 100).is_ok());
+        assert_eq!(
+            bucket.validate_reduce_only(OrderSide::Ask, 150),
+            Err(ProgramError::InvalidArgument)
+        );
+        assert_eq!(
+            bucket.validate_reduce_only(OrderSide::Bid, 10),
+            Err(ProgramError::InvalidArgument)
+        );
+    }
+
+    #[test]
+    fn test_is_liquidatable_at_maintenance_boundary() {
+        let mut market = create_test_market_state();
+        market.last_mark_price = 100_000_000; // $100
+
+        let mut bucket = MarginBucket::new(MarginScope::MarketIsolated(1), Pubkey::new_unique());
+        assert!(!bucket.is_liquidatable(&market).unwrap());
+
+        // Long 10 tokens at $100: $1000 notional, 5% maintenance = $50
+        bucket.net_position = 1_000_000_000;
+        bucket.avg_entry_price = 100_000_000;
+        bucket.committed = 50_000_000;
+        assert!(!bucket.is_liquidatable(&market).unwrap());
+
+        bucket.committed = 49_999_999;
+        assert!(bucket.is_liquidatable(&market).unwrap());
+
+        // Resting orders count towards the worst-case position
+        bucket.committed = 50_000_000;
+        bucket.open_bid_qty = 100_000_000;
+        assert!(bucket.is_liquidatable(&market).unwrap());
+    }
+
+    #[test]
+    fn test_liquidation_qty_to_health() {
+        let mut market = create_test_market_state();
+        market.last_mark_price = 100_000_000; // $100
+        let target_im_bps = 1_000; // 10%
+
+        // Long 10 tokens bought at $110, marked at $100 with $150 committed → $50 equity
+        let mut bucket = MarginBucket::new(MarginScope::MarketIsolated(1), Pubkey::new_unique());
+        bucket.net_position = 1_000_000_000;
+        bucket.avg_entry_price = 110_000_000;
+        bucket.committed = 150_000_000;
+
+        // $50 covers 10% margin on ~5 tokens; truncation in the requirement lets the
+        // remaining size go 9 lots past exactly 5 tokens
+        let qty = bucket
+            .liquidation_qty_to_health(&market, target_im_bps)
+            .unwrap();
+        assert_eq!(qty, 500_000_000 - 9);
+
+        let meets_target = |close_qty: u64| {
+            let mut post = bucket.clone();
+            post.apply_fill(OrderSide::Ask, close_qty, market.last_mark_price)
+                .unwrap();
+            let required = post
+                .calc_required_margin(
+                    market.last_mark_price,
+                    target_im_bps,
+                    post.net_position.unsigned_abs(),
+                )
+                .unwrap();
+            required <= post.calc_equity(market.last_mark_price).unwrap()
+        };
+        assert!(meets_target(qty));
+        assert!(!meets_target(qty - 1));
+
+        // A healthy bucket needs nothing closed
+        bucket.committed = 200_000_000;
+        assert_eq!(
+            bucket
+                .liquidation_qty_to_health(&market, target_im_bps)
+                .unwrap(),
+            0
+        );
+
+        // With no equity left all but the dust whose requirement truncates to zero goes
+        bucket.committed = 100_000_000;
+        assert_eq!(
+            bucket
+                .liquidation_qty_to_health(&market, target_im_bps)
+                .unwrap(),
+            1_000_000_000 - 9
+        );
+    }
+
+    #[test]
+    fn test_adl_score_ranks_profitable_levered_first() {
+        let mark = 110_000_000; // $110, up from $100 entry
+
+        // $1000 committed, long 5 tokens: +$50 → +5% at ~0.5x
+        let mut modest = MarginBucket::new(MarginScope::MarketIsolated(1), Pubkey::new_unique());
+        modest.committed = 1_000_000_000;
+        modest.net_position = 500_000_000;
+        modest.avg_entry_price = 100_000_000;
+
+        // $100 committed, long 10 tokens: +$100 → +100% at ~5.5x
+        let mut levered = MarginBucket::new(MarginScope::MarketIsolated(1), Pubkey::new_unique());
+        levered.committed = 100_000_000;
+        levered.net_position = 1_000_000_000;
+        levered.avg_entry_price = 100_000_000;
+
+        let modest_score = modest.adl_score(mark).unwrap();
+        let levered_score = levered.adl_score(mark).unwrap();
+        assert!(modest_score > 0);
+        assert!(levered_score > modest_score);
+
+        // Losing positions rank below winners, flat buckets score zero
+        let mut losing = levered.clone();
+        losing.avg_entry_price = 105_000_000;
+        losing.net_position = -1_000_000_000;
+        assert!(losing.adl_score(mark).unwrap() < 0);
+        let flat = MarginBucket::new(MarginScope::MarketIsolated(1), Pubkey::new_unique());
+        assert_eq!(flat.adl_score(mark).unwrap(), 0);
+
+        let mut buckets = [modest, levered.clone()];
+        buckets.sort_by_key(|b| std::cmp::Reverse(b.adl_score(mark).unwrap()));
+        assert_eq!(buckets[0].committed, levered.committed);
+    }
+
+    #[test]
+    fn test_effective_leverage_bps() {
+        let mark = 100_000_000; // $100
+        let mut bucket = MarginBucket::new(MarginScope::MarketIsolated(1), Pubkey::new_unique());
+        bucket.committed = 1_000_000_000; // $1000
+        assert_eq!(bucket.effective_leverage_bps(mark).unwrap(), 0);
+
+        // 10 tokens at $100 against $1000 → 1x
+        bucket.net_position = 1_000_000_000;
+        bucket.avg_entry_price = mark;
+        assert_eq!(bucket.effective_leverage_bps(mark).unwrap(), 10_000);
+
+        // 50 tokens short against $1000 → 5x
+        bucket.net_position = -5_000_000_000;
+        assert_eq!(bucket.effective_leverage_bps(mark).unwrap(), 50_000);
+
+        // Losses shrink equity and raise leverage; none left saturates
+        bucket.net_position = 1_000_000_000;
+        bucket.avg_entry_price = 200_000_000;
+        assert_eq!(bucket.calc_equity(mark).unwrap(), 0);
+        assert_eq!(bucket.effective_leverage_bps(mark).unwrap(), u64::MAX);
+    }
+
+    #[test]
+    fn test_margin_requirements_round_notional_up() {
+        // qty 3 × px 50_000_000 / 1e8 = 1.5 units of notional, truncated to 1
+        let (qty, mark) = (3u64, 50_000_000u64);
+        assert_eq!(
+            crate::state::math::mul_qty_px_to_notional(qty, mark).unwrap(),
+            1
+        );
+
+        let mut bucket = MarginBucket::new(MarginScope::MarketIsolated(1), Pubkey::new_unique());
+        // 100% margin exposes the notional rounding directly
+        assert_eq!(bucket.calc_required_margin(mark, 10_000, qty).unwrap(), 2);
+
+        let order = crate::state::order::OrderDetails::new(
+            1,
+            OrderSide::Bid,
+            qty,
+            crate::state::order::OrderPrice::Limit(mark),
+            crate::state::order::TimeInForce::GTC,
+        );
+        assert_eq!(order.cancel_release(mark, 10_000).unwrap(), 2);
+
+        // Equity of 1 doesn't cover the rounded-up requirement of 2
+        bucket.committed = 1;
+        bucket.open_bid_qty = qty;
+        assert_eq!(
+            bucket.calculate_uncommittable_amount(mark, 10_000).unwrap(),
+            0
+        );
+        bucket.committed = 3;
+        assert_eq!(
+            bucket.calculate_uncommittable_amount(mark, 10_000).unwrap(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_set_target_leverage() {
+        let mut bucket = MarginBucket::new(MarginScope::MarketIsolated(1), Pubkey::new_unique());
+        let mm_bps = 500; // 5%
+
+        // 1x → 100% initial margin
+        bucket.set_target_leverage(10_000, mm_bps).unwrap();
+        assert_eq!(bucket.user_set_im_bps, 10_000);
+
+        // 2x → 50%
+        bucket.set_target_leverage(20_000, mm_bps).unwrap();
+        assert_eq!(bucket.user_set_im_bps, 5_000);
+
+        // 10x → 10%
+        bucket.set_target_leverage(100_000, mm_bps).unwrap();
+        assert_eq!(bucket.user_set_im_bps, 1_000);
+
+        // 3x rounds margin up so leverage stays at or below target
+        bucket.set_target_leverage(30_000, mm_bps).unwrap();
+        assert_eq!(bucket.user_set_im_bps, 3_334);
+
+        // 20x is exactly at maintenance; 25x would need 4% and is rejected
+        bucket.set_target_leverage(200_000, mm_bps).unwrap();
+        assert_eq!(bucket.user_set_im_bps, 500);
+        assert_eq!(
+            bucket.set_target_leverage(250_000, mm_bps),
+            Err(ProgramError::InvalidArgument)
+        );
+        assert_eq!(bucket.user_set_im_bps, 500);
+
+        // Extreme targets bottom out at 1 bps rather than zero margin
+        bucket.set_target_leverage(u32::MAX, 0).unwrap();
+        assert_eq!(bucket.user_set_im_bps, 1);
+
+        // Below 1x
+        for target in [0, 9_999] {
+            assert_eq!(
+                bucket.set_target_leverage(target, mm_bps),
+                Err(ProgramError::InvalidArgument)
+            );
+        }
+    }
+
+    #[test]
+    fn test_return_bps() {
+        let mut bucket = MarginBucket::new(MarginScope::MarketIsolated(1), Pubkey::new_unique());
+        assert_eq!(bucket.return_bps(50_000_000_000).unwrap(), 0);
+
+        // $10k committed, long 1 token from $50k
+        bucket.committed = 10_000_000_000;
+        bucket.net_position = 100_000_000;
+        bucket.avg_entry_price = 50_000_000_000;
+
+        // +$1k → +10%, -$1k → -10%
+        assert_eq!(bucket.return_bps(51_000_000_000).unwrap(), 1_000);
+        assert_eq!(bucket.return_bps(49_000_000_000).unwrap(), -1_000);
+        assert_eq!(bucket.return_bps(50_000_000_000).unwrap(), 0);
+
+        // Short mirrors the sign
+        bucket.net_position = -100_000_000;
+        assert_eq!(bucket.return_bps(49_000_000_000).unwrap(), 1_000);
+    }
+
+    #[test]
+    fn test_bucket_direction() {
+        let mut bucket = MarginBucket::new(MarginScope::MarketIsolated(1), Pubkey::new_unique());
+        assert_eq!(bucket.direction(), PositionDirection::Flat);
+
+        bucket.net_position = 5_000_000;
+        assert_eq!(bucket.direction(), PositionDirection::Long);
+
+        bucket.net_position = -5_000_000;
+        assert_eq!(bucket.direction(), PositionDirection::Short);
+
+        // Open orders alone don't give the bucket a direction
+        bucket.net_position = 0;
+        bucket.open_bid_qty = 1_000_000;
+        assert_eq!(bucket.direction(), PositionDirection::Flat);
+    }
+
     #[test]
     fn test_worst_case_position_no_position() {
         let bucket = MarginBucket {
@@ -883,6 +1587,30 @@ mod tests {
         assert_eq!(bucket.worst_case_position(), 100);
     }
 
+    #[test]
+    fn test_worst_case_position_orders_flip_position() {
+        let mut bucket = MarginBucket {
+            scope: MarginScope::MarketIsolated(1),
+            mint: Pubkey::new_unique(),
+            committed: 1_000_000,
+            net_position: 100,
+            open_bid_qty: 50,
+            open_ask_qty: 500,
+            avg_entry_price: 50_000,
+            user_set_im_bps: 0,
+            _pad: [0; 32],
+        };
+
+        // Long 100 with 500 of asks can end up short 400
+        assert_eq!(bucket.worst_case_position(), 400);
+
+        // Short 100 with 500 of bids can end up long 400
+        bucket.net_position = -100;
+        bucket.open_bid_qty = 500;
+        bucket.open_ask_qty = 50;
+        assert_eq!(bucket.worst_case_position(), 400);
+    }
+
     #[test]
     fn test_calculate_uncommittable_amount_no_position() {
         let bucket = MarginBucket {
@@ -1241,6 +1969,57 @@ mod tests {
         assert_eq!(result.unwrap_err(), ProgramError::ArithmeticOverflow);
     }
 
+    #[test]
+    fn test_required_margin_after() {
+        let market_state = create_test_market_state();
+        let bucket = MarginBucket {
+            scope: MarginScope::MarketIsolated(1),
+            mint: market_state.base_token,
+            committed: 0,
+            net_position: 50_000_000,
+            open_bid_qty: 20_000_000,
+            open_ask_qty: 10_000_000,
+            avg_entry_price: 100_000,
+            user_set_im_bps: 0,
+            _pad: [0; 32],
+        };
+
+        for (side, add_qty) in [
+            (OrderSide::Bid, 30_000_000),
+            (OrderSide::Ask, 30_000_000),
+            (OrderSide::Ask, 100_000_000),
+            (OrderSide::Bid, 0),
+        ] {
+            let expected = bucket
+                .calc_required_margin_mkt(
+                    &market_state,
+                    bucket.worst_case_direction_add(side, add_qty).unwrap(),
+                )
+                .unwrap();
+            assert_eq!(
+                bucket
+                    .required_margin_after(&market_state, side, add_qty)
+                    .unwrap(),
+                expected
+            );
+        }
+
+        // Bid: 50M + 20M + 30M = 100M qty @ $100 -> 100_000 notional -> 10% = 10_000
+        assert_eq!(
+            bucket
+                .required_margin_after(&market_state, OrderSide::Bid, 30_000_000)
+                .unwrap(),
+            10_000
+        );
+        // Ask: -50M + 10M + 30M = -10M -> clamps to zero usage
+        assert_eq!(
+            bucket
+                .required_margin_after(&market_state, OrderSide::Ask, 30_000_000)
+                .unwrap(),
+            0
+        );
+    }
+
     #[test]
     fn test_calc_equity_long_profit_and_loss_saturation() {
         // Profit case
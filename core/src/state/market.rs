@@ -1,38 +1,69 @@
-use crate::state::order::OrderSide;
+use crate::state::math::{mul_qty_px_to_notional, BPS_DENOM};
+use crate::state::order::{OrderSide, OrderTombstone, PriceReference, TimeInForce};
 use borsh::{BorshDeserialize, BorshSerialize};
 use solana_program::msg;
 use solana_program::program_error::ProgramError;
 use solana_program::pubkey::Pubkey;
 
+/// Trading status of a market
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MarketStatus {
+    /// Normal trading
+    #[default]
+    Active,
+    /// Only add-liquidity-only (ALO) orders may be placed
+    PostOnly,
+    /// No new orders; existing orders may only be cancelled
+    CancelOnly,
+    /// Trading halted
+    Halted,
+}
+
+/// Read-only view of the clearing house's book in a market
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ClearingSummary {
+    /// Clearing net position (opposite of the users' aggregate position)
+    pub net_position: i64,
+    /// Weighted average entry price of the clearing position
+    pub entry_price: u64,
+    /// Realized PnL banked by the clearing house
+    pub realized_pnl: i64,
+    /// Market open interest
+    pub open_interest: i64,
+}
+
 /// Market state account - single market for test-net
 /// PDA: ["mkt_v1.2", market_id(8), bump]
 #[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
 pub struct MarketStateV1 {
-    pub version: u8,               // =2
-    pub _pad: [u8; 128],           // forward compat
-    pub oracle: Pubkey,            // price oracle
-    pub base_token: Pubkey,        // base token mint for collateral and PnL
-    pub tick_size: u64,            // price tick size
-    pub last_bid: u64,             // last bid price
-    pub last_ask: u64,             // last ask price
-    pub last_mark_price: u64,      // last mark price
-    pub last_traded_price: u64,    // last traded price from fills
-    pub open_interest: i64,        // open interest
-    pub clearing_net_pos: i64,     // clearing net position
-    pub clearing_entry_price: u64, // clearing entry price
-    pub clearing_real_pnl: i64,    // clearing realized pnl
-    pub im_bps: u16,               // initial margin basis points
-    pub mm_bps: u16,               // maintenance margin basis points
-    pub min_order_size: u64,       // minimum order size
-    pub max_order_size: u64,       // maximum order size
-    pub max_oi_size: u64,          // maximum open interest size
-    pub max_user_oi_size: u64,     // maximum open interest size for a single user
-    pub fill_offset: u16,          // fill offset in fixed unit terms
-    pub current_log_page: u32,     // current log page to write to
-    pub _pad2: [u8; 16],           // padding
-    pub _pad3: [u8; 32],           // padding
-    pub _pad4: [u8; 32],           // padding (total 112 bytes)
-    pub _pad5: [u8; 256],          // padding
+    pub version: u8,                 // =2
+    pub _pad: [u8; 128],             // forward compat
+    pub oracle: Pubkey,              // price oracle
+    pub base_token: Pubkey,          // base token mint for collateral and PnL
+    pub tick_size: u64,              // price tick size
+    pub last_bid: u64,               // last bid price
+    pub last_ask: u64,               // last ask price
+    pub last_mark_price: u64,        // last mark price
+    pub last_traded_price: u64,      // last traded price from fills
+    pub open_interest: i64,          // open interest
+    pub clearing_net_pos: i64,       // clearing net position
+    pub clearing_entry_price: u64,   // clearing entry price
+    pub clearing_real_pnl: i64,      // clearing realized pnl
+    pub im_bps: u16,                 // initial margin basis points
+    pub mm_bps: u16,                 // maintenance margin basis points
+    pub min_order_size: u64,         // minimum order size
+    pub max_order_size: u64,         // maximum order size
+    pub max_oi_size: u64,            // maximum open interest size
+    pub max_user_oi_size: u64,       // maximum open interest size for a single user
+    pub fill_offset: u16,            // fill offset in fixed unit terms
+    pub current_log_page: u32,       // current log page to write to
+    pub max_order_notional: u64,     // maximum order notional (0 = no cap)
+    pub status: MarketStatus,        // trading status
+    pub _pad2: [u8; 7],              // padding
+    pub last_price_update_unix: i64, // unix time of the last keeper price update
+    pub _pad3: [u8; 24],             // padding
+    pub _pad4: [u8; 32],             // padding (total 112 bytes)
+    pub _pad5: [u8; 256],            // padding
 }
 
 impl Default for MarketStateV1 {
@@ -59,8 +90,11 @@ impl Default for MarketStateV1 {
             base_token: Pubkey::default(),
             fill_offset: 0,
             current_log_page: 0,
-            _pad2: [0; 16],
-            _pad3: [0; 32],
+            max_order_notional: 0,
+            status: MarketStatus::Active,
+            _pad2: [0; 7],
+            last_price_update_unix: 0,
+            _pad3: [0; 24],
             _pad4: [0; 32],
             _pad5: [0; 256],
         }
@@ -68,7 +102,17 @@ impl Default for MarketStateV1 {
 }
 
 impl MarketStateV1 {
-    pub const CURRENT_VERSION: u8 = 3; // layout changed - added last_traded_price
+    pub const CURRENT_VERSION: u8 = 4; // layout changed - added status
+
+    /// Price sources that trigger conditions may reference (spot is not wired yet)
+    pub const SUPPORTED_PRICE_REFERENCES: &[PriceReference] = &[
+        PriceReference::MarkPrice(),
+        PriceReference::OraclePrice(),
+        PriceReference::BidPrice(),
+        PriceReference::AskPrice(),
+        PriceReference::MidPrice(),
+        PriceReference::LastTradePrice(),
+    ];
 
     /// Get the mid price
     pub fn mid_price(&self) -> u64 {
@@ -88,70 +132,189 @@ impl MarketStateV1 {
         }
     }
 
+    /// Snap a price to the tick grid: bids round down, asks round up.
+    /// A zero tick size means no grid and the price is returned unchanged.
+    pub fn round_price_to_tick(&self, price: u64, side: OrderSide) -> u64 {
+        if self.tick_size == 0 {
+            return price;
+        }
+
+        let floor = price - price % self.tick_size;
+        match side {
+            OrderSide::Bid => floor,
+            OrderSide::Ask if floor == price => price,
+            // Fall back to the floor if rounding up would overflow
+            OrderSide::Ask => floor.checked_add(self.tick_size).unwrap_or(floor),
+        }
+    }
+
+    /// Check whether a price sits on the tick grid (always true with a zero tick size)
+    pub fn is_price_on_tick(&self, price: u64) -> bool {
+        self.tick_size == 0 || price.is_multiple_of(self.tick_size)
+    }
+
+    /// Spread as a fraction of the mid price, in bps (0 for an inactive market)
+    pub fn spread_bps(&self) -> u64 {
+        let mid = self.mid_price();
+        if !self.is_active() || mid == 0 {
+            return 0;
+        }
+        (self.spread() as u128 * BPS_DENOM as u128 / mid as u128) as u64
+    }
+
+    /// Signed deviation of the mark price from the mid price, in bps (0 for an inactive market)
+    pub fn mark_premium_bps(&self) -> i64 {
+        let mid = self.mid_price();
+        if !self.is_active() || mid == 0 {
+            return 0;
+        }
+        let premium =
+            (self.last_mark_price as i128 - mid as i128) * BPS_DENOM as i128 / mid as i128;
+        premium.clamp(i64::MIN as i128, i64::MAX as i128) as i64
+    }
+
     /// Check if market is active (has recent prices)
     pub fn is_active(&self) -> bool {
         self.last_bid > 0 && self.last_ask > 0
     }
 
+    /// Blend the book mid with the oracle price, weighting the oracle by `oracle_weight_bps`
+    /// (capped at 100%). Falls back to the oracle price when the book is one-sided.
+    pub fn derive_mark_price(&self, oracle_price: u64, oracle_weight_bps: u16) -> u64 {
+        if !self.is_active() {
+            return oracle_price;
+        }
+
+        let denom = BPS_DENOM as u128;
+        let oracle_weight = (oracle_weight_bps as u128).min(denom);
+        let blended = (self.mid_price() as u128 * (denom - oracle_weight)
+            + oracle_price as u128 * oracle_weight)
+            / denom;
+        blended as u64
+    }
+
+    /// Open interest expressed in quote notional at the last mark price
+    pub fn notional_open_interest(&self) -> Result<u64, ProgramError> {
+        mul_qty_px_to_notional(self.open_interest.unsigned_abs(), self.last_mark_price)
+    }
+
+    /// Snapshot of the clearing position, entry price and realized PnL
+    pub fn clearing_summary(&self) -> ClearingSummary {
+        ClearingSummary {
+            net_position: self.clearing_net_pos,
+            entry_price: self.clearing_entry_price,
+            realized_pnl: self.clearing_real_pnl,
+            open_interest: self.open_interest,
+        }
+    }
+
+    /// Zero open interest and the clearing position, entry price and realized PnL
+    /// (`ResetClearingHouse`). Unless `force` is set, refused while the clearing house still
+    /// holds a position, since that would orphan its PnL, and while open interest is non-zero:
+    /// users can hold offsetting positions with clearing flat, and zeroing their open interest
+    /// would drive it negative as they close.
+    pub fn reset_clearing(&mut self, force: bool) -> Result<(), ProgramError> {
+        if self.clearing_net_pos != 0 && !force {
+            msg!(
+                "Error: Cannot reset clearing house with open position {}",
+                self.clearing_net_pos
+            );
+            return Err(ProgramError::InvalidAccountData);
+        }
+        if self.open_interest != 0 && !force {
+            msg!(
+                "Error: Cannot reset clearing house with open interest {}",
+                self.open_interest
+            );
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        self.open_interest = 0;
+        self.clearing_net_pos = 0;
+        self.clearing_entry_price = 0;
+        self.clearing_real_pnl = 0;
+        Ok(())
+    }
+
+    /// Whether changing open interest by `position_delta` would leave it above `max_oi_size`.
+    /// Reductions (`position_delta <= 0`) are always allowed, even while over the cap, so a
+    /// keeper can reject an increasing fill before mutating any state.
+    pub fn would_exceed_oi(&self, position_delta: i64) -> bool {
+        let new_open_interest = self.open_interest.saturating_add(position_delta);
+        position_delta > 0 && new_open_interest > 0 && new_open_interest as u64 > self.max_oi_size
+    }
+
     /// Apply a fill to the market state – updates open interest, clearing position, and last traded price.
+    ///
+    /// Every user fill is booked against the clearing counterparty, so `cma_fill_result` is the
+    /// user's side and the clearing position takes the other side. Open interest is tracked as
+    /// total long exposure across users and clearing: when a position merely transfers between
+    /// two users (one closing, one opening via clearing) the long-side deltas cancel out.
     pub fn process_fill(
         &mut self,
         fill: &crate::state::order::OrderFillResult,
         cma_fill_result: &crate::state::cma::CmaFillResult,
     ) -> Result<(), solana_program::program_error::ProgramError> {
+        // Market orders skipped the notional cap at entry, so enforce it at the fill price
+        if fill.is_market_order {
+            self.validate_order_notional(fill.filled_qty, fill.weighted_avg_price)?;
+        }
+
         // Update last traded price to the fill's weighted average price
         self.last_traded_price = fill.weighted_avg_price;
 
-        // Update open interest (saturating to avoid overflow)
-        let open_interest_change =
-            cma_fill_result.new_net_position.abs() - cma_fill_result.old_net_position.abs();
-        self.open_interest = self.open_interest.saturating_add(open_interest_change);
-
-        if (self.open_interest > 0) && ((self.open_interest as u64) > self.max_oi_size) {
-            if open_interest_change < 0 {
-                msg!(
-                    "Open interest exceeds max size, but reduction allowed {} > {} (delta={})",
-                    self.open_interest,
-                    self.max_oi_size,
-                    open_interest_change
-                );
-            } else {
-                msg!(
-                    "Error: Open interest exceeds max size {} > {}",
-                    self.open_interest,
-                    self.max_oi_size
-                );
-                return Err(ProgramError::InvalidArgument);
-            }
-        }
-
         let fill_qty = fill.filled_qty;
         let fill_price = fill.weighted_avg_price;
 
         // Update clearing position based on order side
         // When user buys (Bid), clearing goes short (negative)
         // When user sells (Ask), clearing goes long (positive)
-        let clearing_position_change = match fill.side {
-            OrderSide::Bid => -(fill_qty as i64), // User buys, clearing sells
-            OrderSide::Ask => fill_qty as i64,    // User sells, clearing buys
-        };
+        let clearing_position_change = fill.side.opposite().sign() * fill_qty as i64;
 
-        // Update clearing position and entry price
         let old_clearing_pos = self.clearing_net_pos;
         let new_clearing_pos = old_clearing_pos + clearing_position_change;
 
+        // Open interest is the change in total long exposure of the user and clearing
+        let open_interest_change = long_exposure_change(
+            cma_fill_result.old_net_position,
+            cma_fill_result.new_net_position,
+        )
+        .saturating_add(long_exposure_change(old_clearing_pos, new_clearing_pos));
+        let new_open_interest = self.open_interest.saturating_add(open_interest_change);
+
+        if self.would_exceed_oi(open_interest_change) {
+            msg!(
+                "Error: Open interest exceeds max size {} > {}",
+                new_open_interest,
+                self.max_oi_size
+            );
+            return Err(ProgramError::InvalidArgument);
+        }
+        if (new_open_interest > 0) && ((new_open_interest as u64) > self.max_oi_size) {
+            msg!(
+                "Open interest exceeds max size, but reduction allowed {} > {} (delta={})",
+                new_open_interest,
+                self.max_oi_size,
+                open_interest_change
+            );
+        }
+        self.open_interest = new_open_interest;
+
         // Calculate new weighted average entry price if position increases
         if (old_clearing_pos >= 0 && clearing_position_change > 0)
             || (old_clearing_pos <= 0 && clearing_position_change < 0)
         {
-            // Position is increasing in same direction, calculate weighted average
-            let old_notional = old_clearing_pos.unsigned_abs() * self.clearing_entry_price;
-            let new_notional = fill_qty * fill_price;
+            // Position is increasing in same direction, calculate weighted average.
+            // Notionals are qty * price products and routinely exceed u64, so work in u128.
+            let old_notional =
+                old_clearing_pos.unsigned_abs() as u128 * self.clearing_entry_price as u128;
+            let new_notional = fill_qty as u128 * fill_price as u128;
             let total_notional = old_notional + new_notional;
-            let total_position = old_clearing_pos.unsigned_abs() + fill_qty;
+            let total_position = old_clearing_pos.unsigned_abs() as u128 + fill_qty as u128;
 
             if total_position > 0 {
-                self.clearing_entry_price = total_notional / total_position;
+                // A weighted average never exceeds the larger of the two u64 prices
+                self.clearing_entry_price = (total_notional / total_position) as u64;
             }
         } else if old_clearing_pos != 0
             && ((old_clearing_pos > 0 && clearing_position_change < 0)
@@ -244,8 +407,167 @@ impl MarketStateV1 {
             return Err(ProgramError::InvalidArgument);
         }
 
+        // Market orders have no price yet; process_fill checks them against the fill price
+        if !is_mkt_order {
+            self.validate_order_notional(qty, price)?;
+        }
+
+        Ok(())
+    }
+
+    /// Validates that the market's parameters are internally consistent.
+    /// Should be checked whenever a market is initialized or its parameters change.
+    pub fn validate_parameters(&self) -> Result<(), ProgramError> {
+        if self.tick_size == 0 {
+            msg!("Error: Market tick size must be non-zero");
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        if self.max_order_size > 0 && self.min_order_size > self.max_order_size {
+            msg!(
+                "Error: Minimum order size {} exceeds maximum order size {}",
+                self.min_order_size,
+                self.max_order_size
+            );
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        if self.mm_bps == 0 {
+            msg!("Error: Maintenance margin must be non-zero");
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        if self.im_bps < self.mm_bps {
+            msg!(
+                "Error: Initial margin {} bps below maintenance margin {} bps",
+                self.im_bps,
+                self.mm_bps
+            );
+            return Err(ProgramError::InvalidArgument);
+        }
+
         Ok(())
     }
+
+    /// Apply a keeper `UpdateMarketData` price update and stamp `last_price_update_unix`,
+    /// the timestamp `is_tradable` checks for staleness
+    pub fn update_market_data(
+        &mut self,
+        last_bid: u64,
+        last_ask: u64,
+        last_trade_price: u64,
+        last_mark_price: u64,
+        now_unix: i64,
+    ) {
+        self.last_bid = last_bid;
+        self.last_ask = last_ask;
+        self.last_traded_price = last_trade_price;
+        self.last_mark_price = last_mark_price;
+        self.last_price_update_unix = now_unix;
+    }
+
+    /// Composite gate handlers call before accepting any order. Returns the tombstone that
+    /// blocks trading: `SystemHalt` when halted, `MarketClosed` when cancel-only or missing a
+    /// tick size, and `Breaker` when prices are missing or older than `max_price_age_secs`
+    /// (a non-positive age disables the staleness check). Until the first `update_market_data`
+    /// the timestamp is 0, so a newly created or migrated market counts as stale.
+    pub fn is_tradable(
+        &self,
+        now_unix: i64,
+        max_price_age_secs: i64,
+    ) -> Result<(), OrderTombstone> {
+        match self.status {
+            MarketStatus::Halted => return Err(OrderTombstone::SystemHalt()),
+            MarketStatus::CancelOnly => return Err(OrderTombstone::MarketClosed()),
+            MarketStatus::Active | MarketStatus::PostOnly => {}
+        }
+
+        if self.tick_size == 0 {
+            return Err(OrderTombstone::MarketClosed());
+        }
+
+        if !self.is_active() || self.last_mark_price == 0 {
+            return Err(OrderTombstone::Breaker());
+        }
+
+        if max_price_age_secs > 0
+            && now_unix.saturating_sub(self.last_price_update_unix) > max_price_age_secs
+        {
+            return Err(OrderTombstone::Breaker());
+        }
+
+        Ok(())
+    }
+
+    /// Checks whether the market's trading status admits a new order with the given time in force
+    pub fn can_place_order(&self, tif: &TimeInForce) -> Result<(), ProgramError> {
+        match (self.status, tif) {
+            (MarketStatus::Active, _) | (MarketStatus::PostOnly, TimeInForce::ALO) => Ok(()),
+            (MarketStatus::PostOnly, _) => {
+                msg!(
+                    "Error: Market is post-only, order must be ALO (got {:?})",
+                    tif
+                );
+                Err(ProgramError::InvalidArgument)
+            }
+            (status, _) => {
+                msg!(
+                    "Error: Market does not accept new orders in status {:?}",
+                    status
+                );
+                Err(ProgramError::InvalidArgument)
+            }
+        }
+    }
+
+    /// Rejects limit prices more than `band_bps` away from the last mark price (fat-finger guard).
+    /// Market orders (price 0) are not checked, and neither is any price before the market has
+    /// a mark to anchor the band on.
+    pub fn validate_price_band(&self, price: u64, band_bps: u16) -> Result<(), ProgramError> {
+        if price == 0 || self.last_mark_price == 0 {
+            return Ok(());
+        }
+
+        let deviation = price.abs_diff(self.last_mark_price) as u128;
+        let max_deviation = self.last_mark_price as u128 * band_bps as u128;
+        if deviation * BPS_DENOM as u128 > max_deviation {
+            msg!(
+                "Error: Price {} outside {} bps band around mark price {}",
+                price,
+                band_bps,
+                self.last_mark_price
+            );
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        Ok(())
+    }
+
+    /// Validates that `qty` at `price` does not exceed the market's notional cap (when set).
+    /// Limit orders are checked at entry; market orders are checked at their fill price in
+    /// `process_fill`.
+    pub fn validate_order_notional(&self, qty: u64, price: u64) -> Result<(), ProgramError> {
+        if self.max_order_notional == 0 {
+            return Ok(());
+        }
+
+        let notional = mul_qty_px_to_notional(qty, price)?;
+        if notional > self.max_order_notional {
+            msg!(
+                "Error: Order notional {} exceeds maximum notional {}",
+                notional,
+                self.max_order_notional
+            );
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        Ok(())
+    }
+}
+
+/// Change in long exposure when a position moves from `old_pos` to `new_pos`
+fn long_exposure_change(old_pos: i64, new_pos: i64) -> i64 {
+    new_pos.max(0) - old_pos.max(0)
 }
 
 #[cfg(test)]
@@ -351,6 +673,64 @@ mod tests {
         assert!(result.is_ok());
     }
 
+    #[test]
+    fn test_validate_order_conformance_max_order_notional() {
+        let mut market = create_test_market_state();
+        market.max_order_notional = 500_000; // $500
+
+        // 5.0 tokens at $100 = $500, exactly at the cap
+        assert!(market
+            .validate_order_conformance(500_000_000, 100_000)
+            .is_ok());
+
+        // 5.0 tokens at $101 = $505, over the cap
+        let result = market.validate_order_conformance(500_000_000, 101_000);
+        assert_eq!(result.unwrap_err(), ProgramError::InvalidArgument);
+
+        // Market orders skip the entry check but fail at an over-cap fill price
+        assert!(market.validate_order_conformance(500_000_000, 0).is_ok());
+        assert_eq!(
+            market
+                .validate_order_notional(500_000_000, 101_000)
+                .unwrap_err(),
+            ProgramError::InvalidArgument
+        );
+
+        // Disabled cap admits any notional
+        market.max_order_notional = 0;
+        assert!(market
+            .validate_order_conformance(1_000_000_000_000, 999_000)
+            .is_ok());
+    }
+
+    #[test]
+    fn test_validate_price_band() {
+        let mut market = create_test_market_state();
+        market.last_mark_price = 100_000; // 500 bps band = +/- 5_000
+
+        // Just inside on both sides
+        assert!(market.validate_price_band(105_000, 500).is_ok());
+        assert!(market.validate_price_band(95_000, 500).is_ok());
+
+        // Just outside on both sides
+        assert_eq!(
+            market.validate_price_band(105_001, 500).unwrap_err(),
+            ProgramError::InvalidArgument
+        );
+        assert_eq!(
+            market.validate_price_band(94_999, 500).unwrap_err(),
+            ProgramError::InvalidArgument
+        );
+
+        // Market orders skip the check
+        assert!(market.validate_price_band(0, 500).is_ok());
+
+        // No mark yet means no band to check against
+        market.last_mark_price = 0;
+        assert!(market.validate_price_band(105_001, 500).is_ok());
+        assert!(market.validate_price_band(1, 0).is_ok());
+    }
+
     #[test]
     fn test_validate_order_conformance_edge_cases() {
         let market = create_test_market_state();
@@ -402,6 +782,86 @@ mod tests {
         assert_eq!(market_zero_ask.spread(), 0);
     }
 
+    #[test]
+    fn test_round_price_to_tick() {
+        let market = create_test_market_state(); // tick_size = 1000
+
+        // Exactly on tick: unchanged on both sides
+        assert_eq!(market.round_price_to_tick(100_000, OrderSide::Bid), 100_000);
+        assert_eq!(market.round_price_to_tick(100_000, OrderSide::Ask), 100_000);
+        assert!(market.is_price_on_tick(100_000));
+
+        // Off tick: bids round down, asks round up
+        assert_eq!(market.round_price_to_tick(100_999, OrderSide::Bid), 100_000);
+        assert_eq!(market.round_price_to_tick(100_001, OrderSide::Ask), 101_000);
+        assert!(!market.is_price_on_tick(100_001));
+
+        // Below one tick
+        assert_eq!(market.round_price_to_tick(999, OrderSide::Bid), 0);
+        assert_eq!(market.round_price_to_tick(999, OrderSide::Ask), 1_000);
+
+        // Rounding up past u64::MAX falls back to the floor
+        let top = market.round_price_to_tick(u64::MAX, OrderSide::Ask);
+        assert_eq!(top, u64::MAX - u64::MAX % 1000);
+    }
+
+    #[test]
+    fn test_round_price_to_tick_zero_tick() {
+        let mut market = create_test_market_state();
+        market.tick_size = 0;
+
+        assert_eq!(market.round_price_to_tick(100_001, OrderSide::Bid), 100_001);
+        assert_eq!(market.round_price_to_tick(100_001, OrderSide::Ask), 100_001);
+        assert!(market.is_price_on_tick(100_001));
+    }
+
+    #[test]
+    fn test_derive_mark_price_blend() {
+        let market = create_test_market_state(); // mid = 100_500
+
+        // 50/50 blend of mid and oracle
+        assert_eq!(market.derive_mark_price(101_500, 5_000), 101_000);
+        // Weight extremes pick one source; overweight is capped at 100% oracle
+        assert_eq!(market.derive_mark_price(101_500, 0), 100_500);
+        assert_eq!(market.derive_mark_price(101_500, 10_000), 101_500);
+        assert_eq!(market.derive_mark_price(101_500, u16::MAX), 101_500);
+    }
+
+    #[test]
+    fn test_derive_mark_price_one_sided_book() {
+        let mut market = create_test_market_state();
+        market.last_ask = 0;
+
+        assert_eq!(market.derive_mark_price(101_500, 0), 101_500);
+        assert_eq!(market.derive_mark_price(101_500, 5_000), 101_500);
+    }
+
+    #[test]
+    fn test_spread_and_mark_premium_bps() {
+        let mut market = create_test_market_state();
+        market.last_bid = 99_950_000; // $99.95
+        market.last_ask = 100_050_000; // $100.05
+        market.last_mark_price = 100_200_000; // $100.20
+
+        // 0.10 / 100.00 = 10 bps
+        assert_eq!(market.spread_bps(), 10);
+        // Mark 0.20 above mid = 20 bps premium
+        assert_eq!(market.mark_premium_bps(), 20);
+
+        // Mark below mid is a discount
+        market.last_mark_price = 99_500_000;
+        assert_eq!(market.mark_premium_bps(), -50);
+    }
+
+    #[test]
+    fn test_spread_and_mark_premium_bps_inactive() {
+        let mut market = create_test_market_state();
+        market.last_bid = 0;
+
+        assert_eq!(market.spread_bps(), 0);
+        assert_eq!(market.mark_premium_bps(), 0);
+    }
+
     #[test]
     fn test_market_state_is_active() {
         let market = create_test_market_state();
@@ -491,6 +951,418 @@ mod tests {
         assert!(result.is_ok(), "Large market orders should be valid");
     }
 
+    #[test]
+    fn test_notional_open_interest() {
+        let mut market = create_test_market_state();
+        market.open_interest = 250_000_000; // 2.5 tokens (1e-8)
+        market.last_mark_price = 100_000_000; // $100 (1e-6)
+
+        // 2.5 * $100 = $250
+        assert_eq!(market.notional_open_interest().unwrap(), 250_000_000);
+
+        // Sign of the stored OI is ignored
+        market.open_interest = -250_000_000;
+        assert_eq!(market.notional_open_interest().unwrap(), 250_000_000);
+
+        market.open_interest = 0;
+        assert_eq!(market.notional_open_interest().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_notional_open_interest_overflow() {
+        let mut market = create_test_market_state();
+        market.open_interest = i64::MAX;
+        market.last_mark_price = u64::MAX;
+
+        assert_eq!(
+            market.notional_open_interest().unwrap_err(),
+            ProgramError::ArithmeticOverflow
+        );
+    }
+
+    fn user_fill(
+        side: OrderSide,
+        qty: u64,
+        old_net_position: i64,
+    ) -> (
+        crate::state::order::OrderFillResult,
+        crate::state::cma::CmaFillResult,
+    ) {
+        let delta = match side {
+            OrderSide::Bid => qty as i64,
+            OrderSide::Ask => -(qty as i64),
+        };
+        (
+            crate::state::order::OrderFillResult {
+                filled_qty: qty,
+                weighted_avg_price: 100_000,
+                is_fully_filled: true,
+                side,
+                is_market_order: false,
+            },
+            crate::state::cma::CmaFillResult {
+                new_net_position: old_net_position + delta,
+                old_net_position,
+                realized_pnl_banked: 0,
+            },
+        )
+    }
+
+    #[test]
+    fn test_process_fill_clearing_entry_price_large_notional() {
+        let mut market = create_test_market_state();
+        market.max_oi_size = u64::MAX;
+
+        // 1e11 lots * 1e11 price = 1e22, far beyond u64::MAX (~1.8e19)
+        let qty = 100_000_000_000;
+        let (mut fill, cma) = user_fill(OrderSide::Bid, qty, 0);
+        fill.weighted_avg_price = 100_000_000_000;
+        market.process_fill(&fill, &cma).unwrap();
+        assert_eq!(market.clearing_entry_price, 100_000_000_000);
+
+        // Adding to the clearing short averages the two prices without wrapping
+        let (mut fill, cma) = user_fill(OrderSide::Bid, qty, 0);
+        fill.weighted_avg_price = 300_000_000_000;
+        market.process_fill(&fill, &cma).unwrap();
+        assert_eq!(market.clearing_net_pos, -2 * qty as i64);
+        assert_eq!(market.clearing_entry_price, 200_000_000_000);
+    }
+
+    #[test]
+    fn test_reset_clearing_rejects_open_position() {
+        let mut market = create_test_market_state();
+        market.max_oi_size = u64::MAX;
+
+        let (fill, cma) = user_fill(OrderSide::Bid, 100_000_000, 0);
+        market.process_fill(&fill, &cma).unwrap();
+        let before = market.clearing_summary();
+        assert_eq!(before.net_position, -100_000_000);
+        assert_eq!(before.entry_price, 100_000);
+        assert_eq!(before.open_interest, 100_000_000);
+
+        assert_eq!(
+            market.reset_clearing(false),
+            Err(ProgramError::InvalidAccountData)
+        );
+        assert_eq!(market.clearing_summary(), before);
+
+        market.reset_clearing(true).unwrap();
+        assert_eq!(market.clearing_summary(), ClearingSummary::default());
+    }
+
+    #[test]
+    fn test_reset_clearing_when_flat() {
+        let mut market = create_test_market_state();
+        market.max_oi_size = u64::MAX;
+
+        // Clearing ends flat while Alice (long) and Bob (short) still hold positions
+        let (fill, cma) = user_fill(OrderSide::Bid, 100_000_000, 0);
+        market.process_fill(&fill, &cma).unwrap();
+        let (fill, cma) = user_fill(OrderSide::Ask, 100_000_000, 0);
+        market.process_fill(&fill, &cma).unwrap();
+        market.clearing_real_pnl = 42;
+        let before = market.clearing_summary();
+        assert_eq!(before.net_position, 0);
+        assert_eq!(before.realized_pnl, 42);
+        assert_eq!(before.open_interest, 100_000_000);
+
+        // Their open interest must survive, or closing would drive it negative
+        assert_eq!(
+            market.reset_clearing(false),
+            Err(ProgramError::InvalidAccountData)
+        );
+        assert_eq!(market.clearing_summary(), before);
+
+        // Alice sells her long, then Bob buys back his short
+        let (fill, cma) = user_fill(OrderSide::Ask, 100_000_000, 100_000_000);
+        market.process_fill(&fill, &cma).unwrap();
+        assert!(market.open_interest >= 0);
+        let (fill, cma) = user_fill(OrderSide::Bid, 100_000_000, -100_000_000);
+        market.process_fill(&fill, &cma).unwrap();
+        assert_eq!(market.open_interest, 0);
+        assert_eq!(market.clearing_net_pos, 0);
+
+        market.reset_clearing(false).unwrap();
+        assert_eq!(market.clearing_summary(), ClearingSummary::default());
+    }
+
+    #[test]
+    fn test_process_fill_open_interest_opening_trade() {
+        let mut market = create_test_market_state();
+        market.max_oi_size = u64::MAX;
+
+        // Alice buys 1 from clearing: one long opened
+        let (fill, cma) = user_fill(OrderSide::Bid, 100_000_000, 0);
+        market.process_fill(&fill, &cma).unwrap();
+        assert_eq!(market.open_interest, 100_000_000);
+        assert_eq!(market.clearing_net_pos, -100_000_000);
+
+        // Bob sells 1 to clearing: Alice long vs Bob short is still 1 of open interest
+        let (fill, cma) = user_fill(OrderSide::Ask, 100_000_000, 0);
+        market.process_fill(&fill, &cma).unwrap();
+        assert_eq!(market.open_interest, 100_000_000);
+        assert_eq!(market.clearing_net_pos, 0);
+    }
+
+    #[test]
+    fn test_process_fill_position_transfer_leaves_open_interest_unchanged() {
+        let mut market = create_test_market_state();
+        market.max_oi_size = u64::MAX;
+
+        // Alice opens 1 long against Bob's 1 short
+        let (fill, cma) = user_fill(OrderSide::Bid, 100_000_000, 0);
+        market.process_fill(&fill, &cma).unwrap();
+        let (fill, cma) = user_fill(OrderSide::Ask, 100_000_000, 0);
+        market.process_fill(&fill, &cma).unwrap();
+        assert_eq!(market.open_interest, 100_000_000);
+
+        // Alice sells her long and Carol buys it: the long just changes hands
+        let (fill, cma) = user_fill(OrderSide::Ask, 100_000_000, 100_000_000);
+        market.process_fill(&fill, &cma).unwrap();
+        assert_eq!(market.open_interest, 100_000_000);
+        let (fill, cma) = user_fill(OrderSide::Bid, 100_000_000, 0);
+        market.process_fill(&fill, &cma).unwrap();
+        assert_eq!(market.open_interest, 100_000_000);
+        assert_eq!(market.clearing_net_pos, 0);
+
+        // Carol closes against Bob: open interest goes back to zero
+        let (fill, cma) = user_fill(OrderSide::Ask, 100_000_000, 100_000_000);
+        market.process_fill(&fill, &cma).unwrap();
+        let (fill, cma) = user_fill(OrderSide::Bid, 100_000_000, -100_000_000);
+        market.process_fill(&fill, &cma).unwrap();
+        assert_eq!(market.open_interest, 0);
+    }
+
+    #[test]
+    fn test_process_fill_open_interest_cap() {
+        let mut market = create_test_market_state();
+        market.max_oi_size = 50_000_000;
+
+        let (fill, cma) = user_fill(OrderSide::Bid, 100_000_000, 0);
+        assert_eq!(
+            market.process_fill(&fill, &cma).unwrap_err(),
+            ProgramError::InvalidArgument
+        );
+    }
+
+    #[test]
+    fn test_process_fill_market_order_notional_cap() {
+        let mut market = create_test_market_state();
+        market.max_order_notional = 500_000; // $500
+        market.max_oi_size = u64::MAX;
+
+        // 10.0 tokens at $100 = $1000, over the cap
+        let (mut fill, cma) = user_fill(OrderSide::Bid, 1_000_000_000, 0);
+        fill.is_market_order = true;
+        assert_eq!(
+            market.process_fill(&fill, &cma).unwrap_err(),
+            ProgramError::InvalidArgument
+        );
+        assert_eq!(market.open_interest, 0);
+        assert_eq!(market.clearing_net_pos, 0);
+
+        // 5.0 tokens at $100 = $500, exactly at the cap
+        let (mut fill, cma) = user_fill(OrderSide::Bid, 500_000_000, 0);
+        fill.is_market_order = true;
+        assert!(market.process_fill(&fill, &cma).is_ok());
+
+        // Limit orders were checked at entry and are not re-checked at fill
+        let (fill, cma) = user_fill(OrderSide::Bid, 1_000_000_000, 500_000_000);
+        assert!(market.process_fill(&fill, &cma).is_ok());
+    }
+
+    #[test]
+    fn test_would_exceed_oi_increasing_fill() {
+        let mut market = create_test_market_state();
+        market.max_oi_size = 100_000_000;
+        market.open_interest = 60_000_000;
+
+        assert!(!market.would_exceed_oi(40_000_000));
+        assert!(market.would_exceed_oi(40_000_001));
+
+        // Predicts the same outcome process_fill enforces, without touching state
+        market.open_interest = 0;
+        market.max_oi_size = 50_000_000;
+        assert!(market.would_exceed_oi(100_000_000));
+        let (fill, cma) = user_fill(OrderSide::Bid, 100_000_000, 0);
+        assert!(market.process_fill(&fill, &cma).is_err());
+        assert_eq!(market.open_interest, 0);
+    }
+
+    #[test]
+    fn test_would_exceed_oi_allows_reductions() {
+        let mut market = create_test_market_state();
+        market.max_oi_size = 100_000_000;
+
+        // Already over the cap (e.g. after the cap was lowered): shrinking is still fine
+        market.open_interest = 150_000_000;
+        assert!(!market.would_exceed_oi(-10_000_000));
+        assert!(!market.would_exceed_oi(0));
+        assert!(market.would_exceed_oi(1));
+    }
+
+    #[test]
+    fn test_validate_parameters() {
+        let mut market = create_test_market_state();
+        market.max_order_size = 10_000_000;
+        assert!(market.validate_parameters().is_ok());
+
+        // Unlimited max size never conflicts with the minimum
+        market.max_order_size = 0;
+        assert!(market.validate_parameters().is_ok());
+
+        // Equal margins are allowed
+        market.im_bps = market.mm_bps;
+        assert!(market.validate_parameters().is_ok());
+    }
+
+    #[test]
+    fn test_validate_parameters_violations() {
+        let base = MarketStateV1 {
+            max_order_size: 10_000_000,
+            ..create_test_market_state()
+        };
+
+        let zero_tick = MarketStateV1 {
+            tick_size: 0,
+            ..base.clone()
+        };
+        let min_over_max = MarketStateV1 {
+            min_order_size: 10_000_001,
+            ..base.clone()
+        };
+        let im_below_mm = MarketStateV1 {
+            im_bps: 400,
+            ..base.clone()
+        };
+        let zero_mm = MarketStateV1 {
+            mm_bps: 0,
+            ..base.clone()
+        };
+
+        for market in [zero_tick, min_over_max, im_below_mm, zero_mm] {
+            assert_eq!(
+                market.validate_parameters().unwrap_err(),
+                ProgramError::InvalidArgument
+            );
+        }
+    }
+
+    #[test]
+    fn test_can_place_order_by_status() {
+        let tifs = [
+            TimeInForce::IOC,
+            TimeInForce::FOK,
+            TimeInForce::GTC,
+            TimeInForce::ALO,
+            TimeInForce::GTT(1_700_000_000),
+        ];
+        let mut market = create_test_market_state();
+
+        market.status = MarketStatus::Active;
+        for tif in &tifs {
+            assert!(market.can_place_order(tif).is_ok(), "Active {:?}", tif);
+        }
+
+        market.status = MarketStatus::PostOnly;
+        for tif in &tifs {
+            let result = market.can_place_order(tif);
+            if *tif == TimeInForce::ALO {
+                assert!(result.is_ok());
+            } else {
+                assert_eq!(result.unwrap_err(), ProgramError::InvalidArgument);
+            }
+        }
+
+        for status in [MarketStatus::CancelOnly, MarketStatus::Halted] {
+            market.status = status;
+            for tif in &tifs {
+                assert_eq!(
+                    market.can_place_order(tif).unwrap_err(),
+                    ProgramError::InvalidArgument,
+                    "{:?} {:?}",
+                    status,
+                    tif
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_is_tradable_gates() {
+        let now = 1_700_000_000;
+        let mut market = MarketStateV1 {
+            tick_size: 100,
+            ..Default::default()
+        };
+        // Never updated: no prices, and the zero timestamp is stale
+        assert_eq!(market.is_tradable(now, 30), Err(OrderTombstone::Breaker()));
+
+        market.update_market_data(99_000, 101_000, 100_500, 100_000, now - 5);
+        assert_eq!(market.last_price_update_unix, now - 5);
+        assert_eq!(market.last_traded_price, 100_500);
+        assert_eq!(market.is_tradable(now, 30), Ok(()));
+
+        // Post-only markets still trade; the time-in-force check is separate
+        market.status = MarketStatus::PostOnly;
+        assert_eq!(market.is_tradable(now, 30), Ok(()));
+
+        market.status = MarketStatus::Halted;
+        assert_eq!(
+            market.is_tradable(now, 30),
+            Err(OrderTombstone::SystemHalt())
+        );
+        market.status = MarketStatus::CancelOnly;
+        assert_eq!(
+            market.is_tradable(now, 30),
+            Err(OrderTombstone::MarketClosed())
+        );
+        market.status = MarketStatus::Active;
+
+        let mut no_tick = market.clone();
+        no_tick.tick_size = 0;
+        assert_eq!(
+            no_tick.is_tradable(now, 30),
+            Err(OrderTombstone::MarketClosed())
+        );
+
+        let mut one_sided = market.clone();
+        one_sided.last_ask = 0;
+        assert_eq!(
+            one_sided.is_tradable(now, 30),
+            Err(OrderTombstone::Breaker())
+        );
+
+        let mut no_mark = market.clone();
+        no_mark.last_mark_price = 0;
+        assert_eq!(no_mark.is_tradable(now, 30), Err(OrderTombstone::Breaker()));
+
+        // Stale prices are rejected unless the staleness check is disabled
+        assert_eq!(
+            market.is_tradable(now + 60, 30),
+            Err(OrderTombstone::Breaker())
+        );
+        assert_eq!(market.is_tradable(now + 60, 0), Ok(()));
+
+        // A fresh keeper update clears the staleness block
+        market.update_market_data(99_000, 101_000, 100_500, 100_000, now + 60);
+        assert_eq!(market.is_tradable(now + 60, 30), Ok(()));
+    }
+
+    #[test]
+    fn test_market_status_preserves_layout() {
+        use borsh::BorshSerialize;
+
+        let mut market = MarketStateV1::default();
+        let active_len = market.try_to_vec().unwrap().len();
+        market.status = MarketStatus::Halted;
+        assert_eq!(market.try_to_vec().unwrap().len(), active_len);
+
+        // Zeroed padding from older layouts decodes as Active
+        assert_eq!(MarketStatus::default(), MarketStatus::Active);
+        assert_eq!(MarketStatus::Active.try_to_vec().unwrap(), vec![0u8]);
+    }
+
     #[test]
     fn test_market_state_serialization() {
         use borsh::{BorshDeserialize, BorshSerialize};
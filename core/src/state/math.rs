@@ -1,28 +1,265 @@
 use solana_program::program_error::ProgramError;
 
-/// qty_raw (1e-8) × px_raw (1e-6)  →  notional_raw (1e-6)
+/// Quantities are in lots of 1e-8 base units
+pub const QTY_SCALE: u64 = 100_000_000;
+/// Prices are in 1e-6 quote units
+pub const PRICE_SCALE: u64 = 1_000_000;
+/// Denominator for basis-point values
+pub const BPS_DENOM: u64 = 10_000;
+/// Collateral amounts are always in 1e-6 units, regardless of the token's own decimals
+pub const COLLATERAL_DECIMALS: u32 = 6;
+
+/// qty_raw (1e-8) × px_raw (1e-6)  →  notional_raw (1e-6), truncating.
+///
+/// Use for values credited to the user (PnL, close notional) so rounding never overpays.
+/// Margin requirements (`calc_required_margin`, `calculate_uncommittable_amount`,
+/// `is_liquidatable`, `OrderDetails::cancel_release`) use `mul_qty_px_to_notional_round_up`.
 pub fn mul_qty_px_to_notional(qty: u64, px: u64) -> Result<u64, ProgramError> {
     let product = (qty as u128)
         .checked_mul(px as u128)
         .ok_or(ProgramError::ArithmeticOverflow)?;
 
     // Scale back to collateral space: divide by 1e8
-    let scaled = product / 100_000_000u128;
+    let scaled = product / QTY_SCALE as u128;
+    if scaled > u64::MAX as u128 {
+        return Err(ProgramError::ArithmeticOverflow);
+    }
+    Ok(scaled as u64)
+}
+
+/// Same as `mul_qty_px_to_notional` but rounds any remainder up, so a requirement
+/// computed from the notional is never understated by truncation.
+pub fn mul_qty_px_to_notional_round_up(qty: u64, px: u64) -> Result<u64, ProgramError> {
+    let product = (qty as u128)
+        .checked_mul(px as u128)
+        .ok_or(ProgramError::ArithmeticOverflow)?;
+
+    let scaled = product.div_ceil(QTY_SCALE as u128);
     if scaled > u64::MAX as u128 {
         return Err(ProgramError::ArithmeticOverflow);
     }
     Ok(scaled as u64)
 }
 
-/// Signed version: qty_signed × px_signed → PnL in collateral raw (1e-6)
+/// Inverse of `mul_qty_px_to_notional`: notional_raw (1e-6) / px_raw (1e-6) → qty_raw (1e-8),
+/// truncating. A zero price is rejected.
+pub fn qty_from_notional(notional: u64, px: u64) -> Result<u64, ProgramError> {
+    if px == 0 {
+        return Err(ProgramError::InvalidArgument);
+    }
+    let qty = notional as u128 * QTY_SCALE as u128 / px as u128;
+    u64::try_from(qty).map_err(|_| ProgramError::ArithmeticOverflow)
+}
+
+/// Signed version: qty_signed × px_signed → PnL in collateral raw (1e-6), truncating toward zero
 pub fn mul_qty_px_signed(qty: i64, px: i64) -> Result<i64, ProgramError> {
     let product = (qty as i128)
         .checked_mul(px as i128)
         .ok_or(ProgramError::ArithmeticOverflow)?;
 
-    let scaled = product / 100_000_000i128;
+    let scaled = product / QTY_SCALE as i128;
     if scaled > i64::MAX as i128 || scaled < i64::MIN as i128 {
         return Err(ProgramError::ArithmeticOverflow);
     }
     Ok(scaled as i64)
 }
+
+/// `value × bps / 10000`, truncating
+pub fn apply_bps(value: u64, bps: u16) -> Result<u64, ProgramError> {
+    value
+        .checked_mul(bps as u64)
+        .map(|x| x / BPS_DENOM)
+        .ok_or(ProgramError::ArithmeticOverflow)
+}
+
+/// Signed `value × bps / 10000`, truncating toward zero
+pub fn apply_bps_i64(value: i64, bps: u16) -> Result<i64, ProgramError> {
+    value
+        .checked_mul(bps as i64)
+        .map(|x| x / BPS_DENOM as i64)
+        .ok_or(ProgramError::ArithmeticOverflow)
+}
+
+/// Lots (1e-8) as a floating-point base quantity, for display only
+#[cfg(feature = "std")]
+pub fn lots_to_f64(lots: u64) -> f64 {
+    lots as f64 / QTY_SCALE as f64
+}
+
+/// Scaled price (1e-6) as a floating-point price, for display only
+#[cfg(feature = "std")]
+pub fn price_to_f64(px: u64) -> f64 {
+    px as f64 / PRICE_SCALE as f64
+}
+
+/// PnL for `qty_lots` (1e-8) over a price move of `price_diff_scaled` (1e-6), in collateral raw (1e-6).
+///
+/// The product is divided by 1e8 (truncating toward zero), so a position must be sizeable for
+/// a small price move to register:
+/// - 1 token (100_000_000 lots) × +$1 (1_000_000) → 1_000_000 ($1 profit)
+/// - 0.5 token (50_000_000 lots) × -$2 (-2_000_000) → -1_000_000 ($1 loss)
+/// - 100 lots × $0.02 (20_000) → 0 (2e-8 of a dollar truncates away)
+pub fn pnl_scaled(qty_lots: i64, price_diff_scaled: i64) -> Result<i64, ProgramError> {
+    mul_qty_px_signed(qty_lots, price_diff_scaled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scale_constants() {
+        assert_eq!(QTY_SCALE, 100_000_000);
+        assert_eq!(PRICE_SCALE, 1_000_000);
+        assert_eq!(BPS_DENOM, 10_000);
+        assert_eq!(10u64.pow(COLLATERAL_DECIMALS), PRICE_SCALE);
+        // One whole token at one whole price unit is one whole collateral unit
+        assert_eq!(
+            mul_qty_px_to_notional(QTY_SCALE, PRICE_SCALE).unwrap(),
+            10u64.pow(COLLATERAL_DECIMALS)
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_display_conversions_round_trip() {
+        assert_eq!(lots_to_f64(150_000_000), 1.5);
+        assert_eq!(price_to_f64(101_250_000), 101.25);
+
+        for lots in [0u64, 1, 150_000_000, 123_456_789_012] {
+            assert_eq!((lots_to_f64(lots) * QTY_SCALE as f64).round() as u64, lots);
+        }
+        for px in [0u64, 1, 101_250_000, 65_432_100_000] {
+            assert_eq!((price_to_f64(px) * PRICE_SCALE as f64).round() as u64, px);
+        }
+    }
+
+    #[test]
+    fn test_qty_from_notional() {
+        // $500 of exposure at $100 → 5 tokens
+        assert_eq!(
+            qty_from_notional(500_000_000, 100_000_000).unwrap(),
+            500_000_000
+        );
+        // Round trip through the forward conversion
+        let qty = qty_from_notional(1_234_567, 98_765_000).unwrap();
+        assert!(mul_qty_px_to_notional(qty, 98_765_000).unwrap() <= 1_234_567);
+
+        assert_eq!(
+            qty_from_notional(500_000_000, 0).unwrap_err(),
+            ProgramError::InvalidArgument
+        );
+
+        // notional × 1e8 overflows u64 but the result still fits
+        assert_eq!(qty_from_notional(u64::MAX, QTY_SCALE).unwrap(), u64::MAX);
+        // Result itself does not fit in u64
+        assert_eq!(
+            qty_from_notional(u64::MAX, 1).unwrap_err(),
+            ProgramError::ArithmeticOverflow
+        );
+    }
+
+    #[test]
+    fn test_pnl_scaled_whole_token() {
+        // 1 token up $1 → $1; down $1 → -$1
+        assert_eq!(pnl_scaled(100_000_000, 1_000_000).unwrap(), 1_000_000);
+        assert_eq!(pnl_scaled(100_000_000, -1_000_000).unwrap(), -1_000_000);
+        // Short sign convention is carried by the price diff, not the qty
+        assert_eq!(pnl_scaled(-100_000_000, 1_000_000).unwrap(), -1_000_000);
+    }
+
+    #[test]
+    fn test_pnl_scaled_fractional_and_large() {
+        // 0.5 token down $2 → -$1
+        assert_eq!(pnl_scaled(50_000_000, -2_000_000).unwrap(), -1_000_000);
+        // 250 tokens up $40 → $10_000
+        assert_eq!(
+            pnl_scaled(25_000_000_000, 40_000_000).unwrap(),
+            10_000_000_000
+        );
+    }
+
+    #[test]
+    fn test_pnl_scaled_truncates_dust() {
+        // 100 lots × $0.02 = 2e-8 dollars → 0 raw units
+        assert_eq!(pnl_scaled(100, 20_000).unwrap(), 0);
+        // Truncation is toward zero for losses too
+        assert_eq!(pnl_scaled(100, -20_000).unwrap(), 0);
+        // Smallest non-zero result: qty × diff must reach 1e8
+        assert_eq!(pnl_scaled(10_000, 10_000).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_notional_rounding_modes() {
+        // Exact multiples agree
+        assert_eq!(
+            mul_qty_px_to_notional(100_000_000, 1_000_000).unwrap(),
+            1_000_000
+        );
+        assert_eq!(
+            mul_qty_px_to_notional_round_up(100_000_000, 1_000_000).unwrap(),
+            1_000_000
+        );
+        assert_eq!(mul_qty_px_to_notional_round_up(0, 1_000_000).unwrap(), 0);
+
+        // Product just above a 1e8 boundary: 100_000_001 × 1 → 1.00000001
+        assert_eq!(mul_qty_px_to_notional(100_000_001, 1).unwrap(), 1);
+        assert_eq!(mul_qty_px_to_notional_round_up(100_000_001, 1).unwrap(), 2);
+
+        // Dust rounds up to one unit
+        assert_eq!(mul_qty_px_to_notional(1, 1).unwrap(), 0);
+        assert_eq!(mul_qty_px_to_notional_round_up(1, 1).unwrap(), 1);
+
+        assert_eq!(
+            mul_qty_px_to_notional_round_up(u64::MAX, u64::MAX).unwrap_err(),
+            ProgramError::ArithmeticOverflow
+        );
+    }
+
+    #[test]
+    fn test_apply_bps_identity_and_fractions() {
+        for x in [0u64, 1, 9_999, 10_000, 123_456_789, u64::MAX / 10_000] {
+            assert_eq!(apply_bps(x, 10_000).unwrap(), x);
+            assert_eq!(apply_bps(x, 0).unwrap(), 0);
+            assert!(apply_bps(x, 5_000).unwrap() <= x);
+        }
+        for x in [0i64, 1, -1, -9_999, 123_456_789, i64::MIN / 10_000] {
+            assert_eq!(apply_bps_i64(x, 10_000).unwrap(), x);
+            assert_eq!(apply_bps_i64(x, 0).unwrap(), 0);
+        }
+
+        assert_eq!(apply_bps(1_000_000, 250).unwrap(), 25_000);
+        assert_eq!(apply_bps(9_999, 1).unwrap(), 0);
+        // Signed results truncate toward zero
+        assert_eq!(apply_bps_i64(-1_000_000, 250).unwrap(), -25_000);
+        assert_eq!(apply_bps_i64(-9_999, 1).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_apply_bps_overflow() {
+        assert_eq!(
+            apply_bps(u64::MAX, 2).unwrap_err(),
+            ProgramError::ArithmeticOverflow
+        );
+        assert_eq!(
+            apply_bps(u64::MAX / 10_000 + 1, 10_000).unwrap_err(),
+            ProgramError::ArithmeticOverflow
+        );
+        assert_eq!(
+            apply_bps_i64(i64::MIN, 2).unwrap_err(),
+            ProgramError::ArithmeticOverflow
+        );
+        assert_eq!(
+            apply_bps_i64(i64::MAX, u16::MAX).unwrap_err(),
+            ProgramError::ArithmeticOverflow
+        );
+    }
+
+    #[test]
+    fn test_pnl_scaled_overflow() {
+        assert_eq!(
+            pnl_scaled(i64::MAX, i64::MAX).unwrap_err(),
+            ProgramError::ArithmeticOverflow
+        );
+    }
+}
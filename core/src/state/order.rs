@@ -1,5 +1,7 @@
+use crate::state::math::BPS_DENOM;
 use borsh::{BorshDeserialize, BorshSerialize};
 use solana_program::msg;
+use solana_program::program_error::ProgramError;
 use solana_program::pubkey::Pubkey;
 
 /// Order side enumeration
@@ -9,8 +11,56 @@ pub enum OrderSide {
     Ask = 1,
 }
 
+impl OrderSide {
+    /// The side a counterparty to this side trades on
+    pub fn opposite(self) -> OrderSide {
+        match self {
+            OrderSide::Bid => OrderSide::Ask,
+            OrderSide::Ask => OrderSide::Bid,
+        }
+    }
+
+    /// True for bids, i.e. fills that increase the net position
+    pub fn is_buy(self) -> bool {
+        self == OrderSide::Bid
+    }
+
+    /// Direction a fill on this side moves the net position: +1 for Bid, -1 for Ask
+    pub fn sign(self) -> i64 {
+        match self {
+            OrderSide::Bid => 1,
+            OrderSide::Ask => -1,
+        }
+    }
+}
+
+/// Wire side code used by instructions and permits: 0 = Bid, 1 = Ask
+impl TryFrom<u8> for OrderSide {
+    type Error = ProgramError;
+
+    fn try_from(code: u8) -> Result<Self, Self::Error> {
+        match code {
+            0 => Ok(OrderSide::Bid),
+            1 => Ok(OrderSide::Ask),
+            _ => {
+                msg!("Error: Invalid order side code {}", code);
+                Err(ProgramError::InvalidArgument)
+            }
+        }
+    }
+}
+
+impl From<OrderSide> for u8 {
+    fn from(side: OrderSide) -> u8 {
+        side as u8
+    }
+}
+
 /// Stored in OrderRegistry PDA: ["orders", market_id(8), bump]
-#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq, Eq)]
+///
+/// Orders by `user`, then `order_id` (then version/page). Every constructor zeroes `_pad1`,
+/// so equality, hashing and ordering only ever distinguish the meaningful fields.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct OrderMarker {
     pub user: Pubkey,       // 32 B
     pub order_id: u64,      // 8 B unique per user
@@ -41,6 +91,21 @@ impl OrderMarker {
     }
 }
 
+/// Derive an order id from the user, a client-side sequence number and the current slot,
+/// as the first 8 bytes of their SHA-256 hash. Collisions are unlikely but not impossible,
+/// so this is advisory only: the program still rejects an id already in use by the user.
+/// Never returns 0, which log entries use for "no order".
+pub fn derive_order_id(user: &Pubkey, client_seq: u64, slot: u64) -> u64 {
+    let digest = solana_program::hash::hashv(&[
+        user.as_ref(),
+        &client_seq.to_le_bytes(),
+        &slot.to_le_bytes(),
+    ]);
+    let mut id = [0u8; 8];
+    id.copy_from_slice(&digest.as_ref()[..8]);
+    u64::from_le_bytes(id).max(1)
+}
+
 /// Stored in user specific PDA: ["orders", market_id(8), user(32), order_id(64), bump]
 #[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
 pub struct OrderDetails {
@@ -58,9 +123,10 @@ pub struct OrderDetails {
     pub tombstone: OrderTombstone,
     pub event_history: EventHistory,
     pub builder_tag: BuilderTag,
-    pub _pad1: [u8; 64], // padding
-    pub _pad2: [u8; 32], // padding
-    pub _pad3: [u8; 24], // padding (total 120 bytes)
+    pub _pad1: [u8; 64],     // padding
+    pub client_id: [u8; 16], // client-supplied dedup id, u128 LE (0 = none); bytes keep align 8
+    pub _pad2: [u8; 16],     // padding
+    pub _pad3: [u8; 24],     // padding (total 104 bytes)
 }
 
 impl Default for OrderDetails {
@@ -81,7 +147,8 @@ impl Default for OrderDetails {
             event_history: EventHistory::default(),
             builder_tag: BuilderTag::default(),
             _pad1: [0; 64],
-            _pad2: [0; 32],
+            client_id: [0; 16],
+            _pad2: [0; 16],
             _pad3: [0; 24],
         }
     }
@@ -136,16 +203,114 @@ impl OrderDetails {
             event_history: EventHistory::default(),
             builder_tag: BuilderTag::default(),
             _pad1: [0; 64],
-            _pad2: [0; 32],
+            client_id: [0; 16],
+            _pad2: [0; 16],
             _pad3: [0; 24],
         }
     }
 
+    /// Client-supplied dedup id (0 = none)
+    pub fn client_id(&self) -> u128 {
+        u128::from_le_bytes(self.client_id)
+    }
+
+    pub fn set_client_id(&mut self, client_id: u128) {
+        self.client_id = client_id.to_le_bytes();
+    }
+
+    /// Recover the time-in-force `new` mapped into `cancel_cond`. Cancel conditions that no
+    /// TIF produces (price or order triggers) leave the order good-till-cancelled.
+    pub fn infer_tif(&self) -> TimeInForce {
+        match self.cancel_cond {
+            TriggerCondition::ImmediateOrCancelFail() => TimeInForce::IOC,
+            TriggerCondition::FillOrKillFail() => TimeInForce::FOK,
+            TriggerCondition::AddLiquidityOnlyFail() => TimeInForce::ALO,
+            TriggerCondition::Time(expiry) => TimeInForce::GTT(expiry),
+            _ => TimeInForce::GTC,
+        }
+    }
+
     /// Calculate unfilled quantity
     pub fn unfilled_qty(&self) -> u64 {
         self.qty.saturating_sub(self.filled_qty)
     }
 
+    /// True for a fill-or-kill order whose unfilled quantity can't be fully absorbed by
+    /// `available` opposing liquidity, i.e. submitting it would only get it killed.
+    pub fn fok_would_fail(&self, available: u64) -> bool {
+        self.cancel_cond == TriggerCondition::FillOrKillFail()
+            && !crate::fill_quote::can_fully_fill(self.unfilled_qty(), available)
+    }
+
+    /// Quantity an immediate-or-cancel order leaves behind after matching, which must be
+    /// cancelled rather than rest. Zero for non-IOC orders.
+    pub fn ioc_residual(&self) -> u64 {
+        if self.cancel_cond == TriggerCondition::ImmediateOrCancelFail() {
+            self.unfilled_qty()
+        } else {
+            0
+        }
+    }
+
+    /// Kill the residual of an IOC order after matching and return the quantity whose
+    /// collateral must be released. The IOC condition lives in `cancel_cond`, so the order is
+    /// tombstoned as `TriggerCancelCond1`. Fully filled and non-IOC orders are left untouched.
+    pub fn cancel_ioc_residual(&mut self, timestamp: i64) -> Result<u64, &'static str> {
+        if self.ioc_residual() == 0 || !self.tombstone.is_alive() {
+            return Ok(0);
+        }
+        let (residual, _) =
+            self.process_cancellation(&OrderTombstone::TriggerCancelCond1(), timestamp)?;
+        Ok(residual)
+    }
+
+    /// Check whether any cancel condition is a `Time` expiry that has been reached
+    pub fn should_cancel_for_time(&self, now_unix: i64) -> bool {
+        [&self.cancel_cond, &self.cancel_cond_2, &self.cancel_cond_3]
+            .into_iter()
+            .any(|cond| match cond {
+                TriggerCondition::Time(expiry) => now_unix >= 0 && now_unix as u64 >= *expiry,
+                _ => false,
+            })
+    }
+
+    /// Arm a dormant conditional order once its entry condition fires, moving it from
+    /// `PreTrigger` to `Open` and recording the trigger time. Returns whether it was armed;
+    /// orders that are already open are left alone.
+    pub fn try_arm(&mut self, refs: &PriceRefs, now_unix: i64) -> Result<bool, &'static str> {
+        if !self.tombstone.is_alive() {
+            return Err("Cannot arm a dead order");
+        }
+        if self.tombstone != OrderTombstone::PreTrigger() || !self.entry_cond.is_triggered(refs) {
+            return Ok(false);
+        }
+
+        self.tombstone = OrderTombstone::Open();
+        self.event_history.trigger_time = now_unix;
+        msg!("Order {} armed by entry condition", self.order_id);
+        Ok(true)
+    }
+
+    /// Margin to free when cancelling this order: initial margin on the unfilled quantity at
+    /// `mark_price`, rounded the same way as `MarginBucket::calc_required_margin`.
+    pub fn cancel_release(&self, mark_price: u64, im_bps: u16) -> Result<u64, ProgramError> {
+        let notional =
+            crate::state::math::mul_qty_px_to_notional_round_up(self.unfilled_qty(), mark_price)?;
+        crate::state::math::apply_bps(notional, im_bps)
+            .inspect_err(|_| msg!("Error: Overflow calculating cancel release"))
+    }
+
+    /// Validate the entry and cancel conditions against the market's supported price sources
+    pub fn validate_conditions(
+        &self,
+        market_supports: &[PriceReference],
+    ) -> Result<(), ProgramError> {
+        self.entry_cond.validate(market_supports)?;
+        self.cancel_cond.validate(market_supports)?;
+        self.cancel_cond_2.validate(market_supports)?;
+        self.cancel_cond_3.validate(market_supports)
+    }
+
     /// Process order cancellation, returning unfilled quantity and side
     pub fn process_cancellation(
         &mut self,
@@ -220,6 +385,7 @@ impl OrderDetails {
             weighted_avg_price,
             is_fully_filled,
             side: self.side,
+            is_market_order: matches!(self.price, OrderPrice::Market()),
         })
     }
 }
@@ -231,6 +397,7 @@ pub struct OrderFillResult {
     pub weighted_avg_price: u64,
     pub is_fully_filled: bool,
     pub side: OrderSide,
+    pub is_market_order: bool, // market orders are checked against the notional cap at fill
 }
 
 #[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Default, PartialEq)]
@@ -344,6 +511,37 @@ pub enum PriceReference {
 #[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
 pub enum PegPriceReference {
     OraclePrice(),
+    /// Best bid; the peg offset is measured in ticks
+    BidPrice(),
+    /// Best ask; the peg offset is measured in ticks
+    AskPrice(),
+}
+
+/// Snapshot of the price sources conditions can reference. Zero means unavailable.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PriceRefs {
+    pub mark: u64,
+    pub oracle: u64,
+    pub spot: u64,
+    pub bid: u64,
+    pub ask: u64,
+    pub mid: u64,
+    pub last_trade: u64,
+}
+
+impl PriceRefs {
+    /// Look up the price for a trigger reference
+    pub fn get(&self, reference: &PriceReference) -> u64 {
+        match reference {
+            PriceReference::MarkPrice() => self.mark,
+            PriceReference::OraclePrice() => self.oracle,
+            PriceReference::SpotPrice() => self.spot,
+            PriceReference::BidPrice() => self.bid,
+            PriceReference::AskPrice() => self.ask,
+            PriceReference::MidPrice() => self.mid,
+            PriceReference::LastTradePrice() => self.last_trade,
+        }
+    }
 }
 
 #[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
@@ -353,6 +551,21 @@ pub enum TriggerEntrySize {
     FixedSize(u64),
 }
 
+impl TriggerEntrySize {
+    /// Quantity an order enters with once triggered. Percentages are in bps (capped at
+    /// 10000) of `|net_position|` or `order_qty`; `FixedSize` is taken as-is.
+    pub fn resolve_qty(&self, net_position: i64, order_qty: u64) -> u64 {
+        let (base, pct_bps) = match self {
+            TriggerEntrySize::PositionSizePercent(pct) => (net_position.unsigned_abs(), *pct),
+            TriggerEntrySize::OrderSizePercent(pct) => (order_qty, *pct),
+            TriggerEntrySize::FixedSize(qty) => return *qty,
+        };
+        // u128 so a full-size position cannot overflow; the result is at most `base`
+        let pct_bps = (pct_bps as u64).min(BPS_DENOM);
+        (base as u128 * pct_bps as u128 / BPS_DENOM as u128) as u64
+    }
+}
+
 /// Time in Force order types
 #[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq, Default)]
 pub enum TimeInForce {
@@ -369,6 +582,38 @@ pub enum TimeInForce {
     GTT(u64),
 }
 
+/// One-line human summary for logs, e.g. `#7 Bid 0.5/2 @ Limit 101.25 Open GTC`
+#[cfg(feature = "std")]
+impl std::fmt::Display for OrderDetails {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use crate::state::math::{lots_to_f64, price_to_f64};
+
+        write!(
+            f,
+            "#{} {:?} {}/{} @ ",
+            self.order_id,
+            self.side,
+            lots_to_f64(self.filled_qty),
+            lots_to_f64(self.qty)
+        )?;
+        match &self.price {
+            OrderPrice::Market() => write!(f, "Market")?,
+            OrderPrice::Limit(px) => write!(f, "Limit {}", price_to_f64(*px))?,
+            OrderPrice::PeggedOffset(offset, reference) => {
+                write!(f, "Peg {:?}{:+}", reference, offset)?
+            }
+        }
+
+        let tombstone = format!("{:?}", self.tombstone);
+        write!(
+            f,
+            " {} {:?}",
+            tombstone.trim_end_matches("()"),
+            self.infer_tif()
+        )
+    }
+}
+
 impl OrderTombstone {
     /// Check if the tombstone represents an alive order
     pub fn is_alive(&self) -> bool {
@@ -382,33 +627,183 @@ impl OrderTombstone {
     pub fn is_valid_cancellation(&self) -> bool {
         !self.is_alive()
     }
+
+    /// Order was refused at entry and never rested on the book
+    pub fn is_rejection(&self) -> bool {
+        matches!(
+            self,
+            OrderTombstone::SelfTrade()
+                | OrderTombstone::TickRejected()
+                | OrderTombstone::PriceBandRejected()
+                | OrderTombstone::MinTradeRejected()
+                | OrderTombstone::OpenInterestCap()
+                | OrderTombstone::MaxPositionRejected()
+                | OrderTombstone::MaxOrderSizeRejected()
+                | OrderTombstone::CancelOnEntrySizing()
+                | OrderTombstone::InvalidPrice()
+                | OrderTombstone::InvalidQty()
+                | OrderTombstone::InvalidCond()
+        )
+    }
+
+    /// Order was ended by the protocol (risk, market state, admin) rather than its owner
+    pub fn is_system_cancel(&self) -> bool {
+        matches!(
+            self,
+            OrderTombstone::LiquidatorMargin()
+                | OrderTombstone::AutoDeleverage()
+                | OrderTombstone::SystemHalt()
+                | OrderTombstone::Breaker()
+                | OrderTombstone::PositionLimits()
+                | OrderTombstone::MarketClosed()
+                | OrderTombstone::ForceExpire()
+                | OrderTombstone::Admin()
+                | OrderTombstone::Error()
+        )
+    }
+
+    /// Order was ended by its owner, either directly or through a cancel condition they set
+    pub fn is_user_cancel(&self) -> bool {
+        matches!(
+            self,
+            OrderTombstone::UserCancel()
+                | OrderTombstone::TriggerCancelCond1()
+                | OrderTombstone::TriggerCancelCond2()
+                | OrderTombstone::TriggerCancelCond3()
+        )
+    }
+}
+
+impl OrderPrice {
+    /// Peg `ticks_inside` ticks inside the opposite touch: a bid rests below the best
+    /// ask, an ask rests above the best bid. Negative values peg further away.
+    pub fn pegged_at_touch(side: OrderSide, ticks_inside: i64) -> OrderPrice {
+        match side {
+            OrderSide::Bid => OrderPrice::PeggedOffset(
+                ticks_inside.saturating_neg(),
+                PegPriceReference::AskPrice(),
+            ),
+            OrderSide::Ask => OrderPrice::PeggedOffset(ticks_inside, PegPriceReference::BidPrice()),
+        }
+    }
+
+    /// Effective limit price against current prices: `Some` for limits, `None` for market
+    /// orders, and `oracle + offset` (saturating at 0) for oracle pegs. Touch pegs are
+    /// measured in ticks and resolve through `resolve_touch_peg` instead, so they return
+    /// `None` here, as does an oracle peg while the oracle price is unavailable.
+    pub fn resolve(&self, refs: &PriceRefs) -> Option<u64> {
+        match self {
+            OrderPrice::Limit(px) => Some(*px),
+            OrderPrice::Market() => None,
+            OrderPrice::PeggedOffset(offset, PegPriceReference::OraclePrice()) => {
+                if refs.oracle == 0 {
+                    return None;
+                }
+                Some(refs.oracle.saturating_add_signed(*offset))
+            }
+            OrderPrice::PeggedOffset(_, _) => None,
+        }
+    }
+
+    /// Resolve a touch-relative peg against the current book. Limit prices pass through;
+    /// market orders, oracle pegs, and pegs that land at or below zero resolve to `None`.
+    pub fn resolve_touch_peg(&self, best_bid: u64, best_ask: u64, tick_size: u64) -> Option<u64> {
+        match self {
+            OrderPrice::Limit(px) => Some(*px),
+            OrderPrice::Market() => None,
+            OrderPrice::PeggedOffset(offset_ticks, reference) => {
+                let touch = match reference {
+                    PegPriceReference::BidPrice() => best_bid,
+                    PegPriceReference::AskPrice() => best_ask,
+                    PegPriceReference::OraclePrice() => return None,
+                };
+                if touch == 0 {
+                    return None;
+                }
+                let offset =
+                    (offset_ticks.unsigned_abs() as u128).checked_mul(tick_size as u128)?;
+                let px = if *offset_ticks >= 0 {
+                    (touch as u128).checked_add(offset)?
+                } else {
+                    (touch as u128).checked_sub(offset)?
+                };
+                match u64::try_from(px) {
+                    Ok(px) if px > 0 => Some(px),
+                    _ => None,
+                }
+            }
+        }
+    }
+}
+
+impl TriggerCondition {
+    /// Reject price triggers whose reference is not a price source the market provides,
+    /// since such a condition would silently never fire.
+    pub fn validate(&self, market_supports: &[PriceReference]) -> Result<(), ProgramError> {
+        match self {
+            TriggerCondition::PriceBelow(_, reference)
+            | TriggerCondition::PriceAbove(_, reference)
+                if !market_supports.contains(reference) =>
+            {
+                msg!(
+                    "Error: Trigger condition references unsupported price source {:?}",
+                    reference
+                );
+                Err(ProgramError::InvalidArgument)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Evaluate a price trigger against current prices. Fires when the referenced price is
+    /// at or below (`PriceBelow`) or at or above (`PriceAbove`) the threshold. An unavailable
+    /// (zero) reference price never fires; `Off` and non-price conditions return false.
+    pub fn is_triggered(&self, refs: &PriceRefs) -> bool {
+        match self {
+            TriggerCondition::PriceBelow(threshold, reference) => {
+                let px = refs.get(reference);
+                px != 0 && px <= *threshold
+            }
+            TriggerCondition::PriceAbove(threshold, reference) => {
+                let px = refs.get(reference);
+                px != 0 && px >= *threshold
+            }
+            _ => false,
+        }
+    }
 }
 
 /// Total number of padding bytes reserved in OrderDetails struct
-pub const ORDER_DETAILS_RESERVED_PADDING: usize = 64 + 32 + 24;
+pub const ORDER_DETAILS_RESERVED_PADDING: usize = 64 + 16 + 24;
 
-/// Compute the maximum possible Borsh-serialized size of OrderDetails using the largest variant payloads
-pub fn max_order_details_borsh_size() -> usize {
-    let fat = OrderDetails {
+/// OrderDetails with the largest payload in every variant field, i.e. the longest Borsh encoding
+pub fn max_order_details() -> OrderDetails {
+    OrderDetails {
         order_id: u64::MAX,
         side: OrderSide::Ask,
         qty: u64::MAX,
         filled_qty: u64::MAX,
         price: OrderPrice::PeggedOffset(i64::MAX, PegPriceReference::OraclePrice()),
         origin: OrderOriginator::VariantPlaceholder([0xff; 16]),
-        entry_cond: TriggerCondition::OrderPartialFill(u64::MAX, u16::MAX),
+        entry_cond: TriggerCondition::VariantPlaceholder([0xff; 16]),
         entry_cond_size: TriggerEntrySize::FixedSize(u64::MAX),
-        cancel_cond: TriggerCondition::OrderPartialFill(u64::MAX, u16::MAX),
-        cancel_cond_2: TriggerCondition::OrderPartialFill(u64::MAX, u16::MAX),
-        cancel_cond_3: TriggerCondition::OrderPartialFill(u64::MAX, u16::MAX),
+        cancel_cond: TriggerCondition::VariantPlaceholder([0xff; 16]),
+        cancel_cond_2: TriggerCondition::VariantPlaceholder([0xff; 16]),
+        cancel_cond_3: TriggerCondition::VariantPlaceholder([0xff; 16]),
         tombstone: OrderTombstone::Error(),
         event_history: EventHistory::default(),
         builder_tag: BuilderTag::default(),
         _pad1: [0u8; 64],
-        _pad2: [0u8; 32],
+        client_id: [0xff; 16],
+        _pad2: [0u8; 16],
         _pad3: [0u8; 24],
-    };
-    fat.try_to_vec()
+    }
+}
+
+/// Compute the maximum possible Borsh-serialized size of OrderDetails using the largest variant payloads
+pub fn max_order_details_borsh_size() -> usize {
+    max_order_details()
+        .try_to_vec()
         .expect("Borsh should serialize OrderDetails")
         .len()
 }
@@ -427,6 +822,14 @@ mod padding_tests {
             (max_len - default_len)
         );
     }
+
+    #[test]
+    fn client_id_keeps_in_memory_layout() {
+        // Storage growth estimates use size_of::<OrderDetails>(); a u128 field would
+        // raise the alignment to 16 and grow the struct
+        assert_eq!(core::mem::align_of::<OrderDetails>(), 8);
+        assert_eq!(core::mem::size_of::<OrderDetails>(), 504);
+    }
 }
 
 #[cfg(test)]
@@ -448,6 +851,58 @@ mod tests {
         assert_eq!(marker._pad1, [0; 2]);
     }
 
+    #[test]
+    fn test_order_side_helpers() {
+        assert_eq!(OrderSide::Bid.opposite(), OrderSide::Ask);
+        assert_eq!(OrderSide::Ask.opposite(), OrderSide::Bid);
+        assert_eq!(OrderSide::Bid.opposite().opposite(), OrderSide::Bid);
+
+        assert!(OrderSide::Bid.is_buy());
+        assert!(!OrderSide::Ask.is_buy());
+
+        assert_eq!(OrderSide::Bid.sign(), 1);
+        assert_eq!(OrderSide::Ask.sign(), -1);
+        assert_eq!(OrderSide::Ask.opposite().sign(), -OrderSide::Ask.sign());
+    }
+
+    #[test]
+    fn test_order_side_u8_conversion() {
+        assert_eq!(OrderSide::try_from(0u8), Ok(OrderSide::Bid));
+        assert_eq!(OrderSide::try_from(1u8), Ok(OrderSide::Ask));
+        assert_eq!(OrderSide::try_from(2u8), Err(ProgramError::InvalidArgument));
+
+        assert_eq!(u8::from(OrderSide::Bid), 0);
+        assert_eq!(u8::from(OrderSide::Ask), 1);
+    }
+
+    #[test]
+    fn test_order_marker_set_indexes() {
+        use std::collections::{BTreeSet, HashSet};
+
+        let alice = Pubkey::new_from_array([1; 32]);
+        let bob = Pubkey::new_from_array([2; 32]);
+        let markers = [
+            OrderMarker::new(bob, 1),
+            OrderMarker::new(alice, 9),
+            OrderMarker::new(alice, 2),
+            OrderMarker::new(bob, 1),
+            OrderMarker::new(alice, 9),
+        ];
+
+        let hashed: HashSet<OrderMarker> = markers.iter().cloned().collect();
+        assert_eq!(hashed.len(), 3);
+        assert!(hashed.contains(&OrderMarker::new(alice, 2)));
+
+        let ordered: Vec<(Pubkey, u64)> = markers
+            .iter()
+            .cloned()
+            .collect::<BTreeSet<OrderMarker>>()
+            .into_iter()
+            .map(|m| (m.user, m.order_id))
+            .collect();
+        assert_eq!(ordered, vec![(alice, 2), (alice, 9), (bob, 1)]);
+    }
+
     #[test]
     fn test_tombstone_is_alive() {
         // Test alive states
@@ -498,6 +953,75 @@ mod tests {
         assert!(OrderTombstone::ForceExpire().is_valid_cancellation());
     }
 
+    #[test]
+    fn test_tombstone_classification() {
+        // (tombstone, is_rejection, is_system_cancel, is_user_cancel)
+        let cases = [
+            (OrderTombstone::Empty(), false, false, false),
+            (OrderTombstone::PreTrigger(), false, false, false),
+            (OrderTombstone::Open(), false, false, false),
+            (OrderTombstone::Filled(), false, false, false),
+            (OrderTombstone::UserCancel(), false, false, true),
+            (OrderTombstone::TriggerCancelCond1(), false, false, true),
+            (OrderTombstone::TriggerCancelCond2(), false, false, true),
+            (OrderTombstone::TriggerCancelCond3(), false, false, true),
+            (OrderTombstone::LiquidatorMargin(), false, true, false),
+            (OrderTombstone::AutoDeleverage(), false, true, false),
+            (OrderTombstone::SystemHalt(), false, true, false),
+            (OrderTombstone::Breaker(), false, true, false),
+            (OrderTombstone::PositionLimits(), false, true, false),
+            (OrderTombstone::MarketClosed(), false, true, false),
+            (OrderTombstone::SelfTrade(), true, false, false),
+            (OrderTombstone::TickRejected(), true, false, false),
+            (OrderTombstone::PriceBandRejected(), true, false, false),
+            (OrderTombstone::MinTradeRejected(), true, false, false),
+            (OrderTombstone::OpenInterestCap(), true, false, false),
+            (OrderTombstone::MaxPositionRejected(), true, false, false),
+            (OrderTombstone::MaxOrderSizeRejected(), true, false, false),
+            (OrderTombstone::CancelOnEntrySizing(), true, false, false),
+            (OrderTombstone::InvalidPrice(), true, false, false),
+            (OrderTombstone::InvalidQty(), true, false, false),
+            (OrderTombstone::InvalidCond(), true, false, false),
+            (OrderTombstone::ForceExpire(), false, true, false),
+            (OrderTombstone::Admin(), false, true, false),
+            (OrderTombstone::Error(), false, true, false),
+        ];
+
+        for (tombstone, rejection, system, user) in cases {
+            assert_eq!(tombstone.is_rejection(), rejection, "{:?}", tombstone);
+            assert_eq!(tombstone.is_system_cancel(), system, "{:?}", tombstone);
+            assert_eq!(tombstone.is_user_cancel(), user, "{:?}", tombstone);
+            // Live orders and fills are in no bucket; every other end state is in exactly one
+            let buckets = [rejection, system, user].iter().filter(|b| **b).count();
+            let expected = if tombstone.is_alive() || tombstone == OrderTombstone::Filled() {
+                0
+            } else {
+                1
+            };
+            assert_eq!(buckets, expected, "{:?}", tombstone);
+        }
+    }
+
+    #[test]
+    fn test_derive_order_id() {
+        let user = Pubkey::new_unique();
+        let id = derive_order_id(&user, 7, 1_000);
+        assert_eq!(id, derive_order_id(&user, 7, 1_000));
+        assert_ne!(id, 0);
+
+        assert_ne!(id, derive_order_id(&user, 8, 1_000));
+        assert_ne!(id, derive_order_id(&user, 7, 1_001));
+        assert_ne!(id, derive_order_id(&Pubkey::new_unique(), 7, 1_000));
+        // Sequence and slot are not interchangeable
+        assert_ne!(derive_order_id(&user, 1, 2), derive_order_id(&user, 2, 1));
+
+        let ids: std::collections::HashSet<u64> = (0..100)
+            .flat_map(|seq| (0..10).map(move |slot| (seq, slot)))
+            .map(|(seq, slot)| derive_order_id(&user, seq, slot))
+            .collect();
+        assert_eq!(ids.len(), 1_000);
+    }
+
     #[test]
     fn test_order_marker_default() {
         let marker = OrderMarker::default();
@@ -650,6 +1174,98 @@ mod tests {
         assert_eq!(order_details.cancel_cond, TriggerCondition::Off());
     }
 
+    #[test]
+    fn test_pegged_at_touch_bid_one_tick_inside_ask() {
+        let price = OrderPrice::pegged_at_touch(OrderSide::Bid, 1);
+        assert_eq!(
+            price,
+            OrderPrice::PeggedOffset(-1, PegPriceReference::AskPrice())
+        );
+
+        // Book 99.00 / 101.00 with a 0.50 tick: one tick inside the ask is 100.50
+        assert_eq!(price.resolve_touch_peg(99_000, 101_000, 500), Some(100_500));
+    }
+
+    #[test]
+    fn test_pegged_at_touch_ask_and_edge_cases() {
+        let ask = OrderPrice::pegged_at_touch(OrderSide::Ask, 2);
+        assert_eq!(
+            ask,
+            OrderPrice::PeggedOffset(2, PegPriceReference::BidPrice())
+        );
+        assert_eq!(ask.resolve_touch_peg(99_000, 101_000, 500), Some(100_000));
+
+        // Negative ticks peg behind the touch
+        let behind = OrderPrice::pegged_at_touch(OrderSide::Bid, -3);
+        assert_eq!(
+            behind.resolve_touch_peg(99_000, 101_000, 500),
+            Some(102_500)
+        );
+
+        // Empty opposite side or a peg through zero does not resolve
+        let bid = OrderPrice::pegged_at_touch(OrderSide::Bid, 1);
+        assert_eq!(bid.resolve_touch_peg(99_000, 0, 500), None);
+        assert_eq!(bid.resolve_touch_peg(0, 500, 500), None);
+        assert_eq!(bid.resolve_touch_peg(0, 400, 500), None);
+
+        // Non-touch prices
+        assert_eq!(
+            OrderPrice::Limit(12_345).resolve_touch_peg(1, 2, 1),
+            Some(12_345)
+        );
+        assert_eq!(OrderPrice::Market().resolve_touch_peg(1, 2, 1), None);
+        assert_eq!(
+            OrderPrice::PeggedOffset(0, PegPriceReference::OraclePrice())
+                .resolve_touch_peg(1, 2, 1),
+            None
+        );
+    }
+
+    #[test]
+    fn test_trigger_condition_validate_supported_reference() {
+        let supports = [PriceReference::MarkPrice(), PriceReference::OraclePrice()];
+
+        assert!(
+            TriggerCondition::PriceBelow(100_000, PriceReference::MarkPrice())
+                .validate(&supports)
+                .is_ok()
+        );
+        assert!(
+            TriggerCondition::PriceAbove(100_000, PriceReference::OraclePrice())
+                .validate(&supports)
+                .is_ok()
+        );
+        // Non-price conditions do not reference a price source
+        assert!(TriggerCondition::Off().validate(&[]).is_ok());
+        assert!(TriggerCondition::Time(1_700_000_000).validate(&[]).is_ok());
+    }
+
+    #[test]
+    fn test_trigger_condition_validate_unsupported_reference() {
+        let supports = [PriceReference::MarkPrice(), PriceReference::OraclePrice()];
+
+        assert_eq!(
+            TriggerCondition::PriceBelow(100_000, PriceReference::SpotPrice())
+                .validate(&supports)
+                .unwrap_err(),
+            ProgramError::InvalidArgument
+        );
+
+        let mut order = OrderDetails::new(
+            1,
+            OrderSide::Ask,
+            1_000,
+            OrderPrice::Limit(100_000),
+            TimeInForce::GTC,
+        );
+        assert!(order.validate_conditions(&supports).is_ok());
+        order.cancel_cond_2 = TriggerCondition::PriceAbove(120_000, PriceReference::SpotPrice());
+        assert_eq!(
+            order.validate_conditions(&supports).unwrap_err(),
+            ProgramError::InvalidArgument
+        );
+    }
+
     #[test]
     fn test_order_details_default_fields() {
         let order_id = 88888u64;
@@ -674,7 +1290,8 @@ mod tests {
         assert_eq!(order_details.event_history, EventHistory::default());
         assert_eq!(order_details.builder_tag, BuilderTag::default());
         assert_eq!(order_details._pad1, [0; 64]);
-        assert_eq!(order_details._pad2, [0; 32]);
+        assert_eq!(order_details.client_id(), 0);
+        assert_eq!(order_details._pad2, [0; 16]);
         assert_eq!(order_details._pad3, [0; 24]);
     }
 
@@ -1082,4 +1699,335 @@ mod tests {
         // Verify tombstone is updated
         assert_eq!(order.tombstone, OrderTombstone::UserCancel());
     }
+
+    #[test]
+    fn test_trigger_condition_price_reference_mapping() {
+        let refs = PriceRefs {
+            mark: 100,
+            oracle: 200,
+            spot: 300,
+            bid: 400,
+            ask: 500,
+            mid: 600,
+            last_trade: 700,
+        };
+        let cases = [
+            (PriceReference::MarkPrice(), 100),
+            (PriceReference::OraclePrice(), 200),
+            (PriceReference::SpotPrice(), 300),
+            (PriceReference::BidPrice(), 400),
+            (PriceReference::AskPrice(), 500),
+            (PriceReference::MidPrice(), 600),
+            (PriceReference::LastTradePrice(), 700),
+        ];
+        for (reference, px) in cases {
+            assert_eq!(refs.get(&reference), px);
+            assert!(TriggerCondition::PriceAbove(px, reference.clone()).is_triggered(&refs));
+            assert!(!TriggerCondition::PriceAbove(px + 1, reference.clone()).is_triggered(&refs));
+            assert!(TriggerCondition::PriceBelow(px, reference.clone()).is_triggered(&refs));
+            assert!(!TriggerCondition::PriceBelow(px - 1, reference).is_triggered(&refs));
+        }
+    }
+
+    #[test]
+    fn test_trigger_condition_non_price_never_triggers() {
+        let refs = PriceRefs {
+            mark: 100,
+            ..Default::default()
+        };
+        assert!(!TriggerCondition::Off().is_triggered(&refs));
+        assert!(!TriggerCondition::Time(0).is_triggered(&refs));
+        assert!(!TriggerCondition::ReduceOnlyFail().is_triggered(&refs));
+
+        // Missing reference price does not fire a below trigger
+        assert!(
+            !TriggerCondition::PriceBelow(1_000, PriceReference::OraclePrice()).is_triggered(&refs)
+        );
+    }
+
+    #[test]
+    fn test_order_price_resolve() {
+        let refs = PriceRefs {
+            oracle: 50_000,
+            bid: 49_990,
+            ask: 50_010,
+            ..Default::default()
+        };
+
+        assert_eq!(OrderPrice::Limit(48_000).resolve(&refs), Some(48_000));
+        assert_eq!(OrderPrice::Market().resolve(&refs), None);
+
+        let above = OrderPrice::PeggedOffset(250, PegPriceReference::OraclePrice());
+        assert_eq!(above.resolve(&refs), Some(50_250));
+        let below = OrderPrice::PeggedOffset(-250, PegPriceReference::OraclePrice());
+        assert_eq!(below.resolve(&refs), Some(49_750));
+
+        // Underflow saturates at zero
+        let underflow = OrderPrice::PeggedOffset(-60_000, PegPriceReference::OraclePrice());
+        assert_eq!(underflow.resolve(&refs), Some(0));
+        let extreme = OrderPrice::PeggedOffset(i64::MIN, PegPriceReference::OraclePrice());
+        assert_eq!(extreme.resolve(&refs), Some(0));
+
+        // Touch pegs and missing oracle prices do not resolve here
+        assert_eq!(
+            OrderPrice::pegged_at_touch(OrderSide::Bid, 1).resolve(&refs),
+            None
+        );
+        assert_eq!(above.resolve(&PriceRefs::default()), None);
+    }
+
+    #[test]
+    fn test_try_arm() {
+        let mut order = OrderDetails::new(
+            1,
+            OrderSide::Ask,
+            1_000_000,
+            OrderPrice::Market(),
+            TimeInForce::IOC,
+        );
+        order.tombstone = OrderTombstone::PreTrigger();
+        order.entry_cond = TriggerCondition::PriceBelow(45_000, PriceReference::MarkPrice());
+
+        // Mark above the stop: stays dormant
+        let refs = PriceRefs {
+            mark: 46_000,
+            ..Default::default()
+        };
+        assert_eq!(order.try_arm(&refs, 1_000), Ok(false));
+        assert_eq!(order.tombstone, OrderTombstone::PreTrigger());
+        assert_eq!(order.event_history.trigger_time, 0);
+
+        // Mark crosses the stop: order opens
+        let refs = PriceRefs {
+            mark: 44_900,
+            ..Default::default()
+        };
+        assert_eq!(order.try_arm(&refs, 2_000), Ok(true));
+        assert_eq!(order.tombstone, OrderTombstone::Open());
+        assert_eq!(order.event_history.trigger_time, 2_000);
+
+        // Already open: nothing further to do
+        assert_eq!(order.try_arm(&refs, 3_000), Ok(false));
+        assert_eq!(order.event_history.trigger_time, 2_000);
+
+        order.tombstone = OrderTombstone::UserCancel();
+        assert!(order.try_arm(&refs, 4_000).is_err());
+    }
+
+    #[test]
+    fn test_trigger_entry_size_resolve_qty() {
+        // Position percent uses the absolute net position
+        let half_position = TriggerEntrySize::PositionSizePercent(5_000);
+        assert_eq!(half_position.resolve_qty(-3_000_000, 999), 1_500_000);
+        assert_eq!(half_position.resolve_qty(3_000_000, 999), 1_500_000);
+        assert_eq!(half_position.resolve_qty(i64::MIN, 0), 1u64 << 62);
+
+        // Order percent uses the order quantity
+        let quarter_order = TriggerEntrySize::OrderSizePercent(2_500);
+        assert_eq!(quarter_order.resolve_qty(5_000_000, 2_000_000), 500_000);
+
+        // Percent is clamped at 100%
+        assert_eq!(
+            TriggerEntrySize::OrderSizePercent(u16::MAX).resolve_qty(0, 2_000_000),
+            2_000_000
+        );
+
+        // 0% yields nothing
+        assert_eq!(
+            TriggerEntrySize::PositionSizePercent(0).resolve_qty(5_000_000, 1),
+            0
+        );
+        assert_eq!(
+            TriggerEntrySize::OrderSizePercent(0).resolve_qty(1, 5_000_000),
+            0
+        );
+
+        assert_eq!(
+            TriggerEntrySize::FixedSize(750_000).resolve_qty(5_000_000, 1),
+            750_000
+        );
+    }
+
+    #[test]
+    fn test_cancel_release_half_filled() {
+        let mut order = OrderDetails::new(
+            1,
+            OrderSide::Bid,
+            2_000_000,
+            OrderPrice::Limit(100_000_000),
+            TimeInForce::GTC,
+        );
+        order
+            .process_fill(1_000_000, 100_000_000, 1_000)
+            .expect("half fill should succeed");
+
+        // 1_000_000 unfilled * 100_000_000 / 1e8 = 1_000_000 notional; 10% IM = 100_000
+        assert_eq!(order.cancel_release(100_000_000, 1_000).unwrap(), 100_000);
+
+        let bucket =
+            crate::MarginBucket::new(crate::MarginScope::MarketIsolated(1), Pubkey::new_unique());
+        assert_eq!(
+            order.cancel_release(123_456_789, 750).unwrap(),
+            bucket
+                .calc_required_margin(123_456_789, 750, order.unfilled_qty())
+                .unwrap()
+        );
+
+        assert_eq!(
+            order.cancel_release(u64::MAX, 10_000),
+            Err(ProgramError::ArithmeticOverflow)
+        );
+    }
+
+    #[test]
+    fn test_fok_would_fail_boundary() {
+        let mut fok = OrderDetails::new(
+            1,
+            OrderSide::Bid,
+            2_000_000,
+            OrderPrice::Limit(100_000_000),
+            TimeInForce::FOK,
+        );
+        assert!(!fok.fok_would_fail(2_000_000));
+        assert!(fok.fok_would_fail(1_999_999));
+
+        // Only the unfilled remainder has to be available
+        fok.process_fill(500_000, 100_000_000, 1_000).unwrap();
+        assert!(!fok.fok_would_fail(1_500_000));
+        assert!(fok.fok_would_fail(1_499_999));
+
+        let gtc = OrderDetails::new(
+            2,
+            OrderSide::Bid,
+            2_000_000,
+            OrderPrice::Limit(100_000_000),
+            TimeInForce::GTC,
+        );
+        assert!(!gtc.fok_would_fail(0));
+    }
+
+    #[test]
+    fn test_ioc_residual_partially_filled() {
+        let mut ioc = OrderDetails::new(
+            1,
+            OrderSide::Ask,
+            3_000_000,
+            OrderPrice::Limit(100_000_000),
+            TimeInForce::IOC,
+        );
+        ioc.process_fill(1_000_000, 100_000_000, 1_000).unwrap();
+        assert_eq!(ioc.ioc_residual(), 2_000_000);
+
+        assert_eq!(ioc.cancel_ioc_residual(1_001), Ok(2_000_000));
+        assert_eq!(ioc.tombstone, OrderTombstone::TriggerCancelCond1());
+        assert_eq!(ioc.event_history.dead_time, 1_001);
+        // Already dead: nothing further to release
+        assert_eq!(ioc.cancel_ioc_residual(1_002), Ok(0));
+    }
+
+    #[test]
+    fn test_ioc_residual_fully_filled() {
+        let mut ioc = OrderDetails::new(
+            1,
+            OrderSide::Bid,
+            3_000_000,
+            OrderPrice::Limit(100_000_000),
+            TimeInForce::IOC,
+        );
+        ioc.process_fill(3_000_000, 100_000_000, 1_000).unwrap();
+        assert_eq!(ioc.ioc_residual(), 0);
+        assert_eq!(ioc.cancel_ioc_residual(1_001), Ok(0));
+        assert_eq!(ioc.tombstone, OrderTombstone::Filled());
+
+        // Non-IOC orders never have a residual to kill
+        let mut gtc = OrderDetails::new(
+            2,
+            OrderSide::Bid,
+            3_000_000,
+            OrderPrice::Limit(100_000_000),
+            TimeInForce::GTC,
+        );
+        assert_eq!(gtc.ioc_residual(), 0);
+        assert_eq!(gtc.cancel_ioc_residual(1_001), Ok(0));
+        assert_eq!(gtc.tombstone, OrderTombstone::Open());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_order_details_display() {
+        let mut order = OrderDetails::new(
+            7,
+            OrderSide::Bid,
+            200_000_000,
+            OrderPrice::Limit(101_250_000),
+            TimeInForce::GTC,
+        );
+        order.process_fill(50_000_000, 101_250_000, 1_000).unwrap();
+        assert_eq!(order.to_string(), "#7 Bid 0.5/2 @ Limit 101.25 Open GTC");
+
+        let ioc = OrderDetails::new(
+            8,
+            OrderSide::Ask,
+            100_000_000,
+            OrderPrice::Market(),
+            TimeInForce::IOC,
+        );
+        assert_eq!(ioc.to_string(), "#8 Ask 0/1 @ Market Open IOC");
+    }
+
+    #[test]
+    fn test_infer_tif_round_trip() {
+        for tif in [
+            TimeInForce::IOC,
+            TimeInForce::FOK,
+            TimeInForce::GTC,
+            TimeInForce::ALO,
+            TimeInForce::GTT(1_700_000_000),
+        ] {
+            let order = OrderDetails::new(
+                1,
+                OrderSide::Bid,
+                1_000_000,
+                OrderPrice::Limit(100_000),
+                tif.clone(),
+            );
+            assert_eq!(order.infer_tif(), tif);
+        }
+    }
+
+    #[test]
+    fn test_should_cancel_for_time() {
+        let expiry = 1_700_000_000u64;
+        let gtt = OrderDetails::new(
+            1,
+            OrderSide::Bid,
+            1_000_000,
+            OrderPrice::Limit(100_000),
+            TimeInForce::GTT(expiry),
+        );
+        assert!(!gtt.should_cancel_for_time(expiry as i64 - 1));
+        assert!(gtt.should_cancel_for_time(expiry as i64));
+        assert!(gtt.should_cancel_for_time(expiry as i64 + 1));
+        assert!(!gtt.should_cancel_for_time(-1));
+
+        let mut gtc = OrderDetails::new(
+            2,
+            OrderSide::Ask,
+            1_000_000,
+            OrderPrice::Limit(100_000),
+            TimeInForce::GTC,
+        );
+        assert!(!gtc.should_cancel_for_time(i64::MAX));
+
+        // Time condition in the second slot
+        gtc.cancel_cond_2 = TriggerCondition::Time(expiry);
+        assert!(!gtc.should_cancel_for_time(expiry as i64 - 1));
+        assert!(gtc.should_cancel_for_time(expiry as i64));
+
+        // And in the third, alongside a non-time condition
+        gtc.cancel_cond_2 = TriggerCondition::ReduceOnlyFail();
+        gtc.cancel_cond_3 = TriggerCondition::Time(expiry + 60);
+        assert!(!gtc.should_cancel_for_time(expiry as i64));
+        assert!(gtc.should_cancel_for_time(expiry as i64 + 60));
+    }
 }
@@ -1,4 +1,4 @@
-use crate::state::math::mul_qty_px_signed;
+use crate::state::math::pnl_scaled;
 use crate::state::order::OrderSide;
 use solana_program::program_error::ProgramError;
 
@@ -10,6 +10,25 @@ pub struct Fill {
     pub price: u64,
 }
 
+/// Which way a net position points
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PositionDirection {
+    Long,
+    Short,
+    Flat,
+}
+
+impl PositionDirection {
+    /// Direction of a signed net position (positive long, negative short)
+    pub fn from_net(net_position: i64) -> Self {
+        match net_position {
+            n if n > 0 => PositionDirection::Long,
+            n if n < 0 => PositionDirection::Short,
+            _ => PositionDirection::Flat,
+        }
+    }
+}
+
 /// Result of processing a fill
 #[derive(Debug, Clone, PartialEq)]
 pub struct FillResult {
@@ -27,10 +46,7 @@ pub fn process_fill(
     use solana_program::msg;
 
     // Convert fill to signed position change
-    let position_change = match fill.side {
-        OrderSide::Bid => fill.qty as i64,
-        OrderSide::Ask => -(fill.qty as i64),
-    };
+    let position_change = fill.side.sign() * fill.qty as i64;
 
     // Calculate new net position
     let new_net_position = current_net_position
@@ -163,7 +179,7 @@ fn calculate_realized_pnl(
     };
 
     // Calculate total PnL (scaled to collateral decimals)
-    mul_qty_px_signed(qty_closed as i64, price_diff)
+    pnl_scaled(qty_closed as i64, price_diff)
 }
 
 /// Process a fill for the clearing/market-wide counterparty
@@ -175,10 +191,7 @@ pub fn process_clearing_fill(
 ) -> Result<FillResult, ProgramError> {
     // Create inverted fill for clearing (opposite side)
     let clearing_fill = Fill {
-        side: match user_fill.side {
-            OrderSide::Bid => OrderSide::Ask, // User buys, clearing sells
-            OrderSide::Ask => OrderSide::Bid, // User sells, clearing buys
-        },
+        side: user_fill.side.opposite(), // User buys, clearing sells and vice versa
         qty: user_fill.qty,
         price: user_fill.price,
     };
@@ -191,6 +204,68 @@ pub fn process_clearing_fill(
     )
 }
 
+/// Combines two positions into one net position with its volume-weighted entry price.
+/// Same-direction positions average their entries; opposite positions net out and the
+/// surviving side keeps its own entry price. A fully flat result has an entry of 0.
+pub fn weighted_entry_price(
+    pos_a: i64,
+    px_a: u64,
+    pos_b: i64,
+    px_b: u64,
+) -> Result<(i64, u64), ProgramError> {
+    use solana_program::msg;
+
+    let net_position = pos_a.checked_add(pos_b).ok_or_else(|| {
+        msg!("Error: Overflow calculating merged net position");
+        ProgramError::ArithmeticOverflow
+    })?;
+
+    if net_position == 0 {
+        return Ok((0, 0));
+    }
+    if pos_a == 0 {
+        return Ok((net_position, px_b));
+    }
+    if pos_b == 0 {
+        return Ok((net_position, px_a));
+    }
+
+    let entry_price = if (pos_a > 0) == (pos_b > 0) {
+        calculate_weighted_avg_price(pos_a.unsigned_abs(), px_a, pos_b.unsigned_abs(), px_b)?
+    } else if (net_position > 0) == (pos_a > 0) {
+        px_a
+    } else {
+        px_b
+    };
+
+    Ok((net_position, entry_price))
+}
+
+/// Unrealized PnL of a position marked at `mark_price`, in collateral raw (1e-6).
+/// Uses the same 1e8 scaling as `MarginBucket::calc_equity`.
+pub fn unrealized_pnl(
+    net_position: i64,
+    avg_entry_price: u64,
+    mark_price: u64,
+) -> Result<i64, ProgramError> {
+    if net_position == 0 {
+        return Ok(0);
+    }
+
+    let (mark, entry) = (mark_price as i64, avg_entry_price as i64);
+    let price_diff = if net_position > 0 {
+        mark.checked_sub(entry)
+    } else {
+        entry.checked_sub(mark)
+    }
+    .ok_or(ProgramError::ArithmeticOverflow)?;
+    let size = net_position
+        .checked_abs()
+        .ok_or(ProgramError::ArithmeticOverflow)?;
+
+    pnl_scaled(size, price_diff)
+}
+
 /// Calculates equity (committed + unrealized PnL) for a position
 pub fn calculate_equity(
     committed_collateral: u64,
@@ -223,6 +298,21 @@ pub fn calculate_equity(
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_position_direction_from_net() {
+        assert_eq!(PositionDirection::from_net(1), PositionDirection::Long);
+        assert_eq!(
+            PositionDirection::from_net(i64::MAX),
+            PositionDirection::Long
+        );
+        assert_eq!(PositionDirection::from_net(-1), PositionDirection::Short);
+        assert_eq!(
+            PositionDirection::from_net(i64::MIN),
+            PositionDirection::Short
+        );
+        assert_eq!(PositionDirection::from_net(0), PositionDirection::Flat);
+    }
+
     #[test]
     fn test_zero_to_long_position() {
         let fill = Fill {
@@ -371,4 +461,103 @@ mod tests {
 
         assert_eq!(equity, 1_000_000);
     }
+
+    #[test]
+    fn test_partial_close_long_realizes_scaled_pnl() {
+        // Sell 0.4 of a 1-token long bought at $50 for $52: 0.4 * $2 = $0.80
+        let fill = Fill {
+            side: OrderSide::Ask,
+            qty: 40_000_000,
+            price: 52_000_000,
+        };
+
+        let result = process_fill(100_000_000, 50_000_000, &fill).unwrap();
+
+        assert_eq!(result.new_net_position, 60_000_000);
+        assert_eq!(result.new_avg_entry_price, 50_000_000);
+        assert_eq!(result.realized_pnl, 800_000);
+    }
+
+    #[test]
+    fn test_weighted_entry_price_same_direction() {
+        // Longs: (100 * 50_000 + 50 * 53_000) / 150 = 51_000
+        let (pos, px) = weighted_entry_price(100, 50_000, 50, 53_000).unwrap();
+        assert_eq!(pos, 150);
+        assert_eq!(px, 51_000);
+
+        // Shorts average the same way
+        let (pos, px) = weighted_entry_price(-100, 50_000, -50, 53_000).unwrap();
+        assert_eq!(pos, -150);
+        assert_eq!(px, 51_000);
+
+        // Merging into an empty position keeps the other side's entry
+        assert_eq!(
+            weighted_entry_price(0, 0, -40, 48_000).unwrap(),
+            (-40, 48_000)
+        );
+        assert_eq!(
+            weighted_entry_price(40, 48_000, 0, 0).unwrap(),
+            (40, 48_000)
+        );
+    }
+
+    #[test]
+    fn test_weighted_entry_price_full_cancellation() {
+        let (pos, px) = weighted_entry_price(100, 50_000, -100, 52_000).unwrap();
+        assert_eq!(pos, 0);
+        assert_eq!(px, 0);
+    }
+
+    #[test]
+    fn test_weighted_entry_price_partial_offset() {
+        // Long survives: keeps the long's entry
+        assert_eq!(
+            weighted_entry_price(100, 50_000, -30, 52_000).unwrap(),
+            (70, 50_000)
+        );
+        // Short survives: keeps the short's entry regardless of argument order
+        assert_eq!(
+            weighted_entry_price(30, 50_000, -100, 52_000).unwrap(),
+            (-70, 52_000)
+        );
+        assert_eq!(
+            weighted_entry_price(-100, 52_000, 30, 50_000).unwrap(),
+            (-70, 52_000)
+        );
+    }
+
+    #[test]
+    fn test_unrealized_pnl_long_profit() {
+        // Long 2 tokens from $50k to $52k → +$4k
+        assert_eq!(
+            unrealized_pnl(200_000_000, 50_000_000_000, 52_000_000_000).unwrap(),
+            4_000_000_000
+        );
+    }
+
+    #[test]
+    fn test_unrealized_pnl_short_profit() {
+        // Short 0.5 token from $50k to $48k → +$1k
+        assert_eq!(
+            unrealized_pnl(-50_000_000, 50_000_000_000, 48_000_000_000).unwrap(),
+            1_000_000_000
+        );
+        // And the same short loses if the mark rises
+        assert_eq!(
+            unrealized_pnl(-50_000_000, 50_000_000_000, 52_000_000_000).unwrap(),
+            -1_000_000_000
+        );
+    }
+
+    #[test]
+    fn test_unrealized_pnl_zero_position() {
+        assert_eq!(unrealized_pnl(0, 50_000_000_000, 1).unwrap(), 0);
+        assert_eq!(unrealized_pnl(0, 0, 0).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_weighted_entry_price_overflow() {
+        let result = weighted_entry_price(i64::MAX, 1, 1, 1);
+        assert_eq!(result.unwrap_err(), ProgramError::ArithmeticOverflow);
+    }
 }
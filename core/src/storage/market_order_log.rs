@@ -33,6 +33,13 @@ pub enum OrderUpdateType {
     Reserved(u8),
 }
 
+impl OrderUpdateType {
+    /// Same variant, ignoring any payload (every `Reserved(_)` is the same kind)
+    pub fn same_kind(&self, other: &OrderUpdateType) -> bool {
+        core::mem::discriminant(self) == core::mem::discriminant(other)
+    }
+}
+
 /// A single entry in the market-wide order log
 #[derive(BorshSerialize, BorshDeserialize, Clone, Debug)]
 pub struct OrderLogEntry {
@@ -85,6 +92,30 @@ pub const EMPTY_FILL_DETAILS: FillLogDetails = FillLogDetails {
 };
 
 impl OrderLogEntry {
+    /// Build a `Fill` entry for an order, capturing the fill price/qty and the
+    /// resulting account position in one place.
+    pub fn fill(
+        user: Pubkey,
+        order: &OrderDetails,
+        cma_result: &CmaFillResult,
+        fill_price: u64,
+        fill_qty: u64,
+        slot: u64,
+    ) -> Self {
+        Self {
+            user,
+            order_id: order.order_id,
+            update_type: OrderUpdateType::Fill,
+            order_details: order.clone(),
+            fill_details: FillLogDetails {
+                price: fill_price,
+                qty: fill_qty,
+                account: cma_result.clone(),
+            },
+            slot,
+        }
+    }
+
     pub fn synth_user_collateral_update(user: Pubkey, collateral_snapshot: u64, slot: u64) -> Self {
         Self {
             user,
@@ -98,6 +129,37 @@ impl OrderLogEntry {
             order_details: OrderDetails::default(),
         }
     }
+
+    /// Build a `Liquidation` entry recording a keeper-driven close of `user`'s position.
+    /// Liquidations are not resting orders, so `order_details` is left at its default.
+    pub fn synth_liquidation(user: Pubkey, order_id: u64, fill: FillLogDetails, slot: u64) -> Self {
+        Self {
+            user,
+            order_id,
+            update_type: OrderUpdateType::Liquidation,
+            order_details: OrderDetails::default(),
+            fill_details: fill,
+            slot,
+        }
+    }
+
+    /// Build a `ClosePosition` entry recording a user-initiated close of their position.
+    /// Like liquidations these never rest on the book, so `order_details` is left at its default.
+    pub fn synth_close_position(
+        user: Pubkey,
+        order_id: u64,
+        fill: FillLogDetails,
+        slot: u64,
+    ) -> Self {
+        Self {
+            user,
+            order_id,
+            update_type: OrderUpdateType::ClosePosition,
+            order_details: OrderDetails::default(),
+            fill_details: fill,
+            slot,
+        }
+    }
 }
 
 impl MarketOrderLog {
@@ -202,12 +264,19 @@ impl MarketOrderLog {
         let mut close_positions = 0;
         let mut user_collateral_updates = 0;
         let mut other = 0;
+        let mut total_fill_qty = 0u128;
+        let mut total_fill_notional = 0u128;
 
         for entry in &self.entries {
             match entry.update_type {
                 OrderUpdateType::OrderEntry => order_entries += 1,
                 OrderUpdateType::Cancel => cancels += 1,
-                OrderUpdateType::Fill => fills += 1,
+                OrderUpdateType::Fill => {
+                    fills += 1;
+                    total_fill_qty += entry.fill_details.qty as u128;
+                    total_fill_notional +=
+                        entry.fill_details.qty as u128 * entry.fill_details.price as u128;
+                }
                 OrderUpdateType::Liquidation => liquidations += 1,
                 OrderUpdateType::ClosePosition => close_positions += 1,
                 OrderUpdateType::UserCollateralUpdate => user_collateral_updates += 1,
@@ -224,12 +293,14 @@ impl MarketOrderLog {
             close_positions,
             user_collateral_updates,
             other,
+            total_fill_qty,
+            total_fill_notional,
         }
     }
 }
 
 /// Statistics about a market order log
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct MarketOrderLogStats {
     pub total_entries: u64,
     pub order_entries: u64,
@@ -239,6 +310,35 @@ pub struct MarketOrderLogStats {
     pub close_positions: u64,
     pub user_collateral_updates: u64,
     pub other: u64,
+    /// Sum of filled quantity across `Fill` entries
+    pub total_fill_qty: u128,
+    /// Sum of `qty * price` across `Fill` entries (raw product, 1e-14 units)
+    pub total_fill_notional: u128,
+}
+
+impl MarketOrderLogStats {
+    /// Per-field activity since `previous`. Counters that went backwards (e.g. a new log page)
+    /// saturate at zero.
+    pub fn delta(&self, previous: &MarketOrderLogStats) -> MarketOrderLogStats {
+        MarketOrderLogStats {
+            total_entries: self.total_entries.saturating_sub(previous.total_entries),
+            order_entries: self.order_entries.saturating_sub(previous.order_entries),
+            cancels: self.cancels.saturating_sub(previous.cancels),
+            fills: self.fills.saturating_sub(previous.fills),
+            liquidations: self.liquidations.saturating_sub(previous.liquidations),
+            close_positions: self
+                .close_positions
+                .saturating_sub(previous.close_positions),
+            user_collateral_updates: self
+                .user_collateral_updates
+                .saturating_sub(previous.user_collateral_updates),
+            other: self.other.saturating_sub(previous.other),
+            total_fill_qty: self.total_fill_qty.saturating_sub(previous.total_fill_qty),
+            total_fill_notional: self
+                .total_fill_notional
+                .saturating_sub(previous.total_fill_notional),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -248,6 +348,14 @@ mod tests {
         OrderOriginator, OrderPrice, OrderSide, OrderTombstone, TriggerCondition, TriggerEntrySize,
     };
 
+    #[test]
+    fn test_update_type_same_kind() {
+        assert!(OrderUpdateType::Fill.same_kind(&OrderUpdateType::Fill));
+        assert!(!OrderUpdateType::Fill.same_kind(&OrderUpdateType::Liquidation));
+        assert!(OrderUpdateType::Reserved(1).same_kind(&OrderUpdateType::Reserved(7)));
+        assert!(!OrderUpdateType::Reserved(0).same_kind(&OrderUpdateType::OrderEntry));
+    }
+
     #[test]
     fn test_market_order_log_new() {
         let log = MarketOrderLog::new(42, 0);
@@ -277,7 +385,8 @@ mod tests {
             event_history: Default::default(),
             builder_tag: Default::default(),
             _pad1: [0; 64],
-            _pad2: [0; 32],
+            client_id: [0; 16],
+            _pad2: [0; 16],
             _pad3: [0; 24],
         };
 
@@ -466,6 +575,147 @@ mod tests {
         assert_eq!(entry.order_details, OrderDetails::default());
     }
 
+    #[test]
+    fn test_fill_entry_builder_round_trip() {
+        let user = Pubkey::new_unique();
+        let mut order = OrderDetails::new(
+            777,
+            OrderSide::Ask,
+            1_000_000,
+            OrderPrice::Limit(50_000),
+            crate::state::order::TimeInForce::GTC,
+        );
+        order.filled_qty = 400_000;
+        let cma_result = CmaFillResult {
+            new_net_position: -400_000,
+            old_net_position: 0,
+            realized_pnl_banked: 0,
+        };
+
+        let entry = OrderLogEntry::fill(user, &order, &cma_result, 50_000, 400_000, 4242);
+        assert_eq!(entry.user, user);
+        assert_eq!(entry.order_id, 777);
+        assert_eq!(entry.update_type, OrderUpdateType::Fill);
+        assert_eq!(entry.order_details, order);
+        assert_eq!(entry.slot, 4242);
+
+        let mut log = MarketOrderLog::new(42, 0);
+        log.append_entry(
+            entry.user,
+            entry.order_id,
+            entry.update_type,
+            entry.order_details.clone(),
+            Some(entry.fill_details.clone()),
+            entry.slot,
+        )
+        .unwrap();
+
+        let bytes = log.try_to_vec().unwrap();
+        let decoded = MarketOrderLog::try_from_slice(&bytes).unwrap();
+        assert_eq!(decoded.entry_count, 1);
+
+        let logged = &decoded.entries[0];
+        assert_eq!(logged.update_type, OrderUpdateType::Fill);
+        assert_eq!(logged.order_id, 777);
+        assert_eq!(logged.fill_details.price, 50_000);
+        assert_eq!(logged.fill_details.qty, 400_000);
+        assert_eq!(logged.fill_details.account, cma_result);
+        assert_eq!(logged.order_details, order);
+    }
+
+    #[test]
+    fn test_stats_accumulate_fill_volume() {
+        let mut log = MarketOrderLog::new(42, 0);
+        let user = Pubkey::new_unique();
+        let fills = [
+            (100_000_000u64, 50_000_000u64),
+            (250_000_000, 51_000_000),
+            (5, u64::MAX),
+        ];
+
+        for (i, (qty, price)) in fills.iter().enumerate() {
+            log.append_entry(
+                user,
+                i as u64,
+                OrderUpdateType::Fill,
+                OrderDetails::default(),
+                Some(FillLogDetails {
+                    price: *price,
+                    qty: *qty,
+                    account: CmaFillResult::default(),
+                }),
+                i as u64,
+            )
+            .unwrap();
+        }
+        // Non-fill entries do not count towards volume
+        log.append_entry(
+            user,
+            9,
+            OrderUpdateType::Cancel,
+            OrderDetails::default(),
+            Some(FillLogDetails {
+                price: 1,
+                qty: 1,
+                account: CmaFillResult::default(),
+            }),
+            9,
+        )
+        .unwrap();
+
+        let stats = log.get_stats();
+        assert_eq!(stats.fills, 3);
+        assert_eq!(stats.total_fill_qty, 350_000_005);
+        assert_eq!(
+            stats.total_fill_notional,
+            100_000_000u128 * 50_000_000 + 250_000_000u128 * 51_000_000 + 5 * u64::MAX as u128
+        );
+    }
+
+    #[test]
+    fn test_stats_delta() {
+        let previous = MarketOrderLogStats {
+            total_entries: 10,
+            order_entries: 4,
+            cancels: 2,
+            fills: 3,
+            liquidations: 0,
+            close_positions: 1,
+            user_collateral_updates: 0,
+            other: 5,
+            total_fill_qty: 300,
+            total_fill_notional: 30_000,
+        };
+        let current = MarketOrderLogStats {
+            total_entries: 17,
+            order_entries: 6,
+            cancels: 3,
+            fills: 7,
+            liquidations: 1,
+            close_positions: 1,
+            user_collateral_updates: 2,
+            other: 0, // counter reset
+            total_fill_qty: 700,
+            total_fill_notional: 75_000,
+        };
+
+        assert_eq!(
+            current.delta(&previous),
+            MarketOrderLogStats {
+                total_entries: 7,
+                order_entries: 2,
+                cancels: 1,
+                fills: 4,
+                liquidations: 1,
+                close_positions: 0,
+                user_collateral_updates: 2,
+                other: 0,
+                total_fill_qty: 400,
+                total_fill_notional: 45_000,
+            }
+        );
+    }
+
     #[test]
     fn test_get_stats_with_collateral_updates() {
         let mut log = MarketOrderLog::new(42, 0);
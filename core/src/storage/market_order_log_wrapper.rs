@@ -1,6 +1,8 @@
 use crate::state::order::OrderDetails;
 use crate::storage::market_order_log::{FillLogDetails, MarketOrderLogStats, OrderUpdateType};
-use crate::storage::zero_copy_market_order_log::{ZeroCopyMarketOrderLog, ZeroCopyOrderLogInfo};
+use crate::storage::zero_copy_market_order_log::{
+    ReallocPlan, ZeroCopyMarketOrderLog, ZeroCopyOrderLogInfo,
+};
 use solana_program::{account_info::AccountInfo, program_error::ProgramError, pubkey::Pubkey};
 
 /// High-level wrapper around ZeroCopyMarketOrderLog that provides
@@ -12,7 +14,7 @@ pub struct MarketOrderLogWrapper<'a> {
 impl<'a> MarketOrderLogWrapper<'a> {
     /// Create a new empty market order log (initializes the account)
     pub fn new(
-        account: &AccountInfo<'a>,
+        account: &'a AccountInfo<'_>,
         market_id: u64,
         page: u32,
         capacity: u64,
@@ -23,7 +25,7 @@ impl<'a> MarketOrderLogWrapper<'a> {
     }
 
     /// Load an existing market order log from account data
-    pub fn load(account: &AccountInfo<'a>) -> Result<Self, ProgramError> {
+    pub fn load(account: &'a AccountInfo<'_>) -> Result<Self, ProgramError> {
         let inner = ZeroCopyMarketOrderLog::load(account)?;
         Ok(Self { inner })
     }
@@ -64,6 +66,11 @@ impl<'a> MarketOrderLogWrapper<'a> {
         self.inner.needs_realloc(current_account_size)
     }
 
+    /// Next capacity step and account size, if the log should grow
+    pub fn realloc_plan(&self, current_account_size: usize) -> Option<ReallocPlan> {
+        self.inner.realloc_plan(current_account_size)
+    }
+
     /// Estimate the serialized size of the log
     pub fn estimated_serialized_size(&self) -> usize {
         self.inner.estimated_serialized_size()
@@ -117,7 +124,10 @@ impl<'a> MarketOrderLogWrapper<'a> {
 
     /// Get the entry size in bytes
     pub fn entry_size(&self) -> usize {
-        crate::storage::zero_copy_market_order_log::get_entry_serialized_size().unwrap_or(0)
+        self.inner
+            .get_basic_info()
+            .map(|info| info.entry_size)
+            .unwrap_or(0)
     }
 
     /// Update the capacity (typically after reallocation)
@@ -169,8 +179,7 @@ mod tests {
         OrderOriginator, OrderPrice, OrderSide, OrderTombstone, TriggerCondition,
     };
 
-    /// Create a representative OrderDetails that matches the one used in get_entry_serialized_size()
-    /// This ensures consistent serialization sizes across tests and runtime
+    /// Create a representative OrderDetails for test entries
     fn create_representative_order_details() -> OrderDetails {
         OrderDetails {
             order_id: 1,
@@ -11,6 +11,9 @@ const ERROR_ORDER_NOT_FOUND: u32 = 111;
 const ERROR_INVALID_ORDER_ID: u32 = 112;
 const ERROR_ACCOUNT_TOO_SMALL: u32 = 113;
 const ERROR_INVALID_INDEX: u32 = 115;
+const ERROR_DUPLICATE_CLIENT_ID: u32 = 116;
+const ERROR_INVALID_TOMBSTONE: u32 = 117;
+const ERROR_INVALID_FILL: u32 = 118;
 
 /// Helper functions for working with OrderDetailStorage in per-user OrderDetails PDAs
 impl OrderDetailStorage {
@@ -115,6 +118,13 @@ impl OrderDetailStorage {
                     ProgramError::Custom(ERROR_ACCOUNT_TOO_SMALL)
                 }
                 OrderDetailStorageError::InvalidIndex => ProgramError::Custom(ERROR_INVALID_INDEX),
+                OrderDetailStorageError::DuplicateClientId => {
+                    ProgramError::Custom(ERROR_DUPLICATE_CLIENT_ID)
+                }
+                OrderDetailStorageError::InvalidTombstone => {
+                    ProgramError::Custom(ERROR_INVALID_TOMBSTONE)
+                }
+                OrderDetailStorageError::InvalidFill => ProgramError::Custom(ERROR_INVALID_FILL),
             }
         })?;
 
@@ -145,6 +155,13 @@ impl OrderDetailStorage {
                     ProgramError::Custom(ERROR_ACCOUNT_TOO_SMALL)
                 }
                 OrderDetailStorageError::InvalidIndex => ProgramError::Custom(ERROR_INVALID_INDEX),
+                OrderDetailStorageError::DuplicateClientId => {
+                    ProgramError::Custom(ERROR_DUPLICATE_CLIENT_ID)
+                }
+                OrderDetailStorageError::InvalidTombstone => {
+                    ProgramError::Custom(ERROR_INVALID_TOMBSTONE)
+                }
+                OrderDetailStorageError::InvalidFill => ProgramError::Custom(ERROR_INVALID_FILL),
             }
         })
     }
@@ -183,6 +200,8 @@ pub mod order_detail_ops {
         order_details_account: &AccountInfo,
         order_details: &crate::state::order::OrderDetails,
     ) -> Result<(), ProgramError> {
+        order_details.validate_conditions(crate::MarketStateV1::SUPPORTED_PRICE_REFERENCES)?;
+
         let current_account_size = order_details_account.data_len();
 
         OrderDetailStorage::with_mut_storage(order_details_account, |storage| {
@@ -224,10 +243,17 @@ pub mod order_detail_ops {
         order_details_account: &AccountInfo,
         order_id: u64,
         fill_qty: u64,
+        fill_price: u64,
+        unix_ts: i64,
     ) -> Result<(), ProgramError> {
         OrderDetailStorage::with_mut_storage(order_details_account, |storage| {
-            storage.fill_order(order_id, fill_qty)?;
-            msg!("Filled order detail {} with qty {}", order_id, fill_qty);
+            storage.fill_order(order_id, fill_qty, fill_price, unix_ts)?;
+            msg!(
+                "Filled order detail {} with qty {} at price {}",
+                order_id,
+                fill_qty,
+                fill_price
+            );
             Ok(())
         })
     }
@@ -1,6 +1,6 @@
 use crate::state::order::{
-    OrderDetails, OrderOriginator, OrderPrice, OrderSide, OrderTombstone, TriggerCondition,
-    TriggerEntrySize,
+    OrderDetails, OrderFillResult, OrderOriginator, OrderPrice, OrderSide, OrderTombstone,
+    TriggerCondition, TriggerEntrySize,
 };
 use borsh::{BorshDeserialize, BorshSerialize};
 use solana_program::msg;
@@ -21,6 +21,9 @@ pub enum OrderDetailStorageError {
     InvalidOrderId,
     AccountTooSmall,
     InvalidIndex,
+    DuplicateClientId,
+    InvalidTombstone,
+    InvalidFill,
 }
 
 /// Per-user order details storage with auto-growth and ring buffer fallback
@@ -35,6 +38,34 @@ pub struct OrderDetailStorage {
     pub orders: Vec<OrderDetails>,
 }
 
+/// `(order_id, slot)` pairs sorted by order_id, built from an `OrderDetailStorage` once per
+/// batch of lookups (e.g. after loading the account for a multi-fill instruction).
+///
+/// Like a slot hint, every hit is checked against the stored order, so an index that went
+/// stale after the storage changed costs a linear scan rather than returning a wrong slot.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OrderIdIndex {
+    entries: Vec<(u64, u32)>,
+}
+
+impl OrderIdIndex {
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Slot recorded for `order_id`, if any
+    pub fn slot_of(&self, order_id: u64) -> Option<u32> {
+        self.entries
+            .binary_search_by_key(&order_id, |&(id, _)| id)
+            .ok()
+            .map(|pos| self.entries[pos].1)
+    }
+}
+
 impl OrderDetailStorage {
     /// Create new OrderDetailStorage with initial capacity
     pub fn new() -> Self {
@@ -48,6 +79,17 @@ impl OrderDetailStorage {
         }
     }
 
+    /// Build a sorted order_id index over the current orders for binary-search lookups
+    pub fn build_index(&self) -> OrderIdIndex {
+        let mut entries: Vec<(u64, u32)> = self.orders[..self.total_inserted]
+            .iter()
+            .enumerate()
+            .map(|(slot, order)| (order.order_id, slot as u32))
+            .collect();
+        entries.sort_unstable();
+        OrderIdIndex { entries }
+    }
+
     /// Insert a new order details with user-provided order_id
     pub fn insert_order(
         &mut self,
@@ -79,7 +121,8 @@ impl OrderDetailStorage {
             event_history: Default::default(),
             builder_tag: Default::default(),
             _pad1: [0; 64],
-            _pad2: [0; 32],
+            client_id: [0; 16],
+            _pad2: [0; 16],
             _pad3: [0; 24],
         };
 
@@ -102,6 +145,32 @@ impl OrderDetailStorage {
         Ok(())
     }
 
+    /// Insert a new order, rejecting it if an alive order already carries the same non-zero client_id
+    pub fn insert_order_dedup(
+        &mut self,
+        order_id: u64,
+        client_id: u128,
+        side: OrderSide,
+        qty: u64,
+        price: OrderPrice,
+        current_account_size: usize,
+    ) -> Result<(), OrderDetailStorageError> {
+        if client_id != 0 && self.has_client_id(client_id) {
+            return Err(OrderDetailStorageError::DuplicateClientId);
+        }
+
+        self.insert_order(order_id, side, qty, price, current_account_size)?;
+        self.orders[self.total_inserted - 1].set_client_id(client_id);
+        Ok(())
+    }
+
+    /// Check whether an alive order carries the given client_id
+    pub fn has_client_id(&self, client_id: u128) -> bool {
+        self.orders[..self.total_inserted]
+            .iter()
+            .any(|order| order.client_id() == client_id && order.tombstone_is_alive())
+    }
+
     /// Returns true if inserting another order would exceed the account's allocated size.
     pub fn needs_resize(&self, current_account_size: usize) -> bool {
         self.total_inserted >= self.capacity && !self.can_grow_alloc(current_account_size)
@@ -192,6 +261,19 @@ impl OrderDetailStorage {
         self.find_order_index(order_id)
     }
 
+    /// Find order index through a prebuilt `OrderIdIndex`, falling back to a linear scan when
+    /// the index misses or is stale
+    pub fn find_order_index_indexed(
+        &self,
+        index: &OrderIdIndex,
+        order_id: u64,
+    ) -> Result<usize, OrderDetailStorageError> {
+        match index.slot_of(order_id) {
+            Some(slot) => self.find_order_index_with_hint(order_id, slot),
+            None => self.find_order_index(order_id),
+        }
+    }
+
     /// Update order tombstone (for cancellation, fills, etc.)
     pub fn update_tombstone(
         &mut self,
@@ -220,20 +302,65 @@ impl OrderDetailStorage {
         self.update_tombstone(order_id, OrderTombstone::UserCancel())
     }
 
-    /// Mark an order as filled
-    pub fn fill_order(
+    /// Cancel `cancel_order_id` and insert its replacement as one step. The replacement is
+    /// inserted first, so if it is rejected (duplicate id, account full) the old order is
+    /// left alive and unchanged.
+    pub fn modify_order(
         &mut self,
-        order_id: u64,
-        fill_qty: u64,
+        cancel_order_id: u64,
+        new_order_id: u64,
+        side: OrderSide,
+        qty: u64,
+        price: OrderPrice,
+        current_account_size: usize,
     ) -> Result<(), OrderDetailStorageError> {
-        let order = self.get_order_mut(order_id)?;
-        order.filled_qty += fill_qty;
+        if !self.get_order(cancel_order_id)?.tombstone_is_alive() {
+            msg!("Error: Cannot modify dead order {}", cancel_order_id);
+            return Err(OrderDetailStorageError::OrderNotFound);
+        }
+
+        self.insert_order(new_order_id, side, qty, price, current_account_size)?;
+        self.cancel_order(cancel_order_id)
+    }
 
-        if order.filled_qty >= order.qty {
-            order.tombstone = OrderTombstone::Filled();
+    /// Mark every alive order dead with the given cancellation tombstone, returning
+    /// `(order_id, side, unfilled_qty)` for each cancelled order so collateral can be released
+    pub fn cancel_all(
+        &mut self,
+        tombstone: OrderTombstone,
+    ) -> Result<Vec<(u64, OrderSide, u64)>, OrderDetailStorageError> {
+        if !tombstone.is_valid_cancellation() {
+            msg!("Invalid cancellation tombstone: {:?}", tombstone);
+            return Err(OrderDetailStorageError::InvalidTombstone);
         }
 
-        Ok(())
+        let mut cancelled = Vec::new();
+        for order in self.orders[..self.total_inserted]
+            .iter_mut()
+            .filter(|order| order.tombstone_is_alive())
+        {
+            order.tombstone = tombstone.clone();
+            cancelled.push((order.order_id, order.side, order.unfilled_qty()));
+        }
+        Ok(cancelled)
+    }
+
+    /// Record a fill on an order, updating its filled quantity, average fill price,
+    /// and fill timestamps via `OrderDetails::process_fill`
+    pub fn fill_order(
+        &mut self,
+        order_id: u64,
+        fill_qty: u64,
+        fill_price: u64,
+        unix_ts: i64,
+    ) -> Result<OrderFillResult, OrderDetailStorageError> {
+        let order = self.get_order_mut(order_id)?;
+        order
+            .process_fill(fill_qty, fill_price, unix_ts)
+            .map_err(|e| {
+                msg!("Error: Fill rejected for order {}: {}", order_id, e);
+                OrderDetailStorageError::InvalidFill
+            })
     }
 
     /// Get all active (non-tombstoned) orders
@@ -245,6 +372,99 @@ impl OrderDetailStorage {
             .collect()
     }
 
+    /// Iterate over every stored order on the given side, alive or not
+    pub fn iter_by_side(&self, side: OrderSide) -> impl Iterator<Item = (u64, &OrderDetails)> {
+        self.orders[..self.total_inserted]
+            .iter()
+            .filter(move |order| order.side == side)
+            .map(|order| (order.order_id, order))
+    }
+
+    /// Iterate over every stored order whose tombstone satisfies `pred`
+    pub fn iter_by_tombstone(
+        &self,
+        pred: impl Fn(&OrderTombstone) -> bool,
+    ) -> impl Iterator<Item = (u64, &OrderDetails)> {
+        self.orders[..self.total_inserted]
+            .iter()
+            .filter(move |order| pred(&order.tombstone))
+            .map(|order| (order.order_id, order))
+    }
+
+    /// Remove all non-alive orders, shifting survivors down while preserving their relative
+    /// order, and return the number of slots reclaimed.
+    pub fn compact(&mut self) -> usize {
+        let mut write = 0;
+        for read in 0..self.total_inserted {
+            if self.orders[read].tombstone_is_alive() {
+                self.orders.swap(write, read);
+                write += 1;
+            }
+        }
+
+        // Clear the vacated tail so stale orders can't be found again
+        for slot in &mut self.orders[write..self.total_inserted] {
+            *slot = OrderDetails::default();
+        }
+        let reclaimed = self.total_inserted - write;
+        self.total_inserted = write;
+
+        reclaimed
+    }
+
+    /// Compact the storage, then release unused capacity down to
+    /// `max(total_inserted, min_capacity, INITIAL_ORDER_CAPACITY)`. Returns the number of bytes
+    /// freed so the caller can realloc the account smaller.
+    pub fn shrink_to_fit(&mut self, min_capacity: usize) -> usize {
+        self.compact();
+
+        let target = self
+            .total_inserted
+            .max(min_capacity)
+            .max(INITIAL_ORDER_CAPACITY);
+        if target >= self.capacity {
+            return 0;
+        }
+
+        let size_before = self.estimated_serialized_size();
+        self.orders.truncate(target);
+        self.orders.shrink_to_fit();
+        self.capacity = target;
+        size_before - self.estimated_serialized_size()
+    }
+
+    /// Compact the storage and return the new `(order_id, slot)` of every surviving order
+    /// so cached hints can be rebuilt.
+    pub fn prune_and_reindex(&mut self) -> Vec<(u64, u32)> {
+        self.compact();
+
+        self.orders[..self.total_inserted]
+            .iter()
+            .enumerate()
+            .map(|(slot, order)| (order.order_id, slot as u32))
+            .collect()
+    }
+
+    /// Sum `(take_volume, make_volume, fees_booked)` per `builder_tag.builder_id` across all
+    /// stored orders, live or dead. Make volume is priced plus pegged make volume. Orders
+    /// already dropped by compaction are not included.
+    #[cfg(feature = "std")]
+    pub fn builder_volume_rollup(&self) -> std::collections::HashMap<u64, (u64, u64, u64)> {
+        let mut rollup = std::collections::HashMap::new();
+        for order in &self.orders[..self.total_inserted] {
+            let history = &order.event_history;
+            let entry: &mut (u64, u64, u64) =
+                rollup.entry(order.builder_tag.builder_id).or_default();
+            entry.0 = entry.0.saturating_add(history.take_volume);
+            entry.1 = entry
+                .1
+                .saturating_add(history.priced_make_volume)
+                .saturating_add(history.pegged_make_volume);
+            entry.2 = entry.2.saturating_add(history.fees_booked);
+        }
+        rollup
+    }
+
     /// Get storage statistics
     pub fn stats(&self) -> OrderDetailStorageStats {
         let active_count = self.get_active_orders().len();
@@ -417,19 +637,460 @@ mod tests {
             .unwrap();
 
         // Partial fill
-        storage.fill_order(order_id, 30_000).unwrap();
+        storage.fill_order(order_id, 30_000, 100_000, 0).unwrap();
         let order = storage.get_order(order_id).unwrap();
         assert_eq!(order.filled_qty, 30_000);
         assert!(matches!(order.tombstone, OrderTombstone::Open())); // Still open
 
         // Complete fill
-        storage.fill_order(order_id, 70_000).unwrap();
+        storage.fill_order(order_id, 70_000, 100_000, 0).unwrap();
         let order = storage.get_order(order_id).unwrap();
         assert_eq!(order.filled_qty, 100_000);
         assert!(matches!(order.tombstone, OrderTombstone::Filled())); // Now filled
         assert_eq!(storage.get_active_orders().len(), 0);
     }
 
+    #[test]
+    fn test_fill_order_tracks_weighted_average_price() {
+        let mut storage = OrderDetailStorage::new();
+        storage
+            .insert_order(9, OrderSide::Bid, 100, create_test_order_price(), 50000)
+            .unwrap();
+
+        storage.fill_order(9, 30, 1_000_000, 1_700_000_000).unwrap();
+        let result = storage.fill_order(9, 10, 1_400_000, 1_700_000_060).unwrap();
+        assert_eq!(result.weighted_avg_price, 1_100_000);
+        assert!(!result.is_fully_filled);
+
+        let order = storage.get_order(9).unwrap();
+        assert_eq!(order.filled_qty, 40);
+        assert_eq!(order.event_history.avg_fill_price, 1_100_000);
+        assert_eq!(order.event_history.first_fill_time, 1_700_000_000);
+        assert_eq!(order.event_history.last_fill_time, 1_700_000_060);
+
+        // Overfills are rejected instead of silently inflating filled_qty
+        assert_eq!(
+            storage.fill_order(9, 61, 1_000_000, 1_700_000_120),
+            Err(OrderDetailStorageError::InvalidFill)
+        );
+        assert_eq!(storage.get_order(9).unwrap().filled_qty, 40);
+    }
+
+    #[test]
+    fn test_prune_and_reindex() {
+        let mut storage = OrderDetailStorage::new();
+        let current_size = 50000;
+
+        for order_id in 1..=8u64 {
+            storage
+                .insert_order(
+                    order_id,
+                    OrderSide::Bid,
+                    1000,
+                    create_test_order_price(),
+                    current_size,
+                )
+                .unwrap();
+        }
+        storage.cancel_order(2).unwrap();
+        storage.fill_order(5, 1000, 100_000, 0).unwrap();
+        storage.cancel_order(6).unwrap();
+
+        let remap = storage.prune_and_reindex();
+
+        assert_eq!(remap, vec![(1, 0), (3, 1), (4, 2), (7, 3), (8, 4)]);
+        assert_eq!(storage.total_inserted, 5);
+        assert_eq!(storage.capacity, INITIAL_ORDER_CAPACITY);
+        for (order_id, slot) in &remap {
+            let index = storage
+                .find_order_index_with_hint(*order_id, *slot)
+                .unwrap();
+            assert_eq!(index, *slot as usize);
+            assert_eq!(storage.orders[index].order_id, *order_id);
+        }
+
+        // Pruned orders are gone
+        for order_id in [2u64, 5, 6] {
+            assert_eq!(
+                storage.get_order(order_id),
+                Err(OrderDetailStorageError::OrderNotFound)
+            );
+        }
+
+        // Nothing left to prune on a second pass
+        assert_eq!(storage.prune_and_reindex(), remap);
+    }
+
+    #[test]
+    fn test_compact_preserves_active_orders() {
+        let mut storage = OrderDetailStorage::new();
+        for order_id in 1..=8u64 {
+            storage
+                .insert_order(
+                    order_id,
+                    OrderSide::Bid,
+                    1000,
+                    create_test_order_price(),
+                    50000,
+                )
+                .unwrap();
+        }
+        // Fill every other order
+        for order_id in [2u64, 4, 6, 8] {
+            storage.fill_order(order_id, 1000, 100_000, 0).unwrap();
+        }
+
+        let before: Vec<(u64, OrderDetails)> = storage
+            .get_active_orders()
+            .into_iter()
+            .map(|(id, order)| (id, order.clone()))
+            .collect();
+
+        assert_eq!(storage.compact(), 4);
+        assert_eq!(storage.total_inserted, 4);
+
+        let after: Vec<(u64, OrderDetails)> = storage
+            .get_active_orders()
+            .into_iter()
+            .map(|(id, order)| (id, order.clone()))
+            .collect();
+        assert_eq!(after, before);
+        assert_eq!(
+            storage.get_order(4),
+            Err(OrderDetailStorageError::OrderNotFound)
+        );
+
+        // Nothing left to reclaim
+        assert_eq!(storage.compact(), 0);
+    }
+
+    #[test]
+    fn test_iter_by_side_and_tombstone() {
+        let mut storage = OrderDetailStorage::new();
+        let sides = [
+            OrderSide::Bid,
+            OrderSide::Ask,
+            OrderSide::Bid,
+            OrderSide::Ask,
+            OrderSide::Bid,
+        ];
+        for (i, side) in sides.iter().enumerate() {
+            storage
+                .insert_order(i as u64 + 1, *side, 1000, create_test_order_price(), 50000)
+                .unwrap();
+        }
+        storage.cancel_order(3).unwrap();
+        storage.fill_order(4, 1000, 100_000, 0).unwrap();
+
+        let bids: Vec<u64> = storage
+            .iter_by_side(OrderSide::Bid)
+            .map(|(id, _)| id)
+            .collect();
+        assert_eq!(bids, vec![1, 3, 5]);
+        let asks: Vec<u64> = storage
+            .iter_by_side(OrderSide::Ask)
+            .map(|(id, _)| id)
+            .collect();
+        assert_eq!(asks, vec![2, 4]);
+
+        let alive: Vec<u64> = storage
+            .iter_by_tombstone(|t| t.is_alive())
+            .map(|(id, _)| id)
+            .collect();
+        assert_eq!(alive, vec![1, 2, 5]);
+        let filled: Vec<u64> = storage
+            .iter_by_tombstone(|t| *t == OrderTombstone::Filled())
+            .map(|(id, _)| id)
+            .collect();
+        assert_eq!(filled, vec![4]);
+
+        // Alive bids combine both filters
+        let alive_bids: Vec<u64> = storage
+            .iter_by_side(OrderSide::Bid)
+            .filter(|(_, order)| order.tombstone_is_alive())
+            .map(|(id, _)| id)
+            .collect();
+        assert_eq!(alive_bids, vec![1, 5]);
+    }
+
+    #[test]
+    fn test_cancel_all_skips_dead_orders() {
+        let mut storage = OrderDetailStorage::new();
+        for (order_id, side) in [
+            (1u64, OrderSide::Bid),
+            (2, OrderSide::Ask),
+            (3, OrderSide::Bid),
+            (4, OrderSide::Ask),
+        ] {
+            storage
+                .insert_order(order_id, side, 1000, create_test_order_price(), 50000)
+                .unwrap();
+        }
+        storage.get_order_mut(1).unwrap().filled_qty = 400;
+        storage.cancel_order(2).unwrap();
+        storage.fill_order(3, 1000, 100_000, 0).unwrap();
+
+        // Live tombstones are not cancellation states
+        assert_eq!(
+            storage.cancel_all(OrderTombstone::Open()),
+            Err(OrderDetailStorageError::InvalidTombstone)
+        );
+
+        let cancelled = storage.cancel_all(OrderTombstone::SystemHalt()).unwrap();
+        assert_eq!(
+            cancelled,
+            vec![(1, OrderSide::Bid, 600), (4, OrderSide::Ask, 1000)]
+        );
+
+        assert_eq!(
+            storage.get_order(1).unwrap().tombstone,
+            OrderTombstone::SystemHalt()
+        );
+        assert_eq!(
+            storage.get_order(2).unwrap().tombstone,
+            OrderTombstone::UserCancel()
+        );
+        assert_eq!(
+            storage.get_order(3).unwrap().tombstone,
+            OrderTombstone::Filled()
+        );
+        assert!(storage.get_active_orders().is_empty());
+        assert!(storage
+            .cancel_all(OrderTombstone::UserCancel())
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_shrink_to_fit_only_when_safe() {
+        let per_order_size = std::mem::size_of::<OrderDetails>();
+        let mut storage = OrderDetailStorage::new();
+        for order_id in 1..=25u64 {
+            storage
+                .insert_order(
+                    order_id,
+                    OrderSide::Bid,
+                    1000,
+                    create_test_order_price(),
+                    SOLANA_MAX_ACCOUNT_SIZE,
+                )
+                .unwrap();
+        }
+        assert_eq!(storage.capacity, 30);
+
+        // Everything alive: nothing can be released below the live count
+        assert_eq!(storage.shrink_to_fit(0), 5 * per_order_size);
+        assert_eq!(storage.capacity, 25);
+        assert_eq!(storage.shrink_to_fit(0), 0);
+
+        // A larger requested floor keeps capacity where it is
+        for order_id in 1..=20u64 {
+            storage.fill_order(order_id, 1000, 100_000, 0).unwrap();
+        }
+        assert_eq!(storage.shrink_to_fit(40), 0);
+        assert_eq!(storage.capacity, 25);
+        assert_eq!(storage.total_inserted, 5);
+
+        // Never drops below the initial capacity
+        assert_eq!(storage.shrink_to_fit(0), 15 * per_order_size);
+        assert_eq!(storage.capacity, INITIAL_ORDER_CAPACITY);
+        assert_eq!(storage.orders.len(), INITIAL_ORDER_CAPACITY);
+        assert_eq!(storage.get_active_orders().len(), 5);
+
+        // Storage keeps growing normally afterwards
+        for order_id in 26..=32u64 {
+            storage
+                .insert_order(
+                    order_id,
+                    OrderSide::Ask,
+                    1000,
+                    create_test_order_price(),
+                    SOLANA_MAX_ACCOUNT_SIZE,
+                )
+                .unwrap();
+        }
+        assert_eq!(storage.capacity, 20);
+        assert_eq!(storage.get_order(32).unwrap().order_id, 32);
+    }
+
+    #[test]
+    fn test_insert_order_dedup_rejects_duplicate_client_id() {
+        let mut storage = OrderDetailStorage::new();
+        let current_size = 50000;
+        let client_id = 0xABCD_u128 << 64;
+
+        storage
+            .insert_order_dedup(
+                1,
+                client_id,
+                OrderSide::Bid,
+                1000,
+                create_test_order_price(),
+                current_size,
+            )
+            .unwrap();
+        assert!(storage.has_client_id(client_id));
+        assert_eq!(storage.get_order(1).unwrap().client_id(), client_id);
+
+        // Retry under a different order_id is rejected and nothing is inserted
+        let result = storage.insert_order_dedup(
+            2,
+            client_id,
+            OrderSide::Bid,
+            1000,
+            create_test_order_price(),
+            current_size,
+        );
+        assert_eq!(result, Err(OrderDetailStorageError::DuplicateClientId));
+        assert_eq!(storage.total_inserted, 1);
+
+        // Orders without a client_id are never deduplicated
+        for order_id in [3u64, 4] {
+            storage
+                .insert_order_dedup(
+                    order_id,
+                    0,
+                    OrderSide::Ask,
+                    1000,
+                    create_test_order_price(),
+                    current_size,
+                )
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn test_insert_order_dedup_allows_reuse_after_cancel() {
+        let mut storage = OrderDetailStorage::new();
+        let current_size = 50000;
+        let client_id = 42u128;
+
+        storage
+            .insert_order_dedup(
+                1,
+                client_id,
+                OrderSide::Bid,
+                1000,
+                create_test_order_price(),
+                current_size,
+            )
+            .unwrap();
+        storage.cancel_order(1).unwrap();
+        assert!(!storage.has_client_id(client_id));
+
+        storage
+            .insert_order_dedup(
+                2,
+                client_id,
+                OrderSide::Bid,
+                1000,
+                create_test_order_price(),
+                current_size,
+            )
+            .unwrap();
+        assert!(storage.has_client_id(client_id));
+        assert_eq!(storage.get_order(2).unwrap().client_id(), client_id);
+    }
+
+    #[test]
+    fn test_order_index_lookups_with_many_orders() {
+        let mut storage = OrderDetailStorage::new();
+        let num_orders = 1000u64;
+
+        // Insert ids out of order so the index has to sort them
+        for i in 0..num_orders {
+            let order_id = 1_000_000 + (i * 7919) % num_orders;
+            storage
+                .insert_order(
+                    order_id,
+                    OrderSide::Bid,
+                    1000,
+                    create_test_order_price(),
+                    SOLANA_MAX_ACCOUNT_SIZE,
+                )
+                .unwrap();
+        }
+
+        let index = storage.build_index();
+        assert_eq!(index.len(), num_orders as usize);
+        assert!(index.entries.windows(2).all(|w| w[0].0 < w[1].0));
+
+        // Pseudo-random lookups agree with the slot the order actually lives in
+        let mut seed = 12345u64;
+        for _ in 0..500 {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            let order_id = 1_000_000 + (seed >> 33) % num_orders;
+            let slot = storage.find_order_index_indexed(&index, order_id).unwrap();
+            assert_eq!(storage.orders[slot].order_id, order_id);
+            assert_eq!(slot, storage.find_order_index(order_id).unwrap());
+        }
+        assert_eq!(
+            storage.find_order_index_indexed(&index, 1_000_000 + num_orders),
+            Err(OrderDetailStorageError::OrderNotFound)
+        );
+    }
+
+    #[test]
+    fn test_order_index_path_is_used() {
+        let mut storage = OrderDetailStorage::new();
+        for order_id in [30u64, 10, 20] {
+            storage
+                .insert_order(
+                    order_id,
+                    OrderSide::Ask,
+                    1000,
+                    create_test_order_price(),
+                    50000,
+                )
+                .unwrap();
+        }
+        let index = storage.build_index();
+        assert_eq!(index.slot_of(20), Some(2));
+
+        // Give slot 0 a duplicate id: a linear scan stops there, the index goes to slot 2
+        storage.orders[0].order_id = 20;
+        assert_eq!(storage.find_order_index(20).unwrap(), 0);
+        assert_eq!(storage.find_order_index_indexed(&index, 20).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_stale_order_index_falls_back() {
+        let mut storage = OrderDetailStorage::new();
+        for order_id in 1..=5u64 {
+            storage
+                .insert_order(
+                    order_id,
+                    OrderSide::Bid,
+                    1000,
+                    create_test_order_price(),
+                    50000,
+                )
+                .unwrap();
+        }
+        let index = storage.build_index();
+
+        // Compaction moves orders and later inserts are unknown to the index
+        storage.cancel_order(1).unwrap();
+        storage.cancel_order(3).unwrap();
+        storage.compact();
+        storage
+            .insert_order(9, OrderSide::Bid, 1000, create_test_order_price(), 50000)
+            .unwrap();
+
+        for order_id in [2u64, 4, 5, 9] {
+            let slot = storage.find_order_index_indexed(&index, order_id).unwrap();
+            assert_eq!(storage.orders[slot].order_id, order_id);
+        }
+        for order_id in [1u64, 3] {
+            assert_eq!(
+                storage.find_order_index_indexed(&index, order_id),
+                Err(OrderDetailStorageError::OrderNotFound)
+            );
+        }
+    }
+
     #[test]
     fn test_storage_growth() {
         let mut storage = OrderDetailStorage::new();
@@ -470,6 +1131,103 @@ mod tests {
         assert_eq!(storage.total_inserted, INITIAL_ORDER_CAPACITY + 1);
     }
 
+    #[test]
+    fn test_modify_order_replaces_atomically() {
+        let mut storage = OrderDetailStorage::new();
+        storage
+            .insert_order(1, OrderSide::Bid, 1000, create_test_order_price(), 50000)
+            .unwrap();
+
+        storage
+            .modify_order(1, 2, OrderSide::Bid, 1500, OrderPrice::Limit(49_000), 50000)
+            .unwrap();
+
+        assert_eq!(
+            storage.get_order(1).unwrap().tombstone,
+            OrderTombstone::UserCancel()
+        );
+        let replacement = storage.get_order(2).unwrap();
+        assert_eq!(replacement.tombstone, OrderTombstone::Open());
+        assert_eq!(replacement.qty, 1500);
+        assert_eq!(replacement.price, OrderPrice::Limit(49_000));
+
+        // The cancelled order cannot be modified again
+        assert_eq!(
+            storage.modify_order(1, 3, OrderSide::Bid, 1, create_test_order_price(), 50000),
+            Err(OrderDetailStorageError::OrderNotFound)
+        );
+        assert!(storage.get_order(3).is_err());
+    }
+
+    #[test]
+    fn test_modify_order_rolls_back_when_full() {
+        let mut storage = OrderDetailStorage::new();
+        for i in 0..INITIAL_ORDER_CAPACITY as u64 {
+            storage
+                .insert_order(i, OrderSide::Ask, 1000, create_test_order_price(), 0)
+                .unwrap();
+        }
+        assert!(storage.needs_resize(0));
+
+        assert_eq!(
+            storage.modify_order(0, 999, OrderSide::Ask, 500, create_test_order_price(), 0),
+            Err(OrderDetailStorageError::AccountTooSmall)
+        );
+        assert_eq!(
+            storage.get_order(0).unwrap().tombstone,
+            OrderTombstone::Open()
+        );
+        assert!(storage.get_order(999).is_err());
+        assert_eq!(storage.total_inserted, INITIAL_ORDER_CAPACITY);
+
+        // A duplicate replacement id is rejected the same way
+        assert_eq!(
+            storage.modify_order(0, 1, OrderSide::Ask, 500, create_test_order_price(), 50000),
+            Err(OrderDetailStorageError::InvalidOrderId)
+        );
+        assert_eq!(
+            storage.get_order(0).unwrap().tombstone,
+            OrderTombstone::Open()
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_builder_volume_rollup() {
+        let mut storage = OrderDetailStorage::new();
+        // (order_id, builder_id, take, priced make, pegged make, fees)
+        let orders = [
+            (1u64, 7u64, 1_000u64, 0u64, 0u64, 10u64),
+            (2, 7, 0, 2_000, 500, 5),
+            (3, 9, 300, 100, 0, 3),
+            (4, 9, 0, 0, 0, 0),
+        ];
+        for (order_id, builder_id, take, priced, pegged, fees) in orders {
+            storage
+                .insert_order(
+                    order_id,
+                    OrderSide::Bid,
+                    10_000,
+                    create_test_order_price(),
+                    50000,
+                )
+                .unwrap();
+            let order = storage.get_order_mut(order_id).unwrap();
+            order.builder_tag.builder_id = builder_id;
+            order.event_history.take_volume = take;
+            order.event_history.priced_make_volume = priced;
+            order.event_history.pegged_make_volume = pegged;
+            order.event_history.fees_booked = fees;
+        }
+        // Dead orders still count towards attribution
+        storage.cancel_order(1).unwrap();
+
+        let rollup = storage.builder_volume_rollup();
+        assert_eq!(rollup.len(), 2);
+        assert_eq!(rollup[&7], (1_000, 2_500, 15));
+        assert_eq!(rollup[&9], (300, 100, 3));
+    }
+
     #[test]
     fn test_invalid_order_id() {
         let storage = OrderDetailStorage::new();
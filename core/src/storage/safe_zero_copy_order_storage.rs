@@ -9,7 +9,10 @@ use core::mem::{align_of, size_of};
 use solana_program::{account_info::AccountInfo, msg, program_error::ProgramError};
 
 /// Version constant for future compatibility
-pub const STORAGE_VERSION: u8 = 1;
+pub const STORAGE_VERSION: u8 = 2; // free links stored as next + 1 so 0 always means occupied
+
+/// Previous version storing free links as the raw next index; migrated in place on load
+pub const STORAGE_VERSION_RAW_LINKS: u8 = 1;
 
 /// Minimum alignment required for safe pointer operations
 const MIN_ALIGNMENT: usize = 8;
@@ -58,6 +61,10 @@ pub struct OrderStorageHeader {
 
 const HEADER_SIZE: usize = size_of::<OrderStorageHeader>();
 
+// Existing storage accounts are laid out with this exact header; any change must be a
+// deliberate version bump, not an accidental field edit.
+const _: () = assert!(HEADER_SIZE == 72 && align_of::<OrderStorageHeader>() == 8);
+
 /// Calculate actual serialized size of OrderMarker at runtime
 fn get_marker_serialized_size() -> Result<usize, ZeroCopyStorageError> {
     let dummy_marker = OrderMarker::default();
@@ -70,7 +77,7 @@ fn get_marker_serialized_size() -> Result<usize, ZeroCopyStorageError> {
 /// One slot entry: link field + variable-sized serialized marker data
 #[repr(C)]
 pub struct SlotEntry {
-    pub next_free: u64,    // Link to next free slot (0 if occupied)
+    pub next_free: u64, // 0 if occupied, else next free slot + 1 (u64::MAX ends the list)
     pub marker_data: [u8], // Variable-sized marker data
 }
 
@@ -165,7 +172,7 @@ impl<'a> SafeZeroCopyOrderStorage<'a> {
         let header = Self::read_header(data)?;
 
         // Validate version
-        if header.version != STORAGE_VERSION {
+        if header.version != STORAGE_VERSION && header.version != STORAGE_VERSION_RAW_LINKS {
             msg!("Error: Unsupported storage version: {}", header.version);
             return Err(ZeroCopyStorageError::UnsupportedVersion.into());
         }
@@ -214,11 +221,39 @@ impl<'a> SafeZeroCopyOrderStorage<'a> {
             return Err(ZeroCopyStorageError::CorruptedData.into());
         }
 
-        Ok(Self {
+        let mut storage = Self {
             data,
             marker_size,
             slot_size,
-        })
+        };
+        if header.version == STORAGE_VERSION_RAW_LINKS {
+            storage.migrate_raw_links()?;
+        }
+        Ok(storage)
+    }
+
+    /// Rewrite a v1 free list to the offset-by-one link encoding and bump the version.
+    /// Walking from `free_head` only follows links, so a v1 link to slot 0 (indistinguishable
+    /// from an occupied slot in v1) is still followed correctly.
+    fn migrate_raw_links(&mut self) -> Result<(), ZeroCopyStorageError> {
+        let capacity = self.header()?.capacity;
+        let mut current_free = self.header()?.free_head;
+        let mut visited = 0u64;
+
+        while current_free != u64::MAX {
+            if current_free >= capacity || visited >= capacity {
+                msg!("Error: Free list is corrupted at slot {}", current_free);
+                return Err(ZeroCopyStorageError::CorruptedData);
+            }
+            let next = self.read_next_free(current_free)?;
+            self.write_free_link(current_free, next)?;
+            current_free = next;
+            visited += 1;
+        }
+
+        self.header_mut()?.version = STORAGE_VERSION;
+        msg!("Migrated storage free list to version {}", STORAGE_VERSION);
+        Ok(())
     }
 
     /// Safely read header with bounds checking
@@ -320,6 +355,28 @@ impl<'a> SafeZeroCopyOrderStorage<'a> {
         Ok(())
     }
 
+    /// Read the free-list successor of a free slot (u64::MAX at the end of the list)
+    fn read_free_link(&self, idx: u64) -> Result<u64, ZeroCopyStorageError> {
+        match self.read_next_free(idx)? {
+            0 => {
+                msg!(
+                    "Error: Slot {} is on the free list but marked occupied",
+                    idx
+                );
+                Err(ZeroCopyStorageError::CorruptedData)
+            }
+            u64::MAX => Ok(u64::MAX),
+            link => Ok(link - 1),
+        }
+    }
+
+    /// Mark a slot free, linking it to `next` (u64::MAX for the end of the list).
+    /// Links are stored offset by one so that a link to slot 0 is not mistaken for an occupied slot.
+    fn write_free_link(&mut self, idx: u64, next: u64) -> Result<(), ZeroCopyStorageError> {
+        let link = if next == u64::MAX { u64::MAX } else { next + 1 };
+        self.write_next_free(idx, link)
+    }
+
     /// Serialize and write a marker into slot index
     fn write_marker(&mut self, idx: u64, marker: &OrderMarker) -> Result<(), ZeroCopyStorageError> {
         let serialized = marker
@@ -359,7 +416,7 @@ impl<'a> SafeZeroCopyOrderStorage<'a> {
             if header.free_head != u64::MAX {
                 // Reuse free slot
                 let idx = header.free_head;
-                let next_free = self.read_next_free(idx)?;
+                let next_free = self.read_free_link(idx)?;
                 (idx, next_free, header.next_free)
             } else if header.next_free < header.capacity {
                 // Use next available slot
@@ -410,7 +467,7 @@ impl<'a> SafeZeroCopyOrderStorage<'a> {
                 let current_free_head = self.header()?.free_head;
 
                 // Mark slot as free and add to free list
-                self.write_next_free(idx, current_free_head)?;
+                self.write_free_link(idx, current_free_head)?;
 
                 // Update header
                 let header = self.header_mut()?;
@@ -506,7 +563,7 @@ impl<'a> SafeZeroCopyOrderStorage<'a> {
             let current_free_head = self.header()?.free_head;
 
             // Mark slot as free and add to free list
-            self.write_next_free(found_idx, current_free_head)?;
+            self.write_free_link(found_idx, current_free_head)?;
 
             // Update header
             let header = self.header_mut()?;
@@ -554,6 +611,53 @@ impl<'a> SafeZeroCopyOrderStorage<'a> {
         )
     }
 
+    /// Remove every live marker owned by `user`, pushing each freed slot onto the free list.
+    /// Returns the removed markers in slot order.
+    pub fn remove_all_for_user(
+        &mut self,
+        user: &solana_program::pubkey::Pubkey,
+    ) -> Result<Vec<OrderMarker>, ProgramError> {
+        let mut matches = Vec::new();
+        for result in self.iter_active() {
+            let (idx, marker) = result?;
+            if marker.user == *user {
+                matches.push((idx, marker));
+            }
+        }
+
+        for (idx, _) in &matches {
+            let current_free_head = self.header()?.free_head;
+            self.write_free_link(*idx, current_free_head)?;
+
+            let header = self.header_mut()?;
+            header.free_head = *idx;
+            header.count -= 1;
+        }
+
+        msg!(
+            "Removed {} orders for user {}, remaining count: {}",
+            matches.len(),
+            user,
+            self.header()?.count
+        );
+        Ok(matches.into_iter().map(|(_, marker)| marker).collect())
+    }
+
+    /// Count the live orders owned by `user`
+    pub fn count_for_user(
+        &self,
+        user: &solana_program::pubkey::Pubkey,
+    ) -> Result<u64, ProgramError> {
+        let mut count = 0;
+        for result in self.iter_active() {
+            let (_, marker) = result?;
+            if marker.user == *user {
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
     /// Get storage statistics
     pub fn stats(&self) -> Result<SafeZeroCopyStorageStats, ProgramError> {
         let header = self.header()?;
@@ -574,6 +678,119 @@ impl<'a> SafeZeroCopyOrderStorage<'a> {
         })
     }
 
+    /// Length of the free list, walking it from `free_head`. Every free slot lies below
+    /// `next_free`, so a longer chain must revisit a slot; that bounds the walk without
+    /// tracking visited slots on the heap.
+    fn free_list_len(&self) -> Result<u64, ZeroCopyStorageError> {
+        let header = self.header()?;
+        let mut len = 0u64;
+        let mut current_free = header.free_head;
+
+        while current_free != u64::MAX {
+            if current_free >= header.next_free || len >= header.next_free {
+                msg!("Error: Free list is corrupted at slot {}", current_free);
+                return Err(ZeroCopyStorageError::CorruptedData);
+            }
+            len += 1;
+            current_free = self.read_free_link(current_free)?;
+        }
+
+        Ok(len)
+    }
+
+    /// Move every live marker to the front of the storage, preserving their relative order,
+    /// and drop the free list. Returns how many slots were reclaimed at the tail.
+    ///
+    /// Slot indices of moved markers change, so any slot hints held by callers are
+    /// invalidated and must be refreshed with `find`.
+    pub fn compact(&mut self) -> Result<u64, ProgramError> {
+        let header = *self.header()?;
+        let free_len = self.free_list_len()?;
+
+        // Free slots carry a non-zero link, so live slots are read off in place
+        let mut live = 0u64;
+        for idx in 0..header.next_free {
+            if self.read_next_free(idx)? == 0 {
+                live += 1;
+            }
+        }
+        if live != header.count || live + free_len != header.next_free {
+            msg!(
+                "Error: Found {} live and {} free slots but header count is {} of {}",
+                live,
+                free_len,
+                header.count,
+                header.next_free
+            );
+            return Err(ZeroCopyStorageError::CorruptedData.into());
+        }
+
+        let mut write_idx = 0u64;
+        for read_idx in 0..header.next_free {
+            if self.read_next_free(read_idx)? != 0 {
+                continue;
+            }
+            if write_idx != read_idx {
+                let src = HEADER_SIZE + (read_idx as usize * self.slot_size);
+                let dst = HEADER_SIZE + (write_idx as usize * self.slot_size);
+                self.data.copy_within(src..src + self.slot_size, dst);
+            }
+            write_idx += 1;
+        }
+
+        // Clear the vacated tail
+        let tail_start = HEADER_SIZE + (header.count as usize * self.slot_size);
+        let tail_end = HEADER_SIZE + (header.next_free as usize * self.slot_size);
+        self.data[tail_start..tail_end].fill(0);
+
+        let reclaimed = header.next_free - header.count;
+        let header = self.header_mut()?;
+        header.free_head = u64::MAX;
+        header.next_free = header.count;
+
+        msg!(
+            "Compacted storage: {} live orders, {} slots reclaimed",
+            header.count,
+            reclaimed
+        );
+        Ok(reclaimed)
+    }
+
+    /// Re-derive the free list from the slot link fields, discarding the existing chain.
+    /// Every slot below `next_free` whose link field is non-zero is treated as free and
+    /// relinked in ascending slot order. Last-resort repair for a corrupted `free_head`.
+    ///
+    /// The rebuilt chain is only installed if its length matches `next_free - count`;
+    /// otherwise the slot links disagree with the header and `free_head` is left as is.
+    pub fn rebuild_free_list(&mut self) -> Result<(), ProgramError> {
+        let header = *self.header()?;
+
+        // Walk backwards so each free slot links to the next one above it
+        let mut next = u64::MAX;
+        let mut free_len = 0u64;
+        for idx in (0..header.next_free).rev() {
+            if self.read_next_free(idx)? != 0 {
+                self.write_free_link(idx, next)?;
+                next = idx;
+                free_len += 1;
+            }
+        }
+
+        if header.count + free_len != header.next_free {
+            msg!(
+                "Error: Found {} free slots but header count is {} of {}",
+                free_len,
+                header.count,
+                header.next_free
+            );
+            return Err(ZeroCopyStorageError::CorruptedData.into());
+        }
+        self.header_mut()?.free_head = next;
+
+        msg!("Rebuilt free list with {} slots", free_len);
+        Ok(())
+    }
+
     /// Validate storage integrity - useful for debugging
     pub fn validate_integrity(&self) -> Result<(), ProgramError> {
         let header = self.header()?;
@@ -605,7 +822,7 @@ impl<'a> SafeZeroCopyOrderStorage<'a> {
                 return Err(ZeroCopyStorageError::CorruptedData.into());
             }
 
-            current_free = self.read_next_free(current_free)?;
+            current_free = self.read_free_link(current_free)?;
         }
 
         msg!("Storage integrity validation passed");
@@ -666,6 +883,13 @@ mod tests {
         AccountInfo::new(&KEY, false, true, lamports, data, &OWNER, false, 0)
     }
 
+    #[test]
+    fn test_header_layout() {
+        // Changing either value is an on-disk format break for existing storage accounts
+        assert_eq!(size_of::<OrderStorageHeader>(), 72);
+        assert_eq!(align_of::<OrderStorageHeader>(), 8);
+    }
+
     #[test]
     fn test_init_and_basic_operations() {
         let marker_size = get_marker_serialized_size().unwrap();
@@ -711,6 +935,313 @@ mod tests {
         storage.validate_integrity().unwrap();
     }
 
+    #[test]
+    fn test_compact_moves_live_markers_to_front() {
+        let marker_size = get_marker_serialized_size().unwrap();
+        let slot_size = SlotEntry::slot_size(marker_size);
+        let capacity = 8u64;
+        let required_size = HEADER_SIZE + (capacity as usize * slot_size);
+
+        let (mut lamports, mut data) = create_test_account_data(required_size);
+        let account = make_account_info(&mut lamports, &mut data);
+
+        SafeZeroCopyOrderStorage::init_in_account(&account, capacity).unwrap();
+        let mut storage = SafeZeroCopyOrderStorage::load(&account).unwrap();
+
+        let user = Pubkey::new_unique();
+        for order_id in 0..6u64 {
+            storage.insert(&OrderMarker::new(user, order_id)).unwrap();
+        }
+        // Remove slot 0 first so a later free slot links back to it
+        for order_id in [0u64, 3, 4] {
+            storage.remove(&user, order_id).unwrap();
+        }
+
+        assert_eq!(storage.compact().unwrap(), 3);
+        storage.validate_integrity().unwrap();
+
+        let stats = storage.stats().unwrap();
+        assert_eq!(stats.count, 3);
+        assert_eq!(stats.next_free, 3);
+        assert_eq!(stats.free_head, u64::MAX);
+
+        let live: Vec<(u64, u64)> = storage
+            .iter_active()
+            .map(|r| r.map(|(idx, marker)| (idx, marker.order_id)).unwrap())
+            .collect();
+        assert_eq!(live, vec![(0, 1), (1, 2), (2, 5)]);
+        assert_eq!(storage.find(&user, 5).unwrap(), Some(2));
+        assert_eq!(storage.find(&user, 3).unwrap(), None);
+
+        // New inserts continue right after the compacted prefix
+        assert_eq!(storage.insert(&OrderMarker::new(user, 6)).unwrap(), 3);
+
+        // Nothing further to reclaim
+        assert_eq!(storage.compact().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_compact_rejects_inconsistent_free_list() {
+        let marker_size = get_marker_serialized_size().unwrap();
+        let slot_size = SlotEntry::slot_size(marker_size);
+        let capacity = 8u64;
+        let required_size = HEADER_SIZE + (capacity as usize * slot_size);
+
+        let (mut lamports, mut data) = create_test_account_data(required_size);
+        let account = make_account_info(&mut lamports, &mut data);
+
+        SafeZeroCopyOrderStorage::init_in_account(&account, capacity).unwrap();
+        let mut storage = SafeZeroCopyOrderStorage::load(&account).unwrap();
+
+        let user = Pubkey::new_unique();
+        for order_id in 0..4u64 {
+            storage.insert(&OrderMarker::new(user, order_id)).unwrap();
+        }
+        storage.remove(&user, 1).unwrap();
+
+        // Slot 2 claims to be free but is not on the free list
+        storage.write_free_link(2, u64::MAX).unwrap();
+        let before = storage.data.to_vec();
+        assert!(storage.compact().is_err());
+        assert_eq!(storage.data[..], before[..]);
+
+        // A free list that loops back on itself is caught without a visited set
+        storage.write_free_link(2, 1).unwrap();
+        storage.write_free_link(1, 2).unwrap();
+        storage.header_mut().unwrap().free_head = 1;
+        assert!(storage.compact().is_err());
+    }
+
+    #[test]
+    fn test_count_for_user() {
+        let marker_size = get_marker_serialized_size().unwrap();
+        let slot_size = SlotEntry::slot_size(marker_size);
+        let capacity = 8u64;
+        let required_size = HEADER_SIZE + (capacity as usize * slot_size);
+
+        let (mut lamports, mut data) = create_test_account_data(required_size);
+        let account = make_account_info(&mut lamports, &mut data);
+
+        SafeZeroCopyOrderStorage::init_in_account(&account, capacity).unwrap();
+        let mut storage = SafeZeroCopyOrderStorage::load(&account).unwrap();
+
+        let alice = Pubkey::new_unique();
+        let bob = Pubkey::new_unique();
+        assert_eq!(storage.count_for_user(&alice).unwrap(), 0);
+
+        storage.insert(&OrderMarker::new(alice, 1)).unwrap();
+        storage.insert(&OrderMarker::new(bob, 1)).unwrap();
+        storage.insert(&OrderMarker::new(alice, 2)).unwrap();
+        storage.insert(&OrderMarker::new(bob, 2)).unwrap();
+        storage.insert(&OrderMarker::new(alice, 3)).unwrap();
+        assert_eq!(storage.count_for_user(&alice).unwrap(), 3);
+        assert_eq!(storage.count_for_user(&bob).unwrap(), 2);
+
+        storage.remove(&alice, 2).unwrap();
+        storage.remove(&bob, 2).unwrap();
+        assert_eq!(storage.count_for_user(&alice).unwrap(), 2);
+        assert_eq!(storage.count_for_user(&bob).unwrap(), 1);
+
+        // Freed slots are reused by the other user
+        storage.insert(&OrderMarker::new(bob, 3)).unwrap();
+        storage.insert(&OrderMarker::new(bob, 4)).unwrap();
+        assert_eq!(storage.count_for_user(&alice).unwrap(), 2);
+        assert_eq!(storage.count_for_user(&bob).unwrap(), 3);
+        assert_eq!(storage.count_for_user(&Pubkey::new_unique()).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_load_migrates_v1_free_list() {
+        let marker_size = get_marker_serialized_size().unwrap();
+        let slot_size = SlotEntry::slot_size(marker_size);
+        let capacity = 8u64;
+        let required_size = HEADER_SIZE + (capacity as usize * slot_size);
+
+        let (mut lamports, mut data) = create_test_account_data(required_size);
+        let user = Pubkey::new_unique();
+        {
+            let account = make_account_info(&mut lamports, &mut data);
+            SafeZeroCopyOrderStorage::init_in_account(&account, capacity).unwrap();
+            let mut storage = SafeZeroCopyOrderStorage::load(&account).unwrap();
+            for order_id in 0..5u64 {
+                storage.insert(&OrderMarker::new(user, order_id)).unwrap();
+            }
+        }
+
+        // Rewrite as a v1 account that freed slot 0 and then slot 2 with raw links:
+        // slot 0 ends the list, slot 2 links to slot 0 (stored as 0)
+        let link_at = |idx: usize| HEADER_SIZE + idx * slot_size;
+        data[0] = STORAGE_VERSION_RAW_LINKS;
+        data[16..24].copy_from_slice(&3u64.to_le_bytes()); // count
+        data[24..32].copy_from_slice(&2u64.to_le_bytes()); // free_head
+        data[link_at(0)..link_at(0) + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+        data[link_at(2)..link_at(2) + 8].copy_from_slice(&0u64.to_le_bytes());
+
+        let account = make_account_info(&mut lamports, &mut data);
+        let mut storage = SafeZeroCopyOrderStorage::load(&account).unwrap();
+        assert_eq!(storage.header().unwrap().version, STORAGE_VERSION);
+        assert_eq!(storage.read_next_free(2).unwrap(), 1);
+        storage.validate_integrity().unwrap();
+
+        for order_id in [1u64, 3, 4] {
+            assert!(storage.find(&user, order_id).unwrap().is_some());
+        }
+        for order_id in [0u64, 2] {
+            assert!(storage.find(&user, order_id).unwrap().is_none());
+        }
+
+        // Freed slots are reused head first before any new slot
+        assert_eq!(storage.insert(&OrderMarker::new(user, 10)).unwrap(), 2);
+        assert_eq!(storage.insert(&OrderMarker::new(user, 11)).unwrap(), 0);
+        assert_eq!(storage.insert(&OrderMarker::new(user, 12)).unwrap(), 5);
+        storage.validate_integrity().unwrap();
+    }
+
+    #[test]
+    fn test_load_rejects_cyclic_v1_free_list() {
+        let marker_size = get_marker_serialized_size().unwrap();
+        let slot_size = SlotEntry::slot_size(marker_size);
+        let capacity = 4u64;
+        let required_size = HEADER_SIZE + (capacity as usize * slot_size);
+
+        let (mut lamports, mut data) = create_test_account_data(required_size);
+        {
+            let account = make_account_info(&mut lamports, &mut data);
+            SafeZeroCopyOrderStorage::init_in_account(&account, capacity).unwrap();
+        }
+        let link_at = |idx: usize| HEADER_SIZE + idx * slot_size;
+        data[0] = STORAGE_VERSION_RAW_LINKS;
+        data[24..32].copy_from_slice(&1u64.to_le_bytes()); // free_head
+        data[32..40].copy_from_slice(&2u64.to_le_bytes()); // next_free
+        data[link_at(1)..link_at(1) + 8].copy_from_slice(&0u64.to_le_bytes());
+        data[link_at(0)..link_at(0) + 8].copy_from_slice(&1u64.to_le_bytes());
+
+        let account = make_account_info(&mut lamports, &mut data);
+        assert!(SafeZeroCopyOrderStorage::load(&account).is_err());
+    }
+
+    #[test]
+    fn test_remove_all_for_user() {
+        let marker_size = get_marker_serialized_size().unwrap();
+        let slot_size = SlotEntry::slot_size(marker_size);
+        let capacity = 10u64;
+        let required_size = HEADER_SIZE + (capacity as usize * slot_size);
+
+        let (mut lamports, mut data) = create_test_account_data(required_size);
+        let account = make_account_info(&mut lamports, &mut data);
+
+        SafeZeroCopyOrderStorage::init_in_account(&account, capacity).unwrap();
+        let mut storage = SafeZeroCopyOrderStorage::load(&account).unwrap();
+
+        let alice = Pubkey::new_unique();
+        let bob = Pubkey::new_unique();
+        // Alice owns slot 0 so freed slots end up linking back to it
+        for order_id in 0..5u64 {
+            storage.insert(&OrderMarker::new(alice, order_id)).unwrap();
+            if order_id % 2 == 0 {
+                storage.insert(&OrderMarker::new(bob, order_id)).unwrap();
+            }
+        }
+        assert_eq!(storage.stats().unwrap().count, 8);
+
+        // Remove three of Alice's five orders one by one
+        for order_id in [0u64, 2, 4] {
+            storage.remove(&alice, order_id).unwrap();
+        }
+        storage.validate_integrity().unwrap();
+        assert_eq!(storage.count_for_user(&alice).unwrap(), 2);
+
+        // Then the remaining two in bulk
+        let removed = storage.remove_all_for_user(&alice).unwrap();
+        let removed_ids: Vec<u64> = removed.iter().map(|m| m.order_id).collect();
+        assert_eq!(removed_ids, vec![1, 3]);
+        assert!(removed.iter().all(|m| m.user == alice));
+
+        storage.validate_integrity().unwrap();
+        assert_eq!(storage.count_for_user(&alice).unwrap(), 0);
+        assert_eq!(storage.count_for_user(&bob).unwrap(), 3);
+        assert_eq!(storage.stats().unwrap().count, 3);
+        assert!(storage.remove_all_for_user(&alice).unwrap().is_empty());
+
+        // Every freed slot is reusable
+        for order_id in 10..17u64 {
+            storage.insert(&OrderMarker::new(bob, order_id)).unwrap();
+        }
+        storage.validate_integrity().unwrap();
+        assert_eq!(storage.count_for_user(&bob).unwrap(), 10);
+    }
+
+    #[test]
+    fn test_rebuild_free_list_repairs_cycle() {
+        let marker_size = get_marker_serialized_size().unwrap();
+        let slot_size = SlotEntry::slot_size(marker_size);
+        let capacity = 8u64;
+        let required_size = HEADER_SIZE + (capacity as usize * slot_size);
+
+        let (mut lamports, mut data) = create_test_account_data(required_size);
+        let account = make_account_info(&mut lamports, &mut data);
+
+        SafeZeroCopyOrderStorage::init_in_account(&account, capacity).unwrap();
+        let mut storage = SafeZeroCopyOrderStorage::load(&account).unwrap();
+
+        let user = Pubkey::new_unique();
+        for order_id in 0..5u64 {
+            storage.insert(&OrderMarker::new(user, order_id)).unwrap();
+        }
+        storage.remove(&user, 1).unwrap();
+        storage.remove(&user, 3).unwrap();
+
+        // Free list is 3 -> 1; point slot 1 back at slot 3 to form a cycle
+        storage.write_free_link(1, 3).unwrap();
+        assert!(storage.validate_integrity().is_err());
+
+        storage.rebuild_free_list().unwrap();
+        storage.validate_integrity().unwrap();
+        assert_eq!(storage.stats().unwrap().free_head, 1);
+
+        // Freed slots are reused before fresh ones, live markers are untouched
+        assert_eq!(storage.insert(&OrderMarker::new(user, 10)).unwrap(), 1);
+        assert_eq!(storage.insert(&OrderMarker::new(user, 11)).unwrap(), 3);
+        assert_eq!(storage.insert(&OrderMarker::new(user, 12)).unwrap(), 5);
+        assert_eq!(storage.count_for_user(&user).unwrap(), 6);
+        assert_eq!(storage.find(&user, 4).unwrap(), Some(4));
+        storage.validate_integrity().unwrap();
+
+        // Rebuilding a list with no free slots leaves it empty
+        storage.rebuild_free_list().unwrap();
+        assert_eq!(storage.stats().unwrap().free_head, u64::MAX);
+    }
+
+    #[test]
+    fn test_rebuild_free_list_rejects_count_mismatch() {
+        let marker_size = get_marker_serialized_size().unwrap();
+        let slot_size = SlotEntry::slot_size(marker_size);
+        let capacity = 8u64;
+        let required_size = HEADER_SIZE + (capacity as usize * slot_size);
+
+        let (mut lamports, mut data) = create_test_account_data(required_size);
+        let account = make_account_info(&mut lamports, &mut data);
+
+        SafeZeroCopyOrderStorage::init_in_account(&account, capacity).unwrap();
+        let mut storage = SafeZeroCopyOrderStorage::load(&account).unwrap();
+
+        let user = Pubkey::new_unique();
+        for order_id in 0..5u64 {
+            storage.insert(&OrderMarker::new(user, order_id)).unwrap();
+        }
+        storage.remove(&user, 1).unwrap();
+        storage.remove(&user, 3).unwrap();
+        let free_head = storage.stats().unwrap().free_head;
+
+        // Slot 3 now reads as occupied, so only one free slot is found where two are expected
+        storage.write_next_free(3, 0).unwrap();
+        assert_eq!(
+            storage.rebuild_free_list().unwrap_err(),
+            ZeroCopyStorageError::CorruptedData.into()
+        );
+        assert_eq!(storage.stats().unwrap().free_head, free_head);
+    }
+
     #[test]
     fn test_account_too_small() {
         let (mut lamports, mut data) = create_test_account_data(10); // Too small
@@ -1,15 +1,19 @@
-use crate::state::order::OrderDetails;
+use crate::state::order::{max_order_details, OrderDetails};
 use crate::storage::market_order_log::{
     FillLogDetails, MarketOrderLogStats, OrderLogEntry, OrderUpdateType,
 };
 use borsh::{BorshDeserialize, BorshSerialize};
 use core::mem::{align_of, size_of};
 use solana_program::{account_info::AccountInfo, msg, program_error::ProgramError, pubkey::Pubkey};
+use std::cell::RefMut;
 
 use crate::storage::market_order_log::EMPTY_FILL_DETAILS;
 
 /// Version constant for future compatibility
-pub const ORDER_LOG_VERSION: u8 = 1;
+pub const ORDER_LOG_VERSION: u8 = 2; // added last-entry checksum
+
+/// Previous log version without a last-entry checksum; still loadable, checksum not verified
+pub const ORDER_LOG_VERSION_NO_CHECKSUM: u8 = 1;
 
 /// Minimum alignment required for safe pointer operations
 const MIN_ALIGNMENT: usize = 8;
@@ -27,6 +31,7 @@ pub enum ZeroCopyOrderLogError {
     UnsupportedVersion,
     InvalidCapacity,
     InvalidEntrySize,
+    ChecksumMismatch,
 }
 
 impl From<ZeroCopyOrderLogError> for ProgramError {
@@ -39,6 +44,7 @@ impl From<ZeroCopyOrderLogError> for ProgramError {
             ZeroCopyOrderLogError::UnsupportedVersion => ProgramError::Custom(304),
             ZeroCopyOrderLogError::InvalidCapacity => ProgramError::Custom(305),
             ZeroCopyOrderLogError::InvalidEntrySize => ProgramError::Custom(306),
+            ZeroCopyOrderLogError::ChecksumMismatch => ProgramError::Custom(307),
         }
     }
 }
@@ -63,15 +69,75 @@ pub struct MarketOrderLogHeader {
     pub entry_count: u64,
     /// Size of each serialized entry (for validation)
     pub entry_size: u64,
+    /// CRC32 of the most recently written slot (0 for an empty log)
+    pub last_entry_crc: u32,
     /// Future expansion space
-    pub _pad3: [u8; 32],
+    pub _pad3: [u8; 28],
 }
 
 const HEADER_SIZE: usize = size_of::<MarketOrderLogHeader>();
 
-/// Calculate the actual serialized size of OrderLogEntry at runtime
-/// This ensures we use the same serialization method during both initialization and usage
+// The header is the on-disk layout of every existing log account; reordering or adding
+// fields must fail the build rather than silently corrupt them.
+const _: () = assert!(HEADER_SIZE == 80 && align_of::<MarketOrderLogHeader>() == 8);
+
+/// Reader position across a market's log pages: the next entry to read is `index` on `page`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LogCursor {
+    pub page: u32,
+    pub index: u64,
+}
+
+/// Byte-wise lookup table for `crc32`, built at compile time
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = (crc >> 1) ^ (0xEDB8_8320 & (crc & 1).wrapping_neg());
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// CRC-32 (IEEE, reflected) over a byte slice.
+///
+/// One table lookup per byte, roughly 6 BPF instructions, so checksumming a ~540-byte slot
+/// costs about 3.3k CU per append, against ~25k CU for a bit-at-a-time loop.
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc = (crc >> 8) ^ CRC32_TABLE[((crc ^ byte as u32) & 0xff) as usize];
+    }
+    !crc
+}
+
+/// Calculate the slot size for OrderLogEntry at runtime: the serialized size of the largest
+/// possible entry, so any order (GTT, trigger, pegged, ...) fits in one slot
 pub fn get_entry_serialized_size() -> Result<usize, ZeroCopyOrderLogError> {
+    let largest_entry = OrderLogEntry {
+        user: Pubkey::default(),
+        order_id: 0,
+        update_type: OrderUpdateType::Reserved(u8::MAX),
+        order_details: max_order_details(),
+        fill_details: EMPTY_FILL_DETAILS,
+        slot: 0,
+    };
+
+    largest_entry
+        .try_to_vec()
+        .map(|v| v.len())
+        .map_err(|_| ZeroCopyOrderLogError::InvalidEntrySize)
+}
+
+/// Slot size used by logs created before slots were sized from the largest entry. Those
+/// accounts stay loadable; entries that don't fit their smaller slots are rejected on write.
+fn get_legacy_entry_serialized_size() -> Result<usize, ZeroCopyOrderLogError> {
     use crate::state::order::{OrderOriginator, OrderPrice, OrderTombstone, TriggerCondition};
     use crate::OrderSide;
 
@@ -105,27 +171,62 @@ pub fn get_entry_serialized_size() -> Result<usize, ZeroCopyOrderLogError> {
         .map_err(|_| ZeroCopyOrderLogError::InvalidEntrySize)
 }
 
+/// Next growth step for a log account, as computed by `realloc_plan`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ReallocPlan {
+    pub new_capacity: u64,
+    pub new_account_size: usize,
+}
+
 /// Zero-copy view over a market order log PDA's data
 pub struct ZeroCopyMarketOrderLog<'a> {
-    data: &'a mut [u8],
+    /// Held for the view's lifetime so the account data can't be re-borrowed underneath it
+    data: RefMut<'a, [u8]>,
     entry_size: usize,
 }
 
 impl<'a> ZeroCopyMarketOrderLog<'a> {
+    /// Exact account size in bytes needed to hold `capacity` entries
+    pub fn required_account_size(capacity: u64) -> Result<usize, ProgramError> {
+        if capacity == 0 {
+            msg!("Error: Capacity cannot be zero");
+            return Err(ZeroCopyOrderLogError::InvalidCapacity.into());
+        }
+
+        let entry_size = get_entry_serialized_size()?;
+        usize::try_from(capacity)
+            .ok()
+            .and_then(|c| c.checked_mul(entry_size))
+            .and_then(|entries| entries.checked_add(HEADER_SIZE))
+            .ok_or_else(|| {
+                msg!("Error: Account size for capacity {} overflows", capacity);
+                ProgramError::ArithmeticOverflow
+            })
+    }
+
+    /// Largest entry capacity that fits in an account of `account_size` bytes
+    pub fn max_capacity_for_size(account_size: usize) -> Result<u64, ProgramError> {
+        let entry_size = get_entry_serialized_size()?;
+        let capacity = account_size.saturating_sub(HEADER_SIZE) / entry_size;
+        if capacity == 0 {
+            msg!(
+                "Error: Account of {} bytes cannot hold a single log entry",
+                account_size
+            );
+            return Err(ZeroCopyOrderLogError::AccountTooSmall.into());
+        }
+        Ok(capacity as u64)
+    }
+
     /// Initialize account data with proper validation
     pub fn init_in_account(
-        account: &AccountInfo<'a>,
+        account: &AccountInfo,
         market_id: u64,
         page: u32,
         capacity: u64,
     ) -> Result<(), ProgramError> {
-        if capacity == 0 {
-            msg!("Error: Capacity cannot be zero");
-            return Err(ZeroCopyOrderLogError::InvalidCapacity.into());
-        }
-
         let entry_size = get_entry_serialized_size()?;
-        let required_size = HEADER_SIZE + (capacity as usize * entry_size);
+        let required_size = Self::required_account_size(capacity)?;
 
         let mut data = account.try_borrow_mut_data()?;
 
@@ -159,7 +260,8 @@ impl<'a> ZeroCopyMarketOrderLog<'a> {
             capacity,
             entry_count: 0,
             entry_size: entry_size as u64,
-            _pad3: [0; 32],
+            last_entry_crc: 0,
+            _pad3: [0; 28],
         };
 
         // Safe header write with alignment check
@@ -176,9 +278,8 @@ impl<'a> ZeroCopyMarketOrderLog<'a> {
     }
 
     /// Load a zero-copy view from existing account data
-    pub fn load(account: &AccountInfo<'a>) -> Result<Self, ProgramError> {
-        let mut data = account.try_borrow_mut_data()?;
-        let data: &'a mut [u8] = unsafe { std::mem::transmute(&mut **data) };
+    pub fn load(account: &'a AccountInfo<'_>) -> Result<Self, ProgramError> {
+        let data = RefMut::map(account.try_borrow_mut_data()?, |data| &mut **data);
 
         // Validate minimum size
         if data.len() < HEADER_SIZE {
@@ -192,17 +293,19 @@ impl<'a> ZeroCopyMarketOrderLog<'a> {
             return Err(ZeroCopyOrderLogError::InvalidAlignment.into());
         }
 
-        let header = Self::read_header(data)?;
+        let header = Self::read_header(&data)?;
 
         // Validate version
-        if header.version != ORDER_LOG_VERSION {
+        if header.version != ORDER_LOG_VERSION && header.version != ORDER_LOG_VERSION_NO_CHECKSUM {
             msg!("Error: Unsupported log version: {}", header.version);
             return Err(ZeroCopyOrderLogError::UnsupportedVersion.into());
         }
 
-        // Validate entry size matches current runtime
+        // Validate entry size matches current runtime (or the pre-max-sizing layout)
         let current_entry_size = get_entry_serialized_size()?;
-        if header.entry_size != current_entry_size as u64 {
+        if header.entry_size != current_entry_size as u64
+            && header.entry_size != get_legacy_entry_serialized_size()? as u64
+        {
             msg!(
                 "Error: Stored entry size {} doesn't match current {}",
                 header.entry_size,
@@ -234,7 +337,40 @@ impl<'a> ZeroCopyMarketOrderLog<'a> {
             return Err(ZeroCopyOrderLogError::CorruptedData.into());
         }
 
-        Ok(Self { data, entry_size })
+        let log = Self { data, entry_size };
+        log.verify_checksum()?;
+        Ok(log)
+    }
+
+    /// Check the newest slot against the header checksum, catching a partially-written
+    /// or corrupted tail. Logs from before the checksum was added are not checked.
+    pub fn verify_checksum(&self) -> Result<(), ZeroCopyOrderLogError> {
+        let header = self.header()?;
+        if header.version == ORDER_LOG_VERSION_NO_CHECKSUM {
+            return Ok(());
+        }
+
+        let actual = match header.entry_count {
+            0 => 0,
+            count => crc32(self.slot_bytes(count - 1)?),
+        };
+        if actual != header.last_entry_crc {
+            msg!(
+                "Error: Log checksum mismatch: header {}, last slot {}",
+                header.last_entry_crc,
+                actual
+            );
+            return Err(ZeroCopyOrderLogError::ChecksumMismatch);
+        }
+        Ok(())
+    }
+
+    /// Raw bytes of the slot at `idx`, exactly `entry_size` long
+    fn slot_bytes(&self, idx: u64) -> Result<&[u8], ZeroCopyOrderLogError> {
+        let offset = HEADER_SIZE + (idx as usize * self.entry_size);
+        self.data
+            .get(offset..offset + self.entry_size)
+            .ok_or(ZeroCopyOrderLogError::AccountTooSmall)
     }
 
     /// Safely read header with bounds checking
@@ -272,7 +408,7 @@ impl<'a> ZeroCopyMarketOrderLog<'a> {
 
     /// Get immutable reference to header
     fn header(&self) -> Result<&MarketOrderLogHeader, ZeroCopyOrderLogError> {
-        Self::read_header(self.data).map(|_| {
+        Self::read_header(&self.data).map(|_| {
             // Safe: we validated alignment and size in load()
             unsafe { &*(self.data.as_ptr() as *const MarketOrderLogHeader) }
         })
@@ -288,54 +424,38 @@ impl<'a> ZeroCopyMarketOrderLog<'a> {
         Ok(unsafe { &mut *(self.data.as_mut_ptr() as *mut MarketOrderLogHeader) })
     }
 
-    /// Get entry data at given index with bounds checking
-    fn get_entry_data(&self, idx: u64, size: usize) -> Result<&[u8], ZeroCopyOrderLogError> {
+    /// Get entry data at given index for deserialization.
+    ///
+    /// The slice extends up to `ORDER_DETAILS_PADDING` bytes past the slot (clamped to the
+    /// account) so a slightly larger legacy entry can still be decoded; `read_entry` rejects
+    /// any decode that consumes more than `entry_size` bytes.
+    fn get_entry_data(&self, idx: u64) -> Result<&[u8], ZeroCopyOrderLogError> {
         let header = self.header()?;
         if idx >= header.entry_count {
             return Err(ZeroCopyOrderLogError::CorruptedData);
         }
 
         let offset = HEADER_SIZE + (idx as usize * self.entry_size);
-        let end = offset + size;
-
-        if size > self.entry_size + ORDER_DETAILS_PADDING {
-            msg!(
-                "Error: Entry size {} exceeds expected size {}",
-                size,
-                self.entry_size + ORDER_DETAILS_PADDING
-            );
-            return Err(ZeroCopyOrderLogError::InvalidEntrySize);
-        }
-
-        if end > self.data.len() {
+        if offset + self.entry_size > self.data.len() {
             return Err(ZeroCopyOrderLogError::AccountTooSmall);
         }
+        let end = (offset + self.entry_size + ORDER_DETAILS_PADDING).min(self.data.len());
 
         Ok(&self.data[offset..end])
     }
 
-    /// Get mutable entry data at given index with bounds checking
-    fn get_entry_data_mut(
-        &mut self,
-        idx: u64,
-        size: usize,
-    ) -> Result<&mut [u8], ZeroCopyOrderLogError> {
+    /// Get mutable entry data at given index for serialization writes.
+    ///
+    /// Strict: the slice is exactly `entry_size` bytes, so a write can never spill into the
+    /// following slot.
+    fn get_entry_data_mut(&mut self, idx: u64) -> Result<&mut [u8], ZeroCopyOrderLogError> {
         let header = self.header()?;
         if idx >= header.capacity {
             return Err(ZeroCopyOrderLogError::CorruptedData);
         }
 
         let offset = HEADER_SIZE + (idx as usize * self.entry_size);
-        let end = offset + size;
-
-        if size > self.entry_size + ORDER_DETAILS_PADDING {
-            msg!(
-                "Error: Entry size {} exceeds expected size {}",
-                size,
-                self.entry_size + ORDER_DETAILS_PADDING
-            );
-            return Err(ZeroCopyOrderLogError::InvalidEntrySize);
-        }
+        let end = offset + self.entry_size;
 
         if end > self.data.len() {
             return Err(ZeroCopyOrderLogError::AccountTooSmall);
@@ -354,21 +474,50 @@ impl<'a> ZeroCopyMarketOrderLog<'a> {
             .try_to_vec()
             .map_err(|_| ZeroCopyOrderLogError::InvalidEntrySize)?;
 
-        let entry_data = self.get_entry_data_mut(idx, serialized.len())?;
-        entry_data.copy_from_slice(&serialized);
+        if serialized.len() > self.entry_size {
+            msg!(
+                "Error: Entry size {} exceeds slot size {}",
+                serialized.len(),
+                self.entry_size
+            );
+            return Err(ZeroCopyOrderLogError::InvalidEntrySize);
+        }
+
+        let entry_data = self.get_entry_data_mut(idx)?;
+        let (used, rest) = entry_data.split_at_mut(serialized.len());
+        used.copy_from_slice(&serialized);
+        rest.fill(0);
+
+        // Keep the checksum pointed at the newest slot (appends and in-place last updates)
+        let crc = crc32(self.slot_bytes(idx)?);
+        let header = self.header_mut()?;
+        if idx + 1 >= header.entry_count {
+            header.last_entry_crc = crc;
+        }
         Ok(())
     }
 
-    /// Read and deserialize an entry from the given slot
+    /// Read and deserialize an entry from the given slot, stopping at the Borsh boundary
     fn read_entry(&self, idx: u64) -> Result<OrderLogEntry, ZeroCopyOrderLogError> {
-        let entry_data = self.get_entry_data(idx, self.entry_size + ORDER_DETAILS_PADDING)?;
+        let entry_data = self.get_entry_data(idx)?;
         let mut entry_slice = entry_data;
 
-        match OrderLogEntry::deserialize(&mut entry_slice) {
-            Ok(entry) => Ok(entry),
-            Err(_e) => Err(ZeroCopyOrderLogError::CorruptedData),
+        let entry = OrderLogEntry::deserialize(&mut entry_slice)
+            .map_err(|_| ZeroCopyOrderLogError::CorruptedData)?;
+
+        // Decoding past the slot would mean consuming the next entry's bytes
+        let consumed = entry_data.len() - entry_slice.len();
+        if consumed > self.entry_size {
+            msg!(
+                "Error: Entry {} decoded {} bytes, exceeding slot size {}",
+                idx,
+                consumed,
+                self.entry_size
+            );
+            return Err(ZeroCopyOrderLogError::CorruptedData);
         }
-        //OrderLogEntry::try_from_slice(entry_data).map_err(|_| ZeroCopyOrderLogError::CorruptedData)
+
+        Ok(entry)
     }
 
     /// Append a new entry to the log (O(1) operation)
@@ -438,24 +587,83 @@ impl<'a> ZeroCopyMarketOrderLog<'a> {
         self.append_log_entry(entry)
     }
 
+    /// Append a keeper-driven liquidation fill for `user`
+    pub fn append_liquidation(
+        &mut self,
+        user: Pubkey,
+        order_id: u64,
+        fill: FillLogDetails,
+        slot: u64,
+    ) -> Result<(), ProgramError> {
+        let entry = OrderLogEntry::synth_liquidation(user, order_id, fill, slot);
+        self.append_log_entry(entry)
+    }
+
+    /// Append the fill from a `ClosePositionEntry` for `user`
+    pub fn append_close_position(
+        &mut self,
+        user: Pubkey,
+        order_id: u64,
+        fill: FillLogDetails,
+        slot: u64,
+    ) -> Result<(), ProgramError> {
+        let entry = OrderLogEntry::synth_close_position(user, order_id, fill, slot);
+        self.append_log_entry(entry)
+    }
+
+    /// Coalesce collateral snapshots: if the most recent entry is a `UserCollateralUpdate` for
+    /// the same user, overwrite it in place and return `true`; otherwise append a new entry
+    /// and return `false`.
+    pub fn update_last_if_collateral(
+        &mut self,
+        user: Pubkey,
+        collateral_snapshot: u64,
+        slot: u64,
+    ) -> Result<bool, ProgramError> {
+        let entry_count = self.header()?.entry_count;
+        if entry_count > 0 {
+            let last_idx = entry_count - 1;
+            let last = self.read_entry(last_idx)?;
+            if last.update_type == OrderUpdateType::UserCollateralUpdate && last.user == user {
+                let entry =
+                    OrderLogEntry::synth_user_collateral_update(user, collateral_snapshot, slot);
+                self.write_entry(last_idx, &entry)?;
+                return Ok(true);
+            }
+        }
+
+        self.append_user_collateral_update(user, collateral_snapshot, slot)?;
+        Ok(false)
+    }
+
     /// Check if the log needs reallocation (for account resizing)
     /// For zero-copy logs, only reallocate when approaching capacity limits
     pub fn needs_realloc(&self, current_account_size: usize) -> bool {
-        if let Ok(header) = self.header() {
-            // Check if we're running out of entry capacity (>90% full)
-            let capacity_threshold = (header.capacity * 9) / 10;
-            if header.entry_count >= capacity_threshold {
-                // We need more capacity - double it
-                let new_capacity = header.capacity * 2;
-                let required_size = HEADER_SIZE + (new_capacity as usize * self.entry_size);
-                required_size > current_account_size
-            } else {
-                // Still have capacity, no reallocation needed
-                false
-            }
-        } else {
-            false
+        self.realloc_plan(current_account_size).is_some()
+    }
+
+    /// Growth step to apply once the log is at least 90% full: the capacity doubles and the
+    /// account must grow to fit it. `None` when no growth is warranted or the account is
+    /// already large enough.
+    pub fn realloc_plan(&self, current_account_size: usize) -> Option<ReallocPlan> {
+        let header = self.header().ok()?;
+
+        // Check if we're running out of entry capacity (>90% full)
+        let capacity_threshold = (header.capacity * 9) / 10;
+        if header.entry_count < capacity_threshold {
+            // Still have capacity, no reallocation needed
+            return None;
         }
+
+        // We need more capacity - double it
+        let new_capacity = header.capacity.checked_mul(2)?;
+        let new_account_size = (new_capacity as usize)
+            .checked_mul(self.entry_size)?
+            .checked_add(HEADER_SIZE)?;
+        (new_account_size > current_account_size).then_some(ReallocPlan {
+            new_capacity,
+            new_account_size,
+        })
     }
 
     /// Estimate the required size for the log based on current capacity
@@ -480,6 +688,8 @@ impl<'a> ZeroCopyMarketOrderLog<'a> {
         let mut close_positions = 0;
         let mut user_collateral_updates = 0;
         let mut other = 0;
+        let mut total_fill_qty = 0u128;
+        let mut total_fill_notional = 0u128;
 
         // Iterate through entries without full deserialization
         for idx in 0..header.entry_count {
@@ -487,7 +697,12 @@ impl<'a> ZeroCopyMarketOrderLog<'a> {
             match entry.update_type {
                 OrderUpdateType::OrderEntry => order_entries += 1,
                 OrderUpdateType::Cancel => cancels += 1,
-                OrderUpdateType::Fill => fills += 1,
+                OrderUpdateType::Fill => {
+                    fills += 1;
+                    total_fill_qty += entry.fill_details.qty as u128;
+                    total_fill_notional +=
+                        entry.fill_details.qty as u128 * entry.fill_details.price as u128;
+                }
                 OrderUpdateType::Liquidation => liquidations += 1,
                 OrderUpdateType::ClosePosition => close_positions += 1,
                 OrderUpdateType::UserCollateralUpdate => user_collateral_updates += 1,
@@ -504,9 +719,23 @@ impl<'a> ZeroCopyMarketOrderLog<'a> {
             close_positions,
             user_collateral_updates,
             other,
+            total_fill_qty,
+            total_fill_notional,
         })
     }
 
+    /// Count entries of a single update type. Matching is by variant, so any `Reserved(_)`
+    /// counts every reserved entry regardless of its payload.
+    pub fn count_of(&self, update_type: OrderUpdateType) -> Result<u64, ProgramError> {
+        let mut count = 0;
+        for entry in self.iter_entries() {
+            if entry?.update_type.same_kind(&update_type) {
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
     /// Get basic log info without scanning entries
     pub fn get_basic_info(&self) -> Result<ZeroCopyOrderLogInfo, ProgramError> {
         let header = self.header()?;
@@ -542,6 +771,95 @@ impl<'a> ZeroCopyMarketOrderLog<'a> {
         self.iter_entries_range(0, entry_count)
     }
 
+    /// Iterate over the most recent `n` entries, newest first (clamped to the entry count)
+    pub fn iter_recent(
+        &self,
+        n: u64,
+    ) -> impl Iterator<Item = Result<OrderLogEntry, ZeroCopyOrderLogError>> + '_ {
+        let entry_count = self.header().map(|h| h.entry_count).unwrap_or(0);
+        let start = entry_count.saturating_sub(n);
+        (start..entry_count)
+            .rev()
+            .map(move |idx| self.read_entry(idx))
+    }
+
+    /// Iterate over the entries belonging to `user`, in log order (lazy deserialization).
+    /// Entries that fail to decode are passed through so callers can surface them.
+    pub fn iter_entries_for_user<'b>(
+        &'b self,
+        user: &'b Pubkey,
+    ) -> impl Iterator<Item = Result<OrderLogEntry, ZeroCopyOrderLogError>> + 'b {
+        self.iter_entries().filter(move |entry| match entry {
+            Ok(entry) => &entry.user == user,
+            Err(_) => true,
+        })
+    }
+
+    /// Index of the first entry whose slot is at or after `slot`, relying on entries being
+    /// appended in non-decreasing slot order. Returns `entry_count` when every entry is earlier
+    /// (including an empty log).
+    pub fn find_first_index_at_or_after_slot(&self, slot: u64) -> Result<u64, ProgramError> {
+        let mut lo = 0u64;
+        let mut hi = self.header()?.entry_count;
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            if self.read_entry(mid)?.slot < slot {
+                lo = mid + 1;
+            } else {
+                hi = mid;
+            }
+        }
+        Ok(lo)
+    }
+
+    /// Iterate over all entries at or after `slot` (lazy deserialization)
+    pub fn iter_entries_from_slot(
+        &self,
+        slot: u64,
+    ) -> Result<impl Iterator<Item = Result<OrderLogEntry, ZeroCopyOrderLogError>> + '_, ProgramError>
+    {
+        let start = self.find_first_index_at_or_after_slot(slot)?;
+        let entry_count = self.header()?.entry_count;
+        Ok(self.iter_entries_range(start, entry_count))
+    }
+
+    /// Read every entry on this page from `cursor.index` onward and return them with the
+    /// advanced cursor. Once the page is full it will never receive more entries, so the
+    /// returned cursor rolls over to index 0 of the next page to signal exhaustion.
+    pub fn entries_after(
+        &self,
+        cursor: LogCursor,
+    ) -> Result<(Vec<OrderLogEntry>, LogCursor), ProgramError> {
+        let header = self.header()?;
+        if cursor.page != header.page || cursor.index > header.entry_count {
+            msg!(
+                "Error: Log cursor page={} index={} does not fall on page {} with {} entries",
+                cursor.page,
+                cursor.index,
+                header.page,
+                header.entry_count
+            );
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        let entries = self
+            .iter_entries_range(cursor.index, header.entry_count)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let next = if header.entry_count >= header.capacity {
+            LogCursor {
+                page: header.page + 1,
+                index: 0,
+            }
+        } else {
+            LogCursor {
+                page: header.page,
+                index: header.entry_count,
+            }
+        };
+        Ok((entries, next))
+    }
+
     /// Get the current capacity of the log
     pub fn capacity(&self) -> Result<u64, ProgramError> {
         let header = self.header()?;
@@ -583,11 +901,12 @@ mod tests {
     use super::*;
     use crate::state::cma::CmaFillResult;
     use crate::state::order::{
-        OrderOriginator, OrderPrice, OrderSide, OrderTombstone, TriggerCondition,
+        OrderOriginator, OrderPrice, OrderSide, OrderTombstone, PegPriceReference, PriceReference,
+        TimeInForce, TriggerCondition,
     };
 
-    /// Create a representative OrderDetails that matches the one used in get_entry_serialized_size()
-    /// This ensures consistent serialization sizes across tests and runtime
+    /// Create a representative OrderDetails that matches the one used in
+    /// get_legacy_entry_serialized_size()
     fn create_representative_order_details() -> OrderDetails {
         OrderDetails {
             order_id: 1,
@@ -616,6 +935,86 @@ mod tests {
         AccountInfo::new(&KEY, false, true, lamports, data, &OWNER, false, 0)
     }
 
+    #[test]
+    fn test_header_layout() {
+        // Changing either value is an on-disk format break for existing log accounts
+        assert_eq!(size_of::<MarketOrderLogHeader>(), 80);
+        assert_eq!(align_of::<MarketOrderLogHeader>(), 8);
+    }
+
+    #[test]
+    fn test_account_size_capacity_round_trip() {
+        let entry_size = get_entry_serialized_size().unwrap();
+        for capacity in [1u64, 2, 7, 1_000] {
+            let size = ZeroCopyMarketOrderLog::required_account_size(capacity).unwrap();
+            assert_eq!(size, HEADER_SIZE + capacity as usize * entry_size);
+            assert_eq!(
+                ZeroCopyMarketOrderLog::max_capacity_for_size(size).unwrap(),
+                capacity
+            );
+            // Slack short of a whole entry does not add capacity
+            assert_eq!(
+                ZeroCopyMarketOrderLog::max_capacity_for_size(size + entry_size - 1).unwrap(),
+                capacity
+            );
+        }
+
+        let (mut lamports, mut data) =
+            create_test_account_data(ZeroCopyMarketOrderLog::required_account_size(3).unwrap());
+        let account = make_account_info(&mut lamports, &mut data);
+        ZeroCopyMarketOrderLog::init_in_account(&account, 42, 0, 3).unwrap();
+
+        assert_eq!(
+            ZeroCopyMarketOrderLog::required_account_size(0).unwrap_err(),
+            ZeroCopyOrderLogError::InvalidCapacity.into()
+        );
+        assert_eq!(
+            ZeroCopyMarketOrderLog::required_account_size(u64::MAX).unwrap_err(),
+            ProgramError::ArithmeticOverflow
+        );
+        assert_eq!(
+            ZeroCopyMarketOrderLog::max_capacity_for_size(HEADER_SIZE + entry_size - 1)
+                .unwrap_err(),
+            ZeroCopyOrderLogError::AccountTooSmall.into()
+        );
+    }
+
+    #[test]
+    fn test_realloc_plan_at_ninety_percent_threshold() {
+        let capacity = 10u64;
+        let required_size = ZeroCopyMarketOrderLog::required_account_size(capacity).unwrap();
+
+        let (mut lamports, mut data) = create_test_account_data(required_size);
+        let account = make_account_info(&mut lamports, &mut data);
+
+        ZeroCopyMarketOrderLog::init_in_account(&account, 42, 0, capacity).unwrap();
+        let mut log = ZeroCopyMarketOrderLog::load(&account).unwrap();
+        let user = Pubkey::new_unique();
+
+        // 8 of 10 entries: below the 90% threshold
+        for slot in 0..8 {
+            log.append_user_collateral_update(user, 1, slot).unwrap();
+        }
+        assert_eq!(log.realloc_plan(required_size), None);
+        assert!(!log.needs_realloc(required_size));
+
+        // 9 of 10 entries: growth kicks in
+        log.append_user_collateral_update(user, 1, 8).unwrap();
+        let expected = ReallocPlan {
+            new_capacity: 20,
+            new_account_size: ZeroCopyMarketOrderLog::required_account_size(20).unwrap(),
+        };
+        assert_eq!(log.realloc_plan(required_size), Some(expected));
+        assert!(log.needs_realloc(required_size));
+
+        // An account already sized for the doubled capacity needs nothing
+        assert_eq!(log.realloc_plan(expected.new_account_size), None);
+        assert_eq!(
+            log.realloc_plan(expected.new_account_size - 1),
+            Some(expected)
+        );
+    }
+
     #[test]
     fn test_init_and_basic_operations() {
         let entry_size = get_entry_serialized_size().unwrap();
@@ -665,9 +1064,9 @@ mod tests {
     }
 
     #[test]
-    fn test_multiple_entries_and_stats() {
+    fn test_entry_reads_stay_within_slot() {
         let entry_size = get_entry_serialized_size().unwrap();
-        let capacity = 10u64;
+        let capacity = 4u64;
         let required_size = HEADER_SIZE + (capacity as usize * entry_size);
 
         let (mut lamports, mut data) = create_test_account_data(required_size);
@@ -678,27 +1077,378 @@ mod tests {
 
         let user = Pubkey::new_unique();
         let order_details = create_representative_order_details();
+        for slot in [100u64, 200] {
+            log.append_entry(
+                user,
+                slot,
+                OrderUpdateType::OrderEntry,
+                order_details.clone(),
+                None,
+                slot,
+            )
+            .unwrap();
+        }
 
-        // Add various types of entries
+        // Scribble over entry 1; entry 0 must still decode from its own bytes only
+        let entry_1 = HEADER_SIZE + entry_size..HEADER_SIZE + 2 * entry_size;
+        log.data[entry_1.clone()].fill(0xff);
+        let entry_0 = log.read_entry(0).unwrap();
+        assert_eq!(entry_0.order_id, 100);
+        assert_eq!(entry_0.slot, 100);
+        assert_eq!(entry_0.order_details, order_details);
+        assert_eq!(
+            log.get_entry_data(0).unwrap().len(),
+            entry_size + ORDER_DETAILS_PADDING
+        );
+    }
+
+    #[test]
+    fn test_append_gtt_and_trigger_orders() {
+        let entry_size = get_entry_serialized_size().unwrap();
+        let capacity = 4u64;
+        let required_size = HEADER_SIZE + (capacity as usize * entry_size);
+
+        let (mut lamports, mut data) = create_test_account_data(required_size);
+        let account = make_account_info(&mut lamports, &mut data);
+
+        ZeroCopyMarketOrderLog::init_in_account(&account, 42, 0, capacity).unwrap();
+        let mut log = ZeroCopyMarketOrderLog::load(&account).unwrap();
+
+        let user = Pubkey::new_unique();
+        let gtt = OrderDetails::new(
+            1,
+            OrderSide::Bid,
+            1_000_000,
+            OrderPrice::Limit(100_000),
+            TimeInForce::GTT(1_700_000_000),
+        );
+        let mut trigger = OrderDetails::new(
+            2,
+            OrderSide::Ask,
+            1_000_000,
+            OrderPrice::PeggedOffset(-5, PegPriceReference::OraclePrice()),
+            TimeInForce::GTC,
+        );
+        trigger.entry_cond = TriggerCondition::PriceAbove(110_000, PriceReference::MarkPrice());
+        trigger.cancel_cond = TriggerCondition::OrderPartialFill(1, 5_000);
+        trigger.cancel_cond_2 = TriggerCondition::VariantPlaceholder([0xff; 16]);
+
+        for order in [&gtt, &trigger] {
+            log.append_entry(
+                user,
+                order.order_id,
+                OrderUpdateType::OrderEntry,
+                order.clone(),
+                None,
+                order.order_id,
+            )
+            .unwrap();
+        }
         log.append_entry(
             user,
-            1,
-            OrderUpdateType::OrderEntry,
-            order_details.clone(),
+            3,
+            OrderUpdateType::Reserved(7),
+            OrderDetails::default(),
             None,
-            100000,
+            3,
         )
         .unwrap();
+
+        assert_eq!(log.read_entry(0).unwrap().order_details, gtt);
+        assert_eq!(log.read_entry(1).unwrap().order_details, trigger);
+        assert_eq!(
+            log.read_entry(2).unwrap().update_type,
+            OrderUpdateType::Reserved(7)
+        );
+    }
+
+    #[test]
+    fn test_legacy_slot_size_rejects_oversized_entry() {
+        let entry_size = get_legacy_entry_serialized_size().unwrap();
+        let capacity = 4u64;
+        let required_size =
+            HEADER_SIZE + (capacity as usize * get_entry_serialized_size().unwrap());
+
+        let (mut lamports, mut data) = create_test_account_data(required_size);
+        {
+            let account = make_account_info(&mut lamports, &mut data);
+            ZeroCopyMarketOrderLog::init_in_account(&account, 42, 0, capacity).unwrap();
+        }
+        // Rewrite the header as a log created with the old, smaller slots
+        data[40..48].copy_from_slice(&(entry_size as u64).to_le_bytes());
+        let account = make_account_info(&mut lamports, &mut data);
+        let mut log = ZeroCopyMarketOrderLog::load(&account).unwrap();
+        assert_eq!(log.get_basic_info().unwrap().entry_size, entry_size);
+
+        let user = Pubkey::new_unique();
+        let order_details = create_representative_order_details();
         log.append_entry(
             user,
-            1,
-            OrderUpdateType::Fill,
+            100,
+            OrderUpdateType::OrderEntry,
             order_details.clone(),
             None,
-            100001,
+            100,
         )
         .unwrap();
-        log.append_entry(
+        assert_eq!(log.read_entry(0).unwrap().order_details, order_details);
+
+        // Entries larger than the slot are rejected rather than spilling into the next one
+        let entry_1 = HEADER_SIZE + entry_size..HEADER_SIZE + 2 * entry_size;
+        let mut oversized = order_details.clone();
+        oversized.entry_cond = TriggerCondition::OrderPartialFill(1, 1);
+        let snapshot = log.data[entry_1.end..].to_vec();
+        assert_eq!(
+            log.write_entry(
+                1,
+                &OrderLogEntry {
+                    user,
+                    order_id: 300,
+                    update_type: OrderUpdateType::OrderEntry,
+                    order_details: oversized,
+                    fill_details: EMPTY_FILL_DETAILS,
+                    slot: 300,
+                },
+            ),
+            Err(ZeroCopyOrderLogError::InvalidEntrySize)
+        );
+        assert_eq!(log.data[entry_1.end..], snapshot[..]);
+    }
+
+    #[test]
+    fn test_iter_recent_newest_first() {
+        let entry_size = get_entry_serialized_size().unwrap();
+        let capacity = 8u64;
+        let required_size = HEADER_SIZE + (capacity as usize * entry_size);
+
+        let (mut lamports, mut data) = create_test_account_data(required_size);
+        let account = make_account_info(&mut lamports, &mut data);
+
+        ZeroCopyMarketOrderLog::init_in_account(&account, 42, 0, capacity).unwrap();
+        let mut log = ZeroCopyMarketOrderLog::load(&account).unwrap();
+        assert_eq!(log.iter_recent(3).count(), 0);
+
+        let user = Pubkey::new_unique();
+        for order_id in 1..=5u64 {
+            log.append_entry(
+                user,
+                order_id,
+                OrderUpdateType::OrderEntry,
+                create_representative_order_details(),
+                None,
+                order_id,
+            )
+            .unwrap();
+        }
+
+        let recent: Vec<u64> = log.iter_recent(3).map(|e| e.unwrap().order_id).collect();
+        assert_eq!(recent, vec![5, 4, 3]);
+
+        // Asking for more than exists returns everything without panicking
+        let all: Vec<u64> = log.iter_recent(100).map(|e| e.unwrap().order_id).collect();
+        assert_eq!(all, vec![5, 4, 3, 2, 1]);
+        assert_eq!(log.iter_recent(0).count(), 0);
+    }
+
+    #[test]
+    fn test_iter_entries_for_user() {
+        let entry_size = get_entry_serialized_size().unwrap();
+        let capacity = 8u64;
+        let required_size = HEADER_SIZE + (capacity as usize * entry_size);
+
+        let (mut lamports, mut data) = create_test_account_data(required_size);
+        let account = make_account_info(&mut lamports, &mut data);
+
+        ZeroCopyMarketOrderLog::init_in_account(&account, 42, 0, capacity).unwrap();
+        let mut log = ZeroCopyMarketOrderLog::load(&account).unwrap();
+
+        let alice = Pubkey::new_unique();
+        let bob = Pubkey::new_unique();
+        let carol = Pubkey::new_unique();
+        for (order_id, user) in [alice, bob, carol, bob, alice, carol, bob]
+            .iter()
+            .enumerate()
+        {
+            log.append_entry(
+                *user,
+                order_id as u64,
+                OrderUpdateType::OrderEntry,
+                create_representative_order_details(),
+                None,
+                100 + order_id as u64,
+            )
+            .unwrap();
+        }
+
+        let bob_orders: Vec<u64> = log
+            .iter_entries_for_user(&bob)
+            .map(|e| e.unwrap().order_id)
+            .collect();
+        assert_eq!(bob_orders, vec![1, 3, 6]);
+
+        let alice_orders: Vec<u64> = log
+            .iter_entries_for_user(&alice)
+            .map(|e| e.unwrap().order_id)
+            .collect();
+        assert_eq!(alice_orders, vec![0, 4]);
+
+        assert_eq!(log.iter_entries_for_user(&Pubkey::new_unique()).count(), 0);
+    }
+
+    #[test]
+    fn test_find_entries_by_slot_with_gaps() {
+        let entry_size = get_entry_serialized_size().unwrap();
+        let capacity = 8u64;
+        let required_size = HEADER_SIZE + (capacity as usize * entry_size);
+
+        let (mut lamports, mut data) = create_test_account_data(required_size);
+        let account = make_account_info(&mut lamports, &mut data);
+
+        ZeroCopyMarketOrderLog::init_in_account(&account, 42, 0, capacity).unwrap();
+        let mut log = ZeroCopyMarketOrderLog::load(&account).unwrap();
+
+        // Empty log: every slot starts at the end
+        assert_eq!(log.find_first_index_at_or_after_slot(0).unwrap(), 0);
+        assert_eq!(log.iter_entries_from_slot(0).unwrap().count(), 0);
+
+        let user = Pubkey::new_unique();
+        let slots = [10u64, 10, 15, 40, 41, 100];
+        for (i, slot) in slots.iter().enumerate() {
+            log.append_entry(
+                user,
+                i as u64,
+                OrderUpdateType::OrderEntry,
+                create_representative_order_details(),
+                None,
+                *slot,
+            )
+            .unwrap();
+        }
+
+        assert_eq!(log.find_first_index_at_or_after_slot(0).unwrap(), 0);
+        assert_eq!(log.find_first_index_at_or_after_slot(10).unwrap(), 0);
+        assert_eq!(log.find_first_index_at_or_after_slot(11).unwrap(), 2);
+        assert_eq!(log.find_first_index_at_or_after_slot(16).unwrap(), 3);
+        assert_eq!(log.find_first_index_at_or_after_slot(41).unwrap(), 4);
+        assert_eq!(log.find_first_index_at_or_after_slot(100).unwrap(), 5);
+        assert_eq!(log.find_first_index_at_or_after_slot(101).unwrap(), 6);
+
+        let from_gap: Vec<u64> = log
+            .iter_entries_from_slot(20)
+            .unwrap()
+            .map(|e| e.unwrap().slot)
+            .collect();
+        assert_eq!(from_gap, vec![40, 41, 100]);
+        assert_eq!(log.iter_entries_from_slot(500).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_checksum_detects_corrupted_last_slot() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+
+        let entry_size = get_entry_serialized_size().unwrap();
+        let capacity = 4u64;
+        let required_size = HEADER_SIZE + (capacity as usize * entry_size);
+
+        let (mut lamports, mut data) = create_test_account_data(required_size);
+        {
+            let account = make_account_info(&mut lamports, &mut data);
+            ZeroCopyMarketOrderLog::init_in_account(&account, 42, 0, capacity).unwrap();
+            let mut log = ZeroCopyMarketOrderLog::load(&account).unwrap();
+            for order_id in 0..2u64 {
+                log.append_entry(
+                    Pubkey::new_unique(),
+                    order_id,
+                    OrderUpdateType::OrderEntry,
+                    create_representative_order_details(),
+                    None,
+                    100,
+                )
+                .unwrap();
+            }
+            assert_eq!(log.verify_checksum(), Ok(()));
+
+            // In-place updates of the newest slot keep the checksum current
+            let user = Pubkey::new_unique();
+            log.append_user_collateral_update(user, 1, 101).unwrap();
+            assert!(log.update_last_if_collateral(user, 2, 102).unwrap());
+            assert_eq!(log.verify_checksum(), Ok(()));
+        }
+
+        // Flip a byte in the last slot: loading now fails
+        data[HEADER_SIZE + 2 * entry_size + 40] ^= 0xff;
+        let account = make_account_info(&mut lamports, &mut data);
+        assert_eq!(
+            ZeroCopyMarketOrderLog::load(&account).err(),
+            Some(ZeroCopyOrderLogError::ChecksumMismatch.into())
+        );
+    }
+
+    #[test]
+    fn test_loaded_view_holds_account_borrow() {
+        let entry_size = get_entry_serialized_size().unwrap();
+        let capacity = 2u64;
+        let required_size = HEADER_SIZE + (capacity as usize * entry_size);
+
+        let (mut lamports, mut data) = create_test_account_data(required_size);
+        let account = make_account_info(&mut lamports, &mut data);
+        ZeroCopyMarketOrderLog::init_in_account(&account, 42, 0, capacity).unwrap();
+
+        let user = Pubkey::new_unique();
+        {
+            let mut log = ZeroCopyMarketOrderLog::load(&account).unwrap();
+
+            // The view keeps the account data borrowed: no aliasing access while it lives
+            assert!(account.try_borrow_data().is_err());
+            assert!(account.try_borrow_mut_data().is_err());
+            assert!(ZeroCopyMarketOrderLog::load(&account).is_err());
+
+            log.append_user_collateral_update(user, 5, 1).unwrap();
+        }
+
+        // Once the view is dropped the account can be borrowed again and sees the write
+        assert!(account.try_borrow_data().is_ok());
+        let log = ZeroCopyMarketOrderLog::load(&account).unwrap();
+        let entry = log.iter_entries().next().unwrap().unwrap();
+        assert_eq!(entry.user, user);
+        assert_eq!(entry.fill_details.qty, 5);
+    }
+
+    #[test]
+    fn test_multiple_entries_and_stats() {
+        let entry_size = get_entry_serialized_size().unwrap();
+        let capacity = 10u64;
+        let required_size = HEADER_SIZE + (capacity as usize * entry_size);
+
+        let (mut lamports, mut data) = create_test_account_data(required_size);
+        let account = make_account_info(&mut lamports, &mut data);
+
+        ZeroCopyMarketOrderLog::init_in_account(&account, 42, 0, capacity).unwrap();
+        let mut log = ZeroCopyMarketOrderLog::load(&account).unwrap();
+
+        let user = Pubkey::new_unique();
+        let order_details = create_representative_order_details();
+
+        // Add various types of entries
+        log.append_entry(
+            user,
+            1,
+            OrderUpdateType::OrderEntry,
+            order_details.clone(),
+            None,
+            100000,
+        )
+        .unwrap();
+        log.append_entry(
+            user,
+            1,
+            OrderUpdateType::Fill,
+            order_details.clone(),
+            None,
+            100001,
+        )
+        .unwrap();
+        log.append_entry(
             user,
             1,
             OrderUpdateType::Cancel,
@@ -735,6 +1485,59 @@ mod tests {
         assert_eq!(stats.close_positions, 0);
     }
 
+    #[test]
+    fn test_count_of_matches_stats() {
+        let capacity = 10u64;
+        let required_size = ZeroCopyMarketOrderLog::required_account_size(capacity).unwrap();
+
+        let (mut lamports, mut data) = create_test_account_data(required_size);
+        let account = make_account_info(&mut lamports, &mut data);
+
+        ZeroCopyMarketOrderLog::init_in_account(&account, 42, 0, capacity).unwrap();
+        let mut log = ZeroCopyMarketOrderLog::load(&account).unwrap();
+
+        let user = Pubkey::new_unique();
+        let order_details = create_representative_order_details();
+        for (slot, update_type) in [
+            OrderUpdateType::OrderEntry,
+            OrderUpdateType::Fill,
+            OrderUpdateType::Fill,
+            OrderUpdateType::Cancel,
+            OrderUpdateType::Fill,
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            log.append_entry(
+                user,
+                1,
+                update_type,
+                order_details.clone(),
+                None,
+                slot as u64,
+            )
+            .unwrap();
+        }
+        log.append_user_collateral_update(user, 1_000, 7).unwrap();
+
+        let stats = log.get_stats().unwrap();
+        assert_eq!(log.count_of(OrderUpdateType::Fill).unwrap(), stats.fills);
+        assert_eq!(stats.fills, 3);
+        assert_eq!(
+            log.count_of(OrderUpdateType::Cancel).unwrap(),
+            stats.cancels
+        );
+        assert_eq!(
+            log.count_of(OrderUpdateType::UserCollateralUpdate).unwrap(),
+            stats.user_collateral_updates
+        );
+        assert_eq!(log.count_of(OrderUpdateType::Liquidation).unwrap(), 0);
+        assert_eq!(
+            log.count_of(OrderUpdateType::Reserved(0)).unwrap(),
+            stats.other
+        );
+    }
+
     #[test]
     fn test_log_full() {
         let entry_size = get_entry_serialized_size().unwrap();
@@ -924,6 +1727,209 @@ mod tests {
         assert_eq!(entry.order_details, OrderDetails::default()); // Should be default
     }
 
+    #[test]
+    fn test_append_liquidation() {
+        let entry_size = get_entry_serialized_size().unwrap();
+        let capacity = 5u64;
+        let required_size = HEADER_SIZE + (capacity as usize * entry_size);
+
+        let (mut lamports, mut data) = create_test_account_data(required_size);
+        let account = make_account_info(&mut lamports, &mut data);
+
+        ZeroCopyMarketOrderLog::init_in_account(&account, 42, 0, capacity).unwrap();
+        let mut log = ZeroCopyMarketOrderLog::load(&account).unwrap();
+
+        let user = Pubkey::new_unique();
+        let fill = FillLogDetails {
+            price: 95_000_000,
+            qty: 250_000_000,
+            account: CmaFillResult {
+                new_net_position: 0,
+                old_net_position: 250_000_000,
+                realized_pnl_banked: -12_500_000,
+            },
+        };
+        log.append_liquidation(user, 9, fill.clone(), 777).unwrap();
+
+        let stats = log.get_stats().unwrap();
+        assert_eq!(stats.total_entries, 1);
+        assert_eq!(stats.liquidations, 1);
+        assert_eq!(stats.fills, 0);
+
+        let entry = log.read_entry(0).unwrap();
+        assert_eq!(entry.user, user);
+        assert_eq!(entry.order_id, 9);
+        assert_eq!(entry.update_type, OrderUpdateType::Liquidation);
+        assert_eq!(entry.slot, 777);
+        assert_eq!(entry.fill_details.price, fill.price);
+        assert_eq!(entry.fill_details.qty, fill.qty);
+        assert_eq!(entry.fill_details.account, fill.account);
+        assert_eq!(entry.order_details, OrderDetails::default());
+    }
+
+    #[test]
+    fn test_append_close_position() {
+        let entry_size = get_entry_serialized_size().unwrap();
+        let capacity = 5u64;
+        let required_size = HEADER_SIZE + (capacity as usize * entry_size);
+
+        let (mut lamports, mut data) = create_test_account_data(required_size);
+        let account = make_account_info(&mut lamports, &mut data);
+
+        ZeroCopyMarketOrderLog::init_in_account(&account, 42, 0, capacity).unwrap();
+        let mut log = ZeroCopyMarketOrderLog::load(&account).unwrap();
+
+        let user = Pubkey::new_unique();
+        let fill = FillLogDetails {
+            price: 101_000_000,
+            qty: 40_000_000,
+            account: CmaFillResult {
+                new_net_position: 0,
+                old_net_position: -40_000_000,
+                realized_pnl_banked: -400_000,
+            },
+        };
+        log.append_close_position(user, 3, fill.clone(), 888)
+            .unwrap();
+
+        let stats = log.get_stats().unwrap();
+        assert_eq!(stats.total_entries, 1);
+        assert_eq!(stats.close_positions, 1);
+        assert_eq!(stats.liquidations, 0);
+
+        let entry = log.read_entry(0).unwrap();
+        assert_eq!(entry.user, user);
+        assert_eq!(entry.order_id, 3);
+        assert_eq!(entry.update_type, OrderUpdateType::ClosePosition);
+        assert_eq!(entry.slot, 888);
+        assert_eq!(entry.fill_details.price, fill.price);
+        assert_eq!(entry.fill_details.qty, fill.qty);
+        assert_eq!(entry.fill_details.account, fill.account);
+    }
+
+    #[test]
+    fn test_entries_after_advances_within_page() {
+        let entry_size = get_entry_serialized_size().unwrap();
+        let capacity = 5u64;
+        let required_size = HEADER_SIZE + (capacity as usize * entry_size);
+
+        let (mut lamports, mut data) = create_test_account_data(required_size);
+        let account = make_account_info(&mut lamports, &mut data);
+
+        ZeroCopyMarketOrderLog::init_in_account(&account, 42, 3, capacity).unwrap();
+        let mut log = ZeroCopyMarketOrderLog::load(&account).unwrap();
+        let user = Pubkey::new_unique();
+
+        let start = LogCursor { page: 3, index: 0 };
+        let (entries, cursor) = log.entries_after(start).unwrap();
+        assert!(entries.is_empty());
+        assert_eq!(cursor, start);
+
+        log.append_user_collateral_update(user, 1, 10).unwrap();
+        log.append_user_collateral_update(user, 2, 11).unwrap();
+        let (entries, cursor) = log.entries_after(cursor).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].slot, 10);
+        assert_eq!(cursor, LogCursor { page: 3, index: 2 });
+
+        // Only entries appended since the last read are returned
+        log.append_user_collateral_update(user, 3, 12).unwrap();
+        let (entries, cursor) = log.entries_after(cursor).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].slot, 12);
+        assert_eq!(cursor, LogCursor { page: 3, index: 3 });
+
+        // Cursors for another page or past the end are rejected
+        assert_eq!(
+            log.entries_after(LogCursor { page: 2, index: 0 })
+                .unwrap_err(),
+            ProgramError::InvalidArgument
+        );
+        assert_eq!(
+            log.entries_after(LogCursor { page: 3, index: 4 })
+                .unwrap_err(),
+            ProgramError::InvalidArgument
+        );
+    }
+
+    #[test]
+    fn test_entries_after_rolls_over_at_page_end() {
+        let entry_size = get_entry_serialized_size().unwrap();
+        let capacity = 2u64;
+        let required_size = HEADER_SIZE + (capacity as usize * entry_size);
+
+        let (mut lamports, mut data) = create_test_account_data(required_size);
+        let account = make_account_info(&mut lamports, &mut data);
+
+        ZeroCopyMarketOrderLog::init_in_account(&account, 42, 0, capacity).unwrap();
+        let mut log = ZeroCopyMarketOrderLog::load(&account).unwrap();
+        let user = Pubkey::new_unique();
+
+        log.append_user_collateral_update(user, 1, 10).unwrap();
+        let (_, cursor) = log.entries_after(LogCursor::default()).unwrap();
+        assert_eq!(cursor, LogCursor { page: 0, index: 1 });
+
+        log.append_user_collateral_update(user, 2, 11).unwrap();
+        let (entries, cursor) = log.entries_after(cursor).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].slot, 11);
+        assert_eq!(cursor, LogCursor { page: 1, index: 0 });
+
+        // A full page re-read from its start still signals exhaustion
+        let (entries, cursor) = log.entries_after(LogCursor::default()).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(cursor, LogCursor { page: 1, index: 0 });
+    }
+
+    #[test]
+    fn test_update_last_if_collateral() {
+        let entry_size = get_entry_serialized_size().unwrap();
+        let capacity = 5u64;
+        let required_size = HEADER_SIZE + (capacity as usize * entry_size);
+
+        let (mut lamports, mut data) = create_test_account_data(required_size);
+        let account = make_account_info(&mut lamports, &mut data);
+
+        ZeroCopyMarketOrderLog::init_in_account(&account, 42, 0, capacity).unwrap();
+        let mut log = ZeroCopyMarketOrderLog::load(&account).unwrap();
+
+        let user = Pubkey::new_unique();
+        let other_user = Pubkey::new_unique();
+
+        // Empty log: append
+        assert!(!log.update_last_if_collateral(user, 1_000, 10).unwrap());
+
+        // Same user back to back: coalesce in place
+        assert!(log.update_last_if_collateral(user, 2_000, 11).unwrap());
+        assert_eq!(log.get_basic_info().unwrap().entry_count, 1);
+        let last = log.read_entry(0).unwrap();
+        assert_eq!(last.fill_details.qty, 2_000);
+        assert_eq!(last.slot, 11);
+
+        // Different user: append
+        assert!(!log
+            .update_last_if_collateral(other_user, 3_000, 12)
+            .unwrap());
+
+        // Last entry is an order entry: append
+        log.append_entry(
+            user,
+            7,
+            OrderUpdateType::OrderEntry,
+            create_representative_order_details(),
+            None,
+            13,
+        )
+        .unwrap();
+        assert!(!log.update_last_if_collateral(user, 4_000, 14).unwrap());
+
+        let entries: Vec<_> = log.iter_entries().map(|e| e.unwrap()).collect();
+        assert_eq!(entries.len(), 4);
+        assert_eq!(entries[1].user, other_user);
+        assert_eq!(entries[2].update_type, OrderUpdateType::OrderEntry);
+        assert_eq!(entries[3].fill_details.qty, 4_000);
+    }
+
     #[test]
     fn test_get_stats_with_collateral_updates() {
         let entry_size = get_entry_serialized_size().unwrap();